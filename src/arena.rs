@@ -0,0 +1,75 @@
+//! The playfield. Levels are laid out in arena coordinates, from (0, 0) at the top left to
+//! ([`WIDTH`], [`HEIGHT`]) at the bottom right, no matter how big the window is.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use macroquad::{
+    camera::Camera2D,
+    input::{self, is_key_pressed, KeyCode},
+    math::{DVec2, Vec2},
+    miniquad::window::screen_size,
+    window::{set_fullscreen, Conf},
+};
+
+use crate::shared::View;
+
+pub const WIDTH: f64 = 800.0;
+pub const HEIGHT: f64 = 600.0;
+pub const SIZE: DVec2 = DVec2::new(WIDTH, HEIGHT);
+pub const CENTER: DVec2 = DVec2::new(WIDTH * 0.5, HEIGHT * 0.5);
+
+pub fn window_conf() -> Conf {
+    Conf {
+        window_title: "Exclusively Polygons Alongside Rhythms".to_owned(),
+        window_width: WIDTH as i32,
+        window_height: HEIGHT as i32,
+        window_resizable: true,
+        ..Default::default()
+    }
+}
+
+/// The part of the window the arena is drawn to, as `(x, y, width, height)` in pixels.
+/// The arena is made as big as possible, with black bars filling the rest.
+pub fn viewport() -> (i32, i32, i32, i32) {
+    let screen: Vec2 = screen_size().into();
+    let scale = (screen.x / WIDTH as f32).min(screen.y / HEIGHT as f32);
+    let size = SIZE.as_vec2() * scale;
+    let corner = (screen - size) * 0.5;
+    (
+        corner.x as i32,
+        corner.y as i32,
+        size.x as i32,
+        size.y as i32,
+    )
+}
+
+/// A camera looking at the whole arena through `view`, moved by another `offset` arena units.
+/// Anything outside of the arena gets cut off.
+pub fn camera(offset: Vec2, view: View) -> Camera2D {
+    Camera2D {
+        zoom: 2.0 / SIZE.as_vec2() * view.zoom as f32,
+        rotation: view.rotation.to_degrees() as f32,
+        target: (CENTER + view.pan).as_vec2() + offset,
+        viewport: Some(viewport()),
+        ..Default::default()
+    }
+}
+
+/// Where the mouse is in arena coordinates.
+pub fn mouse_position() -> DVec2 {
+    let (x, y, width, height) = viewport();
+    let mouse: Vec2 = input::mouse_position().into();
+    let corner = Vec2::new(x as f32, y as f32);
+    let size = Vec2::new(width as f32, height as f32);
+    ((mouse - corner) / size).as_dvec2() * SIZE
+}
+
+static FULLSCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Toggles fullscreen when F11 is pressed. Called once a frame from every screen.
+pub fn update_fullscreen() {
+    if is_key_pressed(KeyCode::F11) {
+        let fullscreen = !FULLSCREEN.fetch_xor(true, Ordering::Relaxed);
+        set_fullscreen(fullscreen);
+    }
+}
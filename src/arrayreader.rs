@@ -17,10 +17,10 @@ impl<'a> Read for ArrayReader<'a> {
         let max_read = buf
             .len()
             .min(self.array.len().saturating_sub(self.current_index));
-        if max_read > 0 {}
         let dst = &mut buf[..max_read];
         let src = &self.array[self.current_index..self.current_index + max_read];
         dst.copy_from_slice(src);
+        self.current_index += max_read;
         Ok(max_read)
     }
 }
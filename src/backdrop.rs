@@ -0,0 +1,423 @@
+//! Patterns drawn behind every obstacle, in place of a plain background color.
+//!
+//! Backdrops never collide with anything. Their providers get the level's beat, like the palette's.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
+
+use jut::extensions::Boxed;
+use macroquad::{
+    color::{Color, BLACK},
+    math::{DVec2, Mat4, Quat},
+    models::{draw_mesh, Mesh, Vertex},
+    shapes::draw_rectangle,
+};
+
+use crate::{
+    arena, draw,
+    levelfile::BehaviourDefinition,
+    obstacle::builder,
+    palette::{Slot, Swatch},
+    provider::{Constant, Provider},
+};
+
+/// How far from the center of the arena backdrops reach, so zooming out or turning the camera
+/// never shows past their edges.
+const REACH: f64 = arena::WIDTH + arena::HEIGHT;
+/// Stripes, squares and grid lines closer together than this are just noise, and slow to draw.
+const MIN_SPACING: f64 = 20.0;
+/// The second color of the patterns, a dim version of the default primary color.
+const DIM: Color = Color::new(0.2, 0.0, 0.1, 1.0);
+
+pub type DynBackdrop = Box<dyn Backdrop>;
+pub trait Backdrop {
+    fn box_clone(&self) -> DynBackdrop;
+    fn draw(&self, swatch: &Swatch, beat: f64);
+    /// Converts this backdrop into its level file representation, if it has one.
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        None
+    }
+}
+impl Clone for DynBackdrop {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Draws `draw` with (0, 0) at the center of the arena, turned `rotation` radians clockwise.
+fn around_center(rotation: f64, draw: impl FnOnce()) {
+    let transform = Mat4::from_rotation_translation(
+        Quat::from_rotation_z(rotation as f32),
+        arena::CENTER.as_vec2().extend(0.0),
+    );
+    draw::with_transform(transform, draw);
+}
+
+/// Fills everything the backdrop reaches, from inside [`around_center`].
+fn fill(color: Color) {
+    let reach = REACH as f32;
+    draw_rectangle(-reach, -reach, reach * 2.0, reach * 2.0, color);
+}
+
+/// Every `spacing` apart starting from `scroll`, as far as the backdrop reaches either way.
+/// Also gives how many steps from `scroll` each one is, for patterns that alternate.
+fn repeats(scroll: f64, spacing: f64) -> impl Iterator<Item = (i64, f64)> {
+    let spacing = spacing.max(MIN_SPACING);
+    let first = ((-REACH - scroll) / spacing).floor() as i64;
+    let last = ((REACH - scroll) / spacing).ceil() as i64;
+    (first..=last).map(move |step| (step, scroll + step as f64 * spacing))
+}
+
+fn vertex(x: f64, y: f64, color: Color) -> Vertex {
+    Vertex::new(x as f32, y as f32, 0.0, 0.0, 0.0, color)
+}
+
+/// Draws `(x, y, width, height)` rectangles as one mesh, instead of a call per rectangle.
+/// macroquad cuts off meshes past 5000 indices, so patterns draw a row of these at a time.
+fn draw_rectangles(rectangles: impl IntoIterator<Item = (f64, f64, f64, f64)>, color: Color) {
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+        texture: None,
+    };
+    for (x, y, width, height) in rectangles {
+        let first = mesh.vertices.len() as u16;
+        mesh.vertices.extend([
+            vertex(x, y, color),
+            vertex(x + width, y, color),
+            vertex(x + width, y + height, color),
+            vertex(x, y + height, color),
+        ]);
+        mesh.indices
+            .extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+    }
+    draw_mesh(&mesh);
+}
+
+/// Just the palette's background color. Levels start out with this.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fill;
+impl Backdrop for Fill {
+    fn box_clone(&self) -> DynBackdrop {
+        (*self).boxed()
+    }
+    fn draw(&self, swatch: &Swatch, _beat: f64) {
+        around_center(0.0, || fill(swatch[Slot::Background]));
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(BehaviourDefinition::new("Fill"))
+    }
+}
+
+/// Fades from one color to another across the arena, going the way `angle` points.
+#[derive(Clone)]
+pub struct LinearGradient {
+    pub from: Box<dyn Provider<Color>>,
+    pub to: Box<dyn Provider<Color>>,
+    /// In radians, clockwise from going left to right.
+    pub angle: Box<dyn Provider<f64>>,
+}
+impl Default for LinearGradient {
+    fn default() -> Self {
+        Self {
+            from: Constant(BLACK).boxed(),
+            to: Constant(DIM).boxed(),
+            angle: Constant(FRAC_PI_2).boxed(),
+        }
+    }
+}
+impl LinearGradient {
+    builder!(from: Box<dyn Provider<Color>>);
+    builder!(to: Box<dyn Provider<Color>>);
+    builder!(angle: Box<dyn Provider<f64>>);
+}
+impl Backdrop for LinearGradient {
+    fn box_clone(&self) -> DynBackdrop {
+        self.clone().boxed()
+    }
+    fn draw(&self, _swatch: &Swatch, beat: f64) {
+        let (from, to, angle) = (self.from.get(beat), self.to.get(beat), self.angle.get(beat));
+        // how far the arena's corners go along the gradient, so it always fills the arena
+        let extent =
+            (arena::WIDTH * 0.5 * angle.cos()).abs() + (arena::HEIGHT * 0.5 * angle.sin()).abs();
+        around_center(angle, || {
+            fill(from);
+            let (x, reach) = (extent as f32, REACH as f32);
+            draw_rectangle(x, -reach, reach - x, reach * 2.0, to);
+            draw_mesh(&Mesh {
+                vertices: vec![
+                    vertex(-extent, -REACH, from),
+                    vertex(extent, -REACH, to),
+                    vertex(extent, REACH, to),
+                    vertex(-extent, REACH, from),
+                ],
+                indices: vec![0, 1, 2, 0, 2, 3],
+                texture: None,
+            });
+        });
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("LinearGradient")
+                .field("from", self.from.serialize()?)
+                .field("to", self.to.serialize()?)
+                .field("angle", self.angle.serialize()?),
+        )
+    }
+}
+
+/// Fades from `inner` at `center` to `outer` at `radius` away from it, and stays `outer` past that.
+#[derive(Clone)]
+pub struct RadialGradient {
+    pub inner: Box<dyn Provider<Color>>,
+    pub outer: Box<dyn Provider<Color>>,
+    pub center: Box<dyn Provider<DVec2>>,
+    pub radius: Box<dyn Provider<f64>>,
+}
+impl Default for RadialGradient {
+    fn default() -> Self {
+        Self {
+            inner: Constant(DIM).boxed(),
+            outer: Constant(BLACK).boxed(),
+            center: Constant(arena::CENTER).boxed(),
+            radius: Constant(400.0).boxed(),
+        }
+    }
+}
+impl RadialGradient {
+    builder!(inner: Box<dyn Provider<Color>>);
+    builder!(outer: Box<dyn Provider<Color>>);
+    builder!(center: Box<dyn Provider<DVec2>>);
+    builder!(radius: Box<dyn Provider<f64>>);
+}
+impl Backdrop for RadialGradient {
+    fn box_clone(&self) -> DynBackdrop {
+        self.clone().boxed()
+    }
+    fn draw(&self, _swatch: &Swatch, beat: f64) {
+        const SEGMENTS: u16 = 64;
+        let (inner, outer) = (self.inner.get(beat), self.outer.get(beat));
+        let (center, radius) = (self.center.get(beat) - arena::CENTER, self.radius.get(beat));
+        around_center(0.0, || {
+            fill(outer);
+            let mut vertices = vec![vertex(center.x, center.y, inner)];
+            vertices.extend((0..SEGMENTS).map(|i| {
+                let edge = center + DVec2::from_angle(i as f64 / SEGMENTS as f64 * TAU) * radius;
+                vertex(edge.x, edge.y, outer)
+            }));
+            let indices = (0..SEGMENTS)
+                .flat_map(|i| [0, i + 1, (i + 1) % SEGMENTS + 1])
+                .collect();
+            draw_mesh(&Mesh {
+                vertices,
+                indices,
+                texture: None,
+            });
+        });
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("RadialGradient")
+                .field("inner", self.inner.serialize()?)
+                .field("outer", self.outer.serialize()?)
+                .field("center", self.center.serialize()?)
+                .field("radius", self.radius.serialize()?),
+        )
+    }
+}
+
+/// Alternating stripes of `a` and `b`, each `width` wide.
+#[derive(Clone)]
+pub struct Stripes {
+    pub a: Box<dyn Provider<Color>>,
+    pub b: Box<dyn Provider<Color>>,
+    pub width: Box<dyn Provider<f64>>,
+    /// In radians, clockwise from the stripes going straight down.
+    pub rotation: Box<dyn Provider<f64>>,
+    /// How far the stripes have moved across, so `linear` makes them scroll.
+    pub scroll: Box<dyn Provider<f64>>,
+}
+impl Default for Stripes {
+    fn default() -> Self {
+        Self {
+            a: Constant(BLACK).boxed(),
+            b: Constant(DIM).boxed(),
+            width: Constant(40.0).boxed(),
+            rotation: Constant(FRAC_PI_4).boxed(),
+            scroll: Constant(0.0).boxed(),
+        }
+    }
+}
+impl Stripes {
+    builder!(a: Box<dyn Provider<Color>>);
+    builder!(b: Box<dyn Provider<Color>>);
+    builder!(width: Box<dyn Provider<f64>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(scroll: Box<dyn Provider<f64>>);
+}
+impl Backdrop for Stripes {
+    fn box_clone(&self) -> DynBackdrop {
+        self.clone().boxed()
+    }
+    fn draw(&self, _swatch: &Swatch, beat: f64) {
+        let (a, b) = (self.a.get(beat), self.b.get(beat));
+        let width = self.width.get(beat).max(MIN_SPACING);
+        around_center(self.rotation.get(beat), || {
+            fill(a);
+            let stripes = repeats(self.scroll.get(beat), width * 2.0)
+                .map(|(_, x)| (x, -REACH, width, REACH * 2.0));
+            draw_rectangles(stripes, b);
+        });
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Stripes")
+                .field("a", self.a.serialize()?)
+                .field("b", self.b.serialize()?)
+                .field("width", self.width.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scroll", self.scroll.serialize()?),
+        )
+    }
+}
+
+/// A checkerboard of `a` and `b` squares, `size` wide.
+#[derive(Clone)]
+pub struct Checkerboard {
+    pub a: Box<dyn Provider<Color>>,
+    pub b: Box<dyn Provider<Color>>,
+    pub size: Box<dyn Provider<f64>>,
+    /// In radians, clockwise.
+    pub rotation: Box<dyn Provider<f64>>,
+    /// How far the squares have moved, so `velocity` makes them scroll.
+    pub scroll: Box<dyn Provider<DVec2>>,
+}
+impl Default for Checkerboard {
+    fn default() -> Self {
+        Self {
+            a: Constant(BLACK).boxed(),
+            b: Constant(DIM).boxed(),
+            size: Constant(50.0).boxed(),
+            rotation: Constant(0.0).boxed(),
+            scroll: Constant(DVec2::ZERO).boxed(),
+        }
+    }
+}
+impl Checkerboard {
+    builder!(a: Box<dyn Provider<Color>>);
+    builder!(b: Box<dyn Provider<Color>>);
+    builder!(size: Box<dyn Provider<f64>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(scroll: Box<dyn Provider<DVec2>>);
+}
+impl Backdrop for Checkerboard {
+    fn box_clone(&self) -> DynBackdrop {
+        self.clone().boxed()
+    }
+    fn draw(&self, _swatch: &Swatch, beat: f64) {
+        let (a, b) = (self.a.get(beat), self.b.get(beat));
+        let (size, scroll) = (self.size.get(beat).max(MIN_SPACING), self.scroll.get(beat));
+        around_center(self.rotation.get(beat), || {
+            fill(a);
+            for (row, y) in repeats(scroll.y, size) {
+                let squares = repeats(scroll.x, size)
+                    .filter(|(column, _)| (column + row).rem_euclid(2) == 1)
+                    .map(|(_, x)| (x, y, size, size));
+                draw_rectangles(squares, b);
+            }
+        });
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Checkerboard")
+                .field("a", self.a.serialize()?)
+                .field("b", self.b.serialize()?)
+                .field("size", self.size.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scroll", self.scroll.serialize()?),
+        )
+    }
+}
+
+/// Lines `spacing` apart both ways, over a `background` color.
+#[derive(Clone)]
+pub struct Grid {
+    pub background: Box<dyn Provider<Color>>,
+    pub line: Box<dyn Provider<Color>>,
+    pub spacing: Box<dyn Provider<f64>>,
+    pub thickness: Box<dyn Provider<f64>>,
+    /// In radians, clockwise.
+    pub rotation: Box<dyn Provider<f64>>,
+    /// How far the lines have moved, so `velocity` makes them scroll.
+    pub scroll: Box<dyn Provider<DVec2>>,
+}
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            background: Constant(BLACK).boxed(),
+            line: Constant(DIM).boxed(),
+            spacing: Constant(50.0).boxed(),
+            thickness: Constant(2.0).boxed(),
+            rotation: Constant(0.0).boxed(),
+            scroll: Constant(DVec2::ZERO).boxed(),
+        }
+    }
+}
+impl Grid {
+    builder!(background: Box<dyn Provider<Color>>);
+    builder!(line: Box<dyn Provider<Color>>);
+    builder!(spacing: Box<dyn Provider<f64>>);
+    builder!(thickness: Box<dyn Provider<f64>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(scroll: Box<dyn Provider<DVec2>>);
+}
+impl Backdrop for Grid {
+    fn box_clone(&self) -> DynBackdrop {
+        self.clone().boxed()
+    }
+    fn draw(&self, _swatch: &Swatch, beat: f64) {
+        let (background, line) = (self.background.get(beat), self.line.get(beat));
+        let (spacing, thickness) = (self.spacing.get(beat), self.thickness.get(beat));
+        let scroll = self.scroll.get(beat);
+        around_center(self.rotation.get(beat), || {
+            fill(background);
+            let columns = repeats(scroll.x, spacing)
+                .map(|(_, x)| (x - thickness * 0.5, -REACH, thickness, REACH * 2.0));
+            draw_rectangles(columns, line);
+            let rows = repeats(scroll.y, spacing)
+                .map(|(_, y)| (-REACH, y - thickness * 0.5, REACH * 2.0, thickness));
+            draw_rectangles(rows, line);
+        });
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Grid")
+                .field("background", self.background.serialize()?)
+                .field("line", self.line.serialize()?)
+                .field("spacing", self.spacing.serialize()?)
+                .field("thickness", self.thickness.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scroll", self.scroll.serialize()?),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_reach_past_both_sides() {
+        let steps: Vec<_> = repeats(30.0, 100.0).collect();
+        assert!(steps.first().unwrap().1 <= -REACH);
+        assert!(steps.last().unwrap().1 >= REACH);
+        assert!(
+            steps.contains(&(0, 30.0)),
+            "lined up with the scroll, however far it goes"
+        );
+        assert!(steps.contains(&(-1, -70.0)));
+        assert!(
+            repeats(0.0, 0.0).count() * 6 < 5000,
+            "tiny spacings are clamped, so a row of them fits in one mesh"
+        );
+    }
+}
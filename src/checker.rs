@@ -0,0 +1,360 @@
+//! Checks that levels can be survived, without playing them or opening a window.
+//!
+//! The level is stepped a fraction of a beat at a time, and every step a grid of player positions
+//! is tested against it. Steps where no position is safe get reported, along with steps where the
+//! safe positions are too far away to move to in time, first at the player's regular speed and
+//! then while dashing. Movement is only tracked to the nearest grid cell and dashes are assumed to
+//! always be ready, so this can miss things, but anything it reports is worth a look. Obstacles
+//! that aim at the player aim at where it spawns.
+
+use std::{error::Error, fmt, ops::Range, path::PathBuf};
+
+use macroquad::math::DVec2;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    arena,
+    level::Level,
+    levels::{self, FileLevel, LevelDefinition},
+    player::Player,
+};
+
+pub const CELL_SIZE: f64 = 20.0;
+const COLUMNS: usize = (arena::WIDTH / CELL_SIZE) as usize;
+const ROWS: usize = (arena::HEIGHT / CELL_SIZE) as usize;
+pub const STEPS_PER_BEAT: f64 = 8.0;
+/// Where to stop if a level never runs out of obstacles.
+const MAX_BEATS: f64 = 2000.0;
+
+/// Which grid cells are safe, or can be reached.
+#[derive(Clone, Debug, PartialEq)]
+struct Grid {
+    cells: Vec<bool>,
+}
+impl Grid {
+    fn filled(value: bool) -> Self {
+        Self {
+            cells: vec![value; COLUMNS * ROWS],
+        }
+    }
+    /// The middle of a cell, moved inside the area the player is kept in.
+    fn position(column: usize, row: usize, player: &Player) -> DVec2 {
+        let margin = player.radius + 10.0;
+        (DVec2::new(column as f64 + 0.5, row as f64 + 0.5) * CELL_SIZE)
+            .clamp(DVec2::splat(margin), arena::SIZE - margin)
+    }
+    fn get(&self, column: usize, row: usize) -> bool {
+        self.cells[row * COLUMNS + column]
+    }
+    fn any(&self) -> bool {
+        self.cells.contains(&true)
+    }
+    fn and(&self, other: &Grid) -> Grid {
+        Self {
+            cells: (self.cells.iter())
+                .zip(&other.cells)
+                .map(|(a, b)| *a && *b)
+                .collect(),
+        }
+    }
+    /// Spreads every set cell `distance` cells in every direction, including diagonally,
+    /// since the player moves at full speed on both axes at once.
+    fn spread(&self, distance: usize) -> Grid {
+        if distance == 0 {
+            return self.clone();
+        }
+        let mut horizontal = Grid::filled(false);
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let from = column.saturating_sub(distance);
+                let to = (column + distance).min(COLUMNS - 1);
+                horizontal.cells[row * COLUMNS + column] =
+                    (from..=to).any(|column| self.get(column, row));
+            }
+        }
+        let mut spread = Grid::filled(false);
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let from = row.saturating_sub(distance);
+                let to = (row + distance).min(ROWS - 1);
+                spread.cells[row * COLUMNS + column] =
+                    (from..=to).any(|row| horizontal.get(column, row));
+            }
+        }
+        spread
+    }
+    fn bounds(&self) -> Option<Region> {
+        let mut bounds: Option<Region> = None;
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                if self.get(column, row) {
+                    let cell = Region {
+                        min: DVec2::new(column as f64, row as f64) * CELL_SIZE,
+                        max: DVec2::new(column as f64 + 1.0, row as f64 + 1.0) * CELL_SIZE,
+                    };
+                    bounds = Some(bounds.map_or(cell, |bounds| bounds.union(cell)));
+                }
+            }
+        }
+        bounds
+    }
+}
+
+/// A rectangle of the arena.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub min: DVec2,
+    pub max: DVec2,
+}
+impl Region {
+    fn union(self, other: Region) -> Region {
+        Region {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "x {} to {}, y {} to {}",
+            self.min.x, self.max.x, self.min.y, self.max.y
+        )
+    }
+}
+
+/// How growing a set of reachable cells lags behind the player's actual speed,
+/// since it can only grow a whole cell at a time.
+struct Reach {
+    cells: Grid,
+    /// How far the player could move in one second.
+    speed: f64,
+    /// Distance moved that didn't add up to a whole cell yet.
+    leftover: f64,
+}
+impl Reach {
+    fn new(speed: f64, cells: Grid) -> Self {
+        Self {
+            cells,
+            speed,
+            leftover: 0.0,
+        }
+    }
+    /// Moves for `seconds`, staying inside `safe`. Returns false if nothing could be reached.
+    fn step(&mut self, seconds: f64, safe: &Grid) -> bool {
+        self.leftover += self.speed * seconds;
+        let cells = (self.leftover / CELL_SIZE).floor();
+        self.leftover -= cells * CELL_SIZE;
+        self.cells = self.cells.spread(cells as usize).and(safe);
+        self.cells.any()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Every position gets hit.
+    NoSafePosition,
+    /// There's somewhere safe, but it's too far away to get to, even while dashing.
+    Unreachable,
+    /// There's somewhere safe, but only dashing gets there in time.
+    NeedsDash,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub problem: Problem,
+    pub beats: Range<f64>,
+    /// Everywhere that was safe during those beats.
+    pub safe: Option<Region>,
+}
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "beats {} to {}: ", self.beats.start, self.beats.end)?;
+        match (self.problem, self.safe) {
+            (Problem::NoSafePosition, _) | (_, None) => write!(f, "no safe position"),
+            (Problem::Unreachable, Some(safe)) => write!(
+                f,
+                "safe positions ({safe}) can't be reached in time, even while dashing"
+            ),
+            (Problem::NeedsDash, Some(safe)) => {
+                write!(f, "safe positions ({safe}) can only be reached by dashing")
+            }
+        }
+    }
+}
+
+/// Steps through a level from the start of its song, and reports every problem found.
+pub fn check(mut level: Level) -> Vec<Issue> {
+    let player = Player::new();
+    let step = 1.0 / STEPS_PER_BEAT;
+
+    let mut start = Grid::filled(false);
+    let spawn = (player.position / CELL_SIZE).as_uvec2();
+    start.cells[spawn.y as usize * COLUMNS + spawn.x as usize] = true;
+    let mut walking = Reach::new(player.regular_speed, start.clone());
+    let mut dashing = Reach::new(player.dash_speed, start);
+
+    let mut issues: Vec<Issue> = vec![];
+    // starting on a step keeps the reported beats tidy
+    let mut beat = (level.tempo.seconds_to_beats(0.0) / step).floor() * step;
+    while beat < MAX_BEATS && !level.obstacles().is_empty() {
+        level.update(beat, &player);
+        let mut safe = Grid::filled(false);
+        let mut tester = player;
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                tester.position = Grid::position(column, row, &player);
+                safe.cells[row * COLUMNS + column] = !level.collide(&tester, beat);
+            }
+        }
+
+        // the player gets less time per step while the song is fast
+        let seconds =
+            level.tempo.beats_to_seconds(beat + step) - level.tempo.beats_to_seconds(beat);
+        let walkable = walking.step(seconds, &safe);
+        let dashable = dashing.step(seconds, &safe);
+        // after reporting something, checking starts over from anywhere safe,
+        // so one problem doesn't get reported for the rest of the level
+        let problem = if !safe.any() {
+            walking.cells = Grid::filled(true);
+            dashing.cells = Grid::filled(true);
+            Some(Problem::NoSafePosition)
+        } else if !dashable {
+            walking.cells = safe.clone();
+            dashing.cells = safe.clone();
+            Some(Problem::Unreachable)
+        } else if !walkable {
+            walking.cells = dashing.cells.clone();
+            Some(Problem::NeedsDash)
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            let safe = safe.bounds();
+            match issues.last_mut() {
+                Some(last) if last.problem == problem && last.beats.end == beat => {
+                    last.beats.end = beat + step;
+                    last.safe = match (last.safe, safe) {
+                        (Some(a), Some(b)) => Some(a.union(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                _ => issues.push(Issue {
+                    problem,
+                    beats: beat..beat + step,
+                    safe,
+                }),
+            }
+        }
+        beat += step;
+    }
+    issues
+}
+
+/// Checks a level file, or every level if there isn't one, and prints what was found.
+pub fn check_levels(path: Option<impl Into<PathBuf>>) -> Result<(), Box<dyn Error>> {
+    let definitions: Vec<Box<dyn LevelDefinition>> = match path {
+        Some(path) => vec![Box::new(FileLevel::new(path)?)],
+        None => levels::registry(levels::LEVEL_DIRECTORY),
+    };
+    let mut failed = false;
+    for definition in definitions {
+        let seed = definition.info().seed_policy.seed(thread_rng().gen());
+        let issues = check(definition.build(seed)?);
+        println!(
+            "{} (seed {seed}): {} problem{}",
+            definition.info().name,
+            issues.len(),
+            if issues.len() == 1 { "" } else { "s" }
+        );
+        for issue in &issues {
+            println!("    {issue}");
+        }
+        failed |= issues
+            .iter()
+            .any(|issue| issue.problem != Problem::NeedsDash);
+    }
+    if failed {
+        Err("some levels can't be survived".into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jut::extensions::Boxed;
+
+    use crate::{
+        level::{LevelBuilder, Song},
+        obstacle::{Obstacle, Rectangle},
+        provider::Constant,
+        tempo::TempoMap,
+    };
+
+    use super::*;
+
+    fn single(column: usize, row: usize) -> Grid {
+        let mut grid = Grid::filled(false);
+        grid.cells[row * COLUMNS + column] = true;
+        grid
+    }
+
+    #[test]
+    fn spreading_reaches_diagonals() {
+        let spread = single(5, 5).spread(2);
+        assert!(spread.get(7, 7) && spread.get(3, 3) && spread.get(7, 3));
+        assert!(!spread.get(8, 5) && !spread.get(5, 8));
+        assert_eq!(spread.cells.iter().filter(|&&cell| cell).count(), 25);
+    }
+
+    #[test]
+    fn reach_carries_partial_cells_over() {
+        let safe = Grid::filled(true);
+        // a fifth of a cell every step
+        let mut reach = Reach::new(CELL_SIZE * 2.0, single(5, 5));
+        for _ in 0..4 {
+            reach.step(0.1, &safe);
+        }
+        assert_eq!(reach.cells, single(5, 5));
+        reach.step(0.1, &safe);
+        assert_eq!(reach.cells, single(5, 5).spread(1));
+        for _ in 0..5 {
+            reach.step(0.1, &safe);
+        }
+        assert_eq!(reach.cells, single(5, 5).spread(2));
+    }
+
+    #[test]
+    fn covering_the_arena_has_no_safe_position() {
+        let mut builder = LevelBuilder::new(0);
+        // hurts from just after beat 3.9 to beat 5.9, so the steps from 4 to 5.875
+        builder.obstacle(Obstacle::new(
+            3.9,
+            Rectangle {
+                center: Constant(arena::CENTER).boxed(),
+                size: Constant(arena::SIZE * 2.0).boxed(),
+                rotation: Constant(0.0).boxed(),
+                lifetime: 2.0,
+                warn_time: 1.0,
+                leave_time: 0.01,
+            }
+            .boxed(),
+        ));
+        let level = builder.build(
+            Song::builtin("during_pride_month").unwrap(),
+            TempoMap::new(120.0, 0.0),
+            vec![],
+        );
+        assert_eq!(
+            check(level),
+            [Issue {
+                problem: Problem::NoSafePosition,
+                beats: 4.0..6.0,
+                safe: None,
+            }]
+        );
+    }
+}
@@ -1,6 +1,8 @@
-use macroquad::math::{dvec2, DVec2};
+use std::f64::consts::TAU;
 
-use crate::transform;
+use macroquad::math::{dvec2, DVec2, Mat2, Vec2};
+
+use crate::{polygon::Polygon, transform};
 
 pub const CIRCLE_RECTANGLE_COLLISION_MAP: [fn(DVec2, DVec2, DVec2, f64) -> bool; 9] = [
     |posr: DVec2, _sze: DVec2, posc: DVec2, rad: f64| posr.distance_squared(posc) < rad * rad, // top left
@@ -76,3 +78,150 @@ pub fn circle_line(
     let (center, size, rot) = transform::rectify_line(line_start, line_end, line_thickness);
     circle_rectangle(circle_pos, circle_radius, center, size, rot)
 }
+/// The point on the segment from `start` to `end` that's closest to `point`.
+fn closest_on_segment(point: DVec2, start: DVec2, end: DVec2) -> DVec2 {
+    let along = end - start;
+    let length_squared = along.length_squared();
+    if length_squared == 0.0 {
+        return start;
+    }
+    start + along * ((point - start).dot(along) / length_squared).clamp(0.0, 1.0)
+}
+pub fn circle_triangle(circle_pos: DVec2, circle_radius: f64, [a, b, c]: [DVec2; 3]) -> bool {
+    // flat triangles would count everything in line with them as inside
+    if (b - a).perp_dot(c - a) != 0.0 {
+        let side = |from: DVec2, to: DVec2| (to - from).perp_dot(circle_pos - from);
+        let sides = [side(a, b), side(b, c), side(c, a)];
+        if sides.iter().all(|&side| side >= 0.0) || sides.iter().all(|&side| side <= 0.0) {
+            return true;
+        }
+    }
+    [(a, b), (b, c), (c, a)].into_iter().any(|(start, end)| {
+        closest_on_segment(circle_pos, start, end).distance_squared(circle_pos)
+            <= circle_radius * circle_radius
+    })
+}
+/// Tests against a polygon placed the same way as [`Polygon::draw_mat`] would draw it.
+pub fn circle_polygon(
+    circle_pos: DVec2,
+    circle_radius: f64,
+    polygon: &Polygon,
+    offset: Vec2,
+    transformation: Mat2,
+) -> bool {
+    polygon.triangles().any(|triangle| {
+        circle_triangle(
+            circle_pos,
+            circle_radius,
+            triangle.map(|vertex| (transformation * vertex + offset).as_dvec2()),
+        )
+    })
+}
+pub fn circle_annulus(
+    circle_pos: DVec2,
+    circle_radius: f64,
+    center: DVec2,
+    inner_radius: f64,
+    outer_radius: f64,
+) -> bool {
+    let distance = circle_pos.distance(center);
+    distance + circle_radius >= inner_radius && distance - circle_radius <= outer_radius
+}
+/// Tests against the part of an annulus from `start_angle` to `start_angle + span`,
+/// in radians clockwise from pointing right.
+pub fn circle_annular_sector(
+    circle_pos: DVec2,
+    circle_radius: f64,
+    center: DVec2,
+    inner_radius: f64,
+    outer_radius: f64,
+    start_angle: f64,
+    span: f64,
+) -> bool {
+    let offset = circle_pos - center;
+    let angle = offset.y.atan2(offset.x);
+    if (angle - start_angle).rem_euclid(TAU) <= span {
+        let distance = offset.length();
+        return distance + circle_radius >= inner_radius
+            && distance - circle_radius <= outer_radius;
+    }
+    // from outside of the sector's angles, the closest point is always on one of its straight edges
+    [start_angle, start_angle + span].into_iter().any(|angle| {
+        let direction = DVec2::from_angle(angle);
+        let (start, end) = (
+            center + direction * inner_radius,
+            center + direction * outer_radius,
+        );
+        closest_on_segment(circle_pos, start, end).distance_squared(circle_pos)
+            <= circle_radius * circle_radius
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygon::presets::Preset;
+
+    use super::*;
+
+    #[test]
+    fn circle_triangle_either_winding() {
+        let triangle = [dvec2(0.0, 0.0), dvec2(100.0, 0.0), dvec2(0.0, 100.0)];
+        let reversed = [triangle[2], triangle[1], triangle[0]];
+        for triangle in [triangle, reversed] {
+            assert!(circle_triangle(dvec2(20.0, 20.0), 1.0, triangle));
+            assert!(circle_triangle(dvec2(52.0, 52.0), 5.0, triangle));
+            assert!(!circle_triangle(dvec2(60.0, 60.0), 5.0, triangle));
+            assert!(!circle_triangle(dvec2(-10.0, 50.0), 5.0, triangle));
+        }
+    }
+
+    #[test]
+    fn flat_triangles_only_hit_along_their_edges() {
+        let flat = [dvec2(0.0, 0.0), dvec2(50.0, 0.0), dvec2(100.0, 0.0)];
+        assert!(circle_triangle(dvec2(50.0, 4.0), 5.0, flat));
+        assert!(!circle_triangle(dvec2(200.0, 0.0), 5.0, flat));
+    }
+
+    #[test]
+    fn circle_polygon_misses_between_star_points() {
+        let star = Preset::Star {
+            points: 5,
+            inner_radius: 0.3,
+        }
+        .polygon();
+        let center = Vec2::new(400.0, 300.0);
+        let transformation = Mat2::from_scale_angle(Vec2::splat(100.0), 0.0);
+        let hits = |at: DVec2| circle_polygon(at, 1.0, &star, center, transformation);
+        // the top point, then the gap to the right of it
+        assert!(hits(dvec2(400.0, 205.0)));
+        let gap = DVec2::from_angle(-54f64.to_radians()) * 60.0;
+        assert!(!hits(center.as_dvec2() + gap));
+        assert!(hits(center.as_dvec2()));
+    }
+
+    #[test]
+    fn circle_annulus_only_hits_the_band() {
+        let center = dvec2(400.0, 300.0);
+        let hits = |at: DVec2| circle_annulus(at, 5.0, center, 100.0, 120.0);
+        assert!(!hits(center));
+        assert!(!hits(center + dvec2(90.0, 0.0)));
+        assert!(hits(center + dvec2(0.0, 97.0)));
+        assert!(hits(center + dvec2(-110.0, 0.0)));
+        assert!(!hits(center + dvec2(130.0, 0.0)));
+    }
+
+    #[test]
+    fn circle_annular_sector_hits_inside_and_near_its_edges() {
+        use std::f64::consts::FRAC_PI_2;
+
+        let center = dvec2(400.0, 300.0);
+        // the bottom right quarter, since angles go clockwise
+        let hits = |at: DVec2| circle_annular_sector(at, 5.0, center, 100.0, 120.0, 0.0, FRAC_PI_2);
+        assert!(hits(center + DVec2::from_angle(0.5) * 110.0));
+        assert!(!hits(center + DVec2::from_angle(-0.5) * 110.0));
+        assert!(!hits(center + DVec2::from_angle(3.0) * 110.0));
+        // just above the right edge
+        assert!(hits(center + dvec2(110.0, -4.0)));
+        assert!(!hits(center + dvec2(110.0, -6.0)));
+    }
+}
@@ -1,9 +1,10 @@
 use macroquad::{
     color::Color,
-    math::{vec2, Vec2},
+    math::{vec2, Mat4, Vec2},
     miniquad::window::screen_size,
     shapes::draw_triangle,
     text::{draw_text, measure_text},
+    window::get_internal_gl,
 };
 
 use crate::transform;
@@ -18,6 +19,16 @@ pub fn rotated_rect(center: Vec2, size: Vec2, rot: f32, color: impl Into<Color>)
     draw_triangle(br, tr, bl, clr);
 }
 
+/// Draws `draw` moved by `transform`, on top of whatever transform is already in place.
+pub fn with_transform(transform: Mat4, draw: impl FnOnce()) {
+    // SAFETY: only used to change the transform, which every draw call picks up
+    unsafe { get_internal_gl() }
+        .quad_gl
+        .push_model_matrix(transform);
+    draw();
+    unsafe { get_internal_gl() }.quad_gl.pop_model_matrix();
+}
+
 pub fn draw_screen_centered_text(
     text: &str,
     x_from_center: f32,
@@ -0,0 +1,123 @@
+//! Easing curves, for animating between keyframes.
+//! See <https://easings.net> for what they look like.
+
+use std::f64::consts::TAU;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    Quad,
+    Cubic,
+    Expo,
+    /// Pulls back a little before going.
+    Back,
+    Elastic,
+    Bounce,
+}
+impl Curve {
+    pub const ALL: [Curve; 6] = [
+        Curve::Quad,
+        Curve::Cubic,
+        Curve::Expo,
+        Curve::Back,
+        Curve::Elastic,
+        Curve::Bounce,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Quad => "quad",
+            Curve::Cubic => "cubic",
+            Curve::Expo => "expo",
+            Curve::Back => "back",
+            Curve::Elastic => "elastic",
+            Curve::Bounce => "bounce",
+        }
+    }
+    /// The curve easing in, going from 0 to 1.
+    fn ease_in(self, t: f64) -> f64 {
+        match self {
+            Curve::Quad => t * t,
+            Curve::Cubic => t * t * t,
+            Curve::Expo if t <= 0.0 => 0.0,
+            Curve::Expo => 2f64.powf(10.0 * t - 10.0),
+            Curve::Back => {
+                const OVERSHOOT: f64 = 1.70158;
+                (OVERSHOOT + 1.0) * t * t * t - OVERSHOOT * t * t
+            }
+            Curve::Elastic if t <= 0.0 || t >= 1.0 => t,
+            Curve::Elastic => {
+                -(2f64.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * TAU / 3.0).sin()
+            }
+            Curve::Bounce => 1.0 - bounce_out(1.0 - t),
+        }
+    }
+}
+
+/// Bouncing to a stop, in four bounces of halving height.
+fn bounce_out(t: f64) -> f64 {
+    const N: f64 = 7.5625;
+    const D: f64 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Holds still, then jumps at the end.
+    Step,
+    In(Curve),
+    Out(Curve),
+    InOut(Curve),
+}
+impl Easing {
+    /// How far along the eased value is, `t` going from 0 to 1.
+    /// Some curves overshoot, so this can go past 0 and 1 in between.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::Step if t < 1.0 => 0.0,
+            Easing::Step => 1.0,
+            Easing::In(curve) => curve.ease_in(t),
+            Easing::Out(curve) => 1.0 - curve.ease_in(1.0 - t),
+            Easing::InOut(curve) if t < 0.5 => curve.ease_in(t * 2.0) * 0.5,
+            Easing::InOut(curve) => 1.0 - curve.ease_in(2.0 - t * 2.0) * 0.5,
+        }
+    }
+    /// The name used in level files, like `linear` or `quad_in_out`.
+    pub fn name(self) -> String {
+        match self {
+            Easing::Linear => "linear".to_owned(),
+            Easing::Step => "step".to_owned(),
+            Easing::In(curve) => format!("{}_in", curve.name()),
+            Easing::Out(curve) => format!("{}_out", curve.name()),
+            Easing::InOut(curve) => format!("{}_in_out", curve.name()),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => return Some(Easing::Linear),
+            "step" => return Some(Easing::Step),
+            _ => {}
+        }
+        let (curve, kind) = name.split_once('_')?;
+        let curve = Curve::ALL.into_iter().find(|other| other.name() == curve)?;
+        match kind {
+            "in" => Some(Easing::In(curve)),
+            "out" => Some(Easing::Out(curve)),
+            "in_out" => Some(Easing::InOut(curve)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,645 @@
+//! The level editor, so levels can be made without touching any code. Edits level files in the
+//! text format, which can be passed to the game with `cargo run -- --edit levels/new.epar`.
+//! Files that don't exist yet start out empty, using the built-in song.
+//!
+//! - Space: play or pause
+//! - Left/Right or the scroll wheel: move a quarter of a beat, or 4 beats while holding Shift
+//! - Clicking the timeline at the bottom jumps to that beat
+//! - 1 to 0: place an obstacle under the mouse at the current beat
+//! - Clicking a handle selects its obstacle, and dragging it moves that position
+//! - Tab: select the next obstacle near the current beat
+//! - Up/Down: pick a field of the selected obstacle, and `-`/`=` to change it (Shift for more)
+//! - Ctrl+D: copy the selected obstacle to the current beat
+//! - Delete: remove the selected obstacle
+//! - Ctrl+S: save
+//! - Escape: leave, asking again if there are unsaved changes
+
+use std::{
+    error::Error,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use jut::extensions::Boxed;
+use macroquad::{
+    camera::{set_camera, set_default_camera},
+    color::{Color, BLACK, GRAY, GREEN, WHITE, YELLOW},
+    input::{
+        is_key_down, is_key_pressed, is_mouse_button_down, is_mouse_button_pressed,
+        is_mouse_button_released, mouse_position, mouse_wheel, KeyCode, MouseButton,
+    },
+    math::{dvec2, DVec2, Vec2},
+    shapes::{draw_circle_lines, draw_line, draw_rectangle},
+    text::draw_text,
+    window::{clear_background, next_frame, screen_height, screen_width},
+};
+use soloud::{LoadExt, Wav};
+
+use crate::{
+    arena,
+    backdrop::Fill,
+    level::{Level, LevelBuilder, LevelInfo, Song, SongSource},
+    levelfile::{self, BehaviourDefinition, LevelFile, LevelParseError, Value},
+    levels::dpm,
+    music::Music,
+    obstacle::{
+        lasers::{RotatingLaser, SlamLaser, WidenLaser},
+        Bomb, Circle, DynObstacleBehaviour, Rectangle, RectangleGenerator, SetPalette, Shake,
+    },
+    palette::{Palette, Slot},
+    player::Player,
+    provider::{Constant, Linear},
+    shared::View,
+};
+
+/// Makes a new obstacle at the mouse's position.
+type Template = fn(DVec2) -> DynObstacleBehaviour;
+/// Every obstacle that can be placed, in number key order.
+const KINDS: [(&str, Template); 10] = [
+    ("SlamLaser", |at| {
+        SlamLaser::default()
+            .start_pos(dvec2(-50.0, at.y))
+            .end_pos(dvec2(arena::WIDTH + 50.0, at.y))
+            .boxed()
+    }),
+    ("WidenLaser", |at| {
+        WidenLaser::default()
+            .start_pos(dvec2(at.x, -50.0))
+            .end_pos(dvec2(at.x, arena::HEIGHT + 50.0))
+            .boxed()
+    }),
+    ("RotatingLaser", |at| {
+        RotatingLaser::default()
+            .pivot(Constant(at).boxed())
+            .angle(Linear::new(0.0, 0.5).boxed())
+            .boxed()
+    }),
+    ("Bomb", |at| {
+        Bomb {
+            start_position: dvec2(arena::WIDTH, at.y),
+            end_position: at,
+            lifetime: 2.0,
+            radius_per_beat: 10.0,
+            projectile_count: 8,
+            projectile_radius: 5.0,
+            projectile_speed: 100.0,
+            aimed: false,
+        }
+        .boxed()
+    }),
+    ("Circle", |at| {
+        Circle::pellet(20.0, at, dvec2(-100.0, 0.0)).boxed()
+    }),
+    ("Rectangle", |at| {
+        Rectangle {
+            center: Constant(at).boxed(),
+            size: Constant(dvec2(100.0, 100.0)).boxed(),
+            rotation: Constant(0.0).boxed(),
+            lifetime: 4.0,
+            warn_time: 4.0,
+            leave_time: 0.25,
+        }
+        .boxed()
+    }),
+    ("RectangleGenerator", |at| {
+        RectangleGenerator {
+            interval: 0.25,
+            lifetime: 4.0,
+            spawned: 0,
+            spawned_center: Constant(at).boxed(),
+            spawned_size: Constant(dvec2(50.0, 50.0)).boxed(),
+            spawned_rotation: Constant(0.0).boxed(),
+            spawned_lifetime: 1.0,
+            spawned_warn_time: 4.0,
+        }
+        .boxed()
+    }),
+    ("Shake", |_| Shake(20.0).boxed()),
+    ("SetPalette primary", |_| {
+        SetPalette::default()
+            .color(
+                Slot::Primary,
+                Constant(Color::new(1.0, 0.0, 0.5, 1.0)).boxed(),
+            )
+            .boxed()
+    }),
+    ("SetPalette background", |_| {
+        SetPalette::default()
+            .color(Slot::Background, Constant(BLACK).boxed())
+            .boxed()
+    }),
+];
+/// Fields that hold a position, which get a handle that can be dragged around.
+const HANDLE_FIELDS: [&str; 8] = [
+    "pivot",
+    "start_pos",
+    "end_pos",
+    "start_position",
+    "end_position",
+    "position",
+    "center",
+    "spawned_center",
+];
+/// How close to the current beat an obstacle has to be to get handles or be selected.
+const NEARBY_BEATS: f64 = 8.0;
+const HANDLE_RADIUS: f64 = 8.0;
+const SNAP: f64 = 0.25;
+const TIMELINE_HEIGHT: f32 = 30.0;
+
+/// The position a field holds, if it can be dragged around.
+/// Providers like `velocity(start, velocity)` are dragged by their first argument.
+fn handle(value: &Value) -> Option<DVec2> {
+    match value {
+        Value::Vector(position) => Some(*position),
+        Value::Call(_, args) => args.first().and_then(handle),
+        _ => None,
+    }
+}
+fn handle_mut(value: &mut Value) -> Option<&mut DVec2> {
+    match value {
+        Value::Vector(position) => Some(position),
+        Value::Call(_, args) => args.first_mut().and_then(handle_mut),
+        _ => None,
+    }
+}
+/// How much a number field changes with `-` and `=`.
+fn step(field: &str) -> f64 {
+    if field.ends_with("time") || field == "interval" {
+        0.25
+    } else {
+        1.0
+    }
+}
+fn snap(beat: f64) -> f64 {
+    (beat / SNAP).round() * SNAP
+}
+/// How far to move the playhead, with the wheel scrolling down and right both going later.
+fn scrub(wheel: f32, right: bool, left: bool, step: f64) -> f64 {
+    let mut scrub = 0.0;
+    // the wheel reads 0 when it hasn't moved, which `signum` would still count as up
+    if wheel != 0.0 {
+        scrub -= wheel.signum() as f64 * step;
+    }
+    if right {
+        scrub += step;
+    }
+    if left {
+        scrub -= step;
+    }
+    scrub
+}
+
+#[derive(Clone, PartialEq)]
+struct EditorObstacle {
+    beat: f64,
+    definition: BehaviourDefinition,
+}
+
+struct Editor {
+    path: PathBuf,
+    /// Everything but the obstacles, which are kept as definitions so they can be edited.
+    file: LevelFile,
+    song: Song,
+    track: Wav,
+    obstacles: Vec<EditorObstacle>,
+    /// Rebuilt from the definitions after every change.
+    level: Level,
+
+    paused: bool,
+    /// The beat the song is paused at.
+    beat: f64,
+
+    selected: Option<usize>,
+    /// The field of the selected obstacle that `-` and `=` change. 0 is the obstacle's beat.
+    field: usize,
+    /// The field whose handle is being dragged.
+    dragging: Option<usize>,
+    scrubbing: bool,
+
+    unsaved: bool,
+    leaving: bool,
+    status: String,
+}
+impl Editor {
+    fn open(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let mut file = if path.exists() {
+            levelfile::read(&path)?
+        } else {
+            LevelFile {
+                info: LevelInfo::default(),
+                song: SongSource::Builtin("during_pride_month"),
+                tempo: dpm::tempo(),
+                checkpoints: vec![],
+                palette: Palette::default(),
+                backdrop: Fill.boxed(),
+                obstacles: vec![],
+            }
+        };
+        let obstacles = mem::take(&mut file.obstacles)
+            .into_iter()
+            .map(|obstacle| EditorObstacle {
+                beat: obstacle.offset,
+                definition: obstacle
+                    .serialize()
+                    .expect("everything in a level file can be written back"),
+            })
+            .collect();
+        let song = file.load_song()?;
+        let mut track = Wav::default();
+        track.load_mem(&song.data)?;
+        let mut editor = Self {
+            level: LevelBuilder::new(0).build(song.clone(), file.tempo.clone(), vec![]),
+            path,
+            file,
+            song,
+            track,
+            obstacles,
+            paused: true,
+            beat: 0.0,
+            selected: None,
+            field: 0,
+            dragging: None,
+            scrubbing: false,
+            unsaved: false,
+            leaving: false,
+            status: String::new(),
+        };
+        editor.level = editor.build()?;
+        Ok(editor)
+    }
+    fn build(&self) -> Result<Level, LevelParseError> {
+        // nothing placed in the editor is random, so the seed doesn't matter
+        let mut builder = LevelBuilder::new(0);
+        builder.info(self.file.info.clone());
+        builder.palette(self.file.palette.clone());
+        builder.backdrop(self.file.backdrop.clone());
+        for obstacle in &self.obstacles {
+            builder.obstacle(obstacle.definition.build(obstacle.beat)?);
+        }
+        Ok(builder.build(
+            self.song.clone(),
+            self.file.tempo.clone(),
+            self.file.checkpoints.clone(),
+        ))
+    }
+    fn song_beats(&self) -> f64 {
+        self.file
+            .tempo
+            .seconds_to_beats(self.track.length())
+            .max(1.0)
+    }
+    fn current_beat(&self, music: &Music) -> f64 {
+        if self.paused {
+            self.beat
+        } else {
+            music.beat()
+        }
+    }
+    /// Rebuilds the level and catches it up to the song.
+    fn refresh(&mut self, music: &Music) -> Result<(), LevelParseError> {
+        self.level = self.build()?;
+        self.level.update_to(music.beat(), &Player::new());
+        Ok(())
+    }
+    fn seek(&mut self, music: &mut Music, beat: f64) -> Result<(), Box<dyn Error>> {
+        let beat = beat.clamp(0.0, self.song_beats());
+        music.play(&self.track, &self.file.tempo);
+        music.seek(beat)?;
+        music.set_paused(self.paused);
+        self.beat = beat;
+        self.refresh(music)?;
+        Ok(())
+    }
+    /// Changes an obstacle, unless the change makes it invalid.
+    fn edit(&mut self, music: &Music, index: usize, change: impl FnOnce(&mut EditorObstacle)) {
+        let mut obstacle = self.obstacles[index].clone();
+        change(&mut obstacle);
+        if obstacle == self.obstacles[index] {
+            return;
+        }
+        if let Err(error) = obstacle.definition.build(obstacle.beat) {
+            self.status = error.kind.to_string();
+            return;
+        }
+        self.obstacles[index] = obstacle;
+        self.unsaved = true;
+        self.status.clear();
+        self.refresh(music)
+            .expect("obstacles are checked before being changed");
+    }
+    fn add(&mut self, music: &Music, obstacle: EditorObstacle) {
+        self.obstacles.push(obstacle);
+        self.selected = Some(self.obstacles.len() - 1);
+        self.field = 0;
+        self.unsaved = true;
+        self.refresh(music)
+            .expect("obstacles are checked before being added");
+    }
+    fn nearby(&self, beat: f64) -> impl Iterator<Item = (usize, &EditorObstacle)> {
+        self.obstacles
+            .iter()
+            .enumerate()
+            .filter(move |(_, obstacle)| (obstacle.beat - beat).abs() <= NEARBY_BEATS)
+    }
+    /// The obstacle and field of the handle under the mouse, if there is one.
+    fn handle_at(&self, beat: f64, position: DVec2) -> Option<(usize, usize)> {
+        self.nearby(beat).find_map(|(index, obstacle)| {
+            let field = obstacle
+                .definition
+                .fields
+                .iter()
+                .position(|(name, value)| {
+                    HANDLE_FIELDS.contains(&name.as_str())
+                        && handle(value).is_some_and(|at| at.distance(position) <= HANDLE_RADIUS)
+                })?;
+            Some((index, field))
+        })
+    }
+    fn save(&mut self) {
+        self.status = match self.build().map(|level| level.save(&self.path)) {
+            Ok(Ok(())) => {
+                self.unsaved = false;
+                format!("saved to {}", self.path.display())
+            }
+            Ok(Err(error)) => format!("couldn't save: {error}"),
+            Err(error) => format!("couldn't save: {}", error.kind),
+        };
+    }
+
+    fn update(&mut self, music: &mut Music) -> Result<(), Box<dyn Error>> {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let beat = self.current_beat(music);
+
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+            self.beat = beat;
+            music.set_paused(self.paused);
+        }
+        if !self.paused && music.finished() {
+            self.paused = true;
+            self.seek(music, 0.0)?;
+        }
+
+        let scrub_step = if shift { 4.0 } else { SNAP };
+        let scrub = scrub(
+            mouse_wheel().1,
+            is_key_pressed(KeyCode::Right),
+            is_key_pressed(KeyCode::Left),
+            scrub_step,
+        );
+        if scrub != 0.0 {
+            self.seek(music, snap(beat + scrub))?;
+        }
+
+        let mouse: Vec2 = mouse_position().into();
+        let on_timeline = mouse.y > screen_height() - TIMELINE_HEIGHT;
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if on_timeline {
+                self.scrubbing = true;
+            } else if let Some((index, field)) = self.handle_at(beat, arena::mouse_position()) {
+                self.selected = Some(index);
+                self.field = field + 1;
+                self.dragging = Some(field);
+            } else {
+                self.selected = None;
+            }
+        }
+        if is_mouse_button_released(MouseButton::Left) {
+            self.scrubbing = false;
+            self.dragging = None;
+        }
+        if self.scrubbing && is_mouse_button_down(MouseButton::Left) {
+            let beat = (mouse.x / screen_width()) as f64 * self.song_beats();
+            self.seek(music, snap(beat))?;
+        }
+        if let (Some(index), Some(field)) = (self.selected, self.dragging) {
+            let position = arena::mouse_position().round();
+            self.edit(music, index, |obstacle| {
+                if let Some(at) = handle_mut(&mut obstacle.definition.fields[field].1) {
+                    *at = position;
+                }
+            });
+        }
+
+        for (index, key) in [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+            KeyCode::Key0,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if is_key_pressed(key) {
+                let behaviour = KINDS[index].1(arena::mouse_position().round());
+                self.add(
+                    music,
+                    EditorObstacle {
+                        beat: snap(beat),
+                        definition: behaviour
+                            .serialize()
+                            .expect("every placeable obstacle can be serialized"),
+                    },
+                );
+            }
+        }
+        if is_key_pressed(KeyCode::Tab) {
+            let after = self.selected.map_or(0, |selected| selected + 1);
+            let nearby: Vec<usize> = self.nearby(beat).map(|(index, _)| index).collect();
+            self.selected = nearby
+                .iter()
+                .find(|&&index| index >= after)
+                .or(nearby.first())
+                .copied();
+            self.field = 0;
+        }
+
+        let Some(selected) = self.selected else {
+            return Ok(());
+        };
+        let field_count = self.obstacles[selected].definition.fields.len() + 1;
+        if is_key_pressed(KeyCode::Down) {
+            self.field = (self.field + 1) % field_count;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.field = (self.field + field_count - 1) % field_count;
+        }
+        let direction = if is_key_pressed(KeyCode::Equal) {
+            1.0
+        } else if is_key_pressed(KeyCode::Minus) {
+            -1.0
+        } else {
+            0.0
+        };
+        if direction != 0.0 {
+            let multiplier = if shift { 10.0 } else { 1.0 } * direction;
+            let field = self.field;
+            self.edit(music, selected, |obstacle| {
+                if field == 0 {
+                    obstacle.beat += SNAP * multiplier;
+                } else if let (name, Value::Number(number)) =
+                    &mut obstacle.definition.fields[field - 1]
+                {
+                    *number += step(name) * multiplier;
+                }
+            });
+        }
+        if control && is_key_pressed(KeyCode::D) {
+            let mut copy = self.obstacles[selected].clone();
+            copy.beat = snap(beat);
+            self.add(music, copy);
+        }
+        if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+            self.obstacles.remove(selected);
+            self.selected = None;
+            self.unsaved = true;
+            self.refresh(music)?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, music: &Music) {
+        let beat = self.current_beat(music);
+        clear_background(BLACK);
+        set_camera(&arena::camera(Vec2::ZERO, View::NEUTRAL));
+        self.level.draw_background(beat);
+        self.level.draw(beat);
+        for (index, obstacle) in self.nearby(beat) {
+            let color = if self.selected == Some(index) {
+                YELLOW
+            } else {
+                WHITE
+            };
+            let handles: Vec<DVec2> = obstacle
+                .definition
+                .fields
+                .iter()
+                .filter(|(name, _)| HANDLE_FIELDS.contains(&name.as_str()))
+                .filter_map(|(_, value)| handle(value))
+                .collect();
+            for pair in handles.windows(2) {
+                draw_line(
+                    pair[0].x as f32,
+                    pair[0].y as f32,
+                    pair[1].x as f32,
+                    pair[1].y as f32,
+                    1.0,
+                    GRAY,
+                );
+            }
+            for at in handles {
+                draw_circle_lines(at.x as f32, at.y as f32, HANDLE_RADIUS as f32, 2.0, color);
+            }
+        }
+        set_default_camera();
+
+        let title = format!(
+            "{}{} - beat {beat:.2} ({}){}",
+            self.file.info.name,
+            if self.unsaved { "*" } else { "" },
+            self.file.tempo.position(beat),
+            if self.paused { " (paused)" } else { "" },
+        );
+        draw_text(&title, 10.0, 20.0, 20.0, WHITE);
+        match self.selected {
+            Some(selected) => {
+                let obstacle = &self.obstacles[selected];
+                draw_text(&obstacle.definition.kind, 10.0, 45.0, 20.0, YELLOW);
+                let lines = [format!("at {}", obstacle.beat)].into_iter().chain(
+                    obstacle
+                        .definition
+                        .fields
+                        .iter()
+                        .map(|(name, value)| format!("{name} {value}")),
+                );
+                for (i, line) in lines.enumerate() {
+                    let color = if i == self.field { YELLOW } else { WHITE };
+                    draw_text(&line, 20.0, 65.0 + i as f32 * 18.0, 18.0, color);
+                }
+            }
+            None => {
+                for (i, (kind, _)) in KINDS.iter().enumerate() {
+                    let line = format!("{} {kind}", (i + 1) % 10);
+                    draw_text(&line, 10.0, 45.0 + i as f32 * 18.0, 18.0, GRAY);
+                }
+            }
+        }
+        draw_text(
+            &self.status,
+            10.0,
+            screen_height() - TIMELINE_HEIGHT - 10.0,
+            18.0,
+            WHITE,
+        );
+
+        let top = screen_height() - TIMELINE_HEIGHT;
+        let beat_x = |beat: f64| (beat / self.song_beats()) as f32 * screen_width();
+        draw_rectangle(0.0, top, screen_width(), TIMELINE_HEIGHT, GRAY);
+        for obstacle in &self.obstacles {
+            let x = beat_x(obstacle.beat);
+            draw_line(x, top, x, top + TIMELINE_HEIGHT * 0.5, 1.0, BLACK);
+        }
+        for &checkpoint in &self.file.checkpoints {
+            let x = beat_x(checkpoint);
+            draw_line(x, top, x, screen_height(), 2.0, GREEN);
+        }
+        let x = beat_x(beat);
+        draw_line(x, top, x, screen_height(), 2.0, WHITE);
+    }
+}
+
+/// Opens the editor on a level file, until escape is pressed.
+pub async fn edit(path: impl AsRef<Path>, music: &mut Music) -> Result<(), Box<dyn Error>> {
+    let mut editor = Editor::open(path.as_ref().to_owned())?;
+    editor.seek(music, 0.0)?;
+    loop {
+        arena::update_fullscreen();
+        if is_key_pressed(KeyCode::Escape) {
+            if !editor.unsaved || editor.leaving {
+                music.set_paused(true);
+                return Ok(());
+            }
+            editor.leaving = true;
+            editor.status = "unsaved changes! press Escape again to leave anyway".to_owned();
+        }
+        let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if control && is_key_pressed(KeyCode::S) {
+            editor.save();
+            editor.leaving = false;
+        }
+        editor.update(music)?;
+        if !editor.paused {
+            // there's no player while editing, so aimed obstacles aim at where it spawns
+            let beat = music.beat();
+            editor.level.update(beat, &Player::new());
+        }
+        editor.draw(music);
+        next_frame().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubbing_only_moves_with_input() {
+        assert_eq!(
+            scrub(0.0, false, false, SNAP),
+            0.0,
+            "an idle wheel stays put"
+        );
+        assert_eq!(scrub(-120.0, false, false, SNAP), SNAP);
+        assert_eq!(scrub(3.0, false, false, 4.0), -4.0);
+        assert_eq!(scrub(0.0, true, false, SNAP), SNAP);
+        assert_eq!(scrub(1.0, true, false, SNAP), 0.0);
+        assert_eq!(scrub(0.0, true, true, SNAP), 0.0);
+    }
+}
@@ -1,92 +1,242 @@
-use std::mem;
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufWriter},
+    mem,
+    path::{Path, PathBuf},
+};
 
 use jut::extensions::Boxed;
 use macroquad::{
-    color::{Color, BLACK, RED},
+    color::{Color, GREEN, ORANGE, RED, SKYBLUE},
     math::{dvec2, Vec2},
     shapes::draw_rectangle,
-    window::{screen_height, screen_width},
 };
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
 use crate::{
+    arena,
+    backdrop::{DynBackdrop, Fill},
     ext::ColorExt,
-    music::Music,
+    levelfile::{self, LevelWriteError},
     obstacle::Obstacle,
+    palette::Palette,
     player::Player,
-    provider::{FnWrap, Provider},
-    shared::Shared,
+    res::songs,
+    shared::{Shared, View},
+    tempo::TempoMap,
     transform,
 };
 
+/// Where a level's song comes from, kept around so levels can be written back out.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SongSource {
+    /// A song embedded in the executable, looked up with [`songs::builtin`].
+    Builtin(&'static str),
+    File(PathBuf),
+}
+#[derive(Clone)]
+pub struct Song {
+    pub source: SongSource,
+    pub data: Cow<'static, [u8]>,
+}
+impl Song {
+    pub fn builtin(name: &'static str) -> Option<Self> {
+        Some(Self {
+            source: SongSource::Builtin(name),
+            data: Cow::Borrowed(songs::builtin(name)?),
+        })
+    }
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        Ok(Self {
+            data: Cow::Owned(std::fs::read(&path)?),
+            source: SongSource::File(path),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Hardcore,
+}
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Hardcore,
+    ];
+    /// The name used for this difficulty in level files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Hardcore => "hardcore",
+        }
+    }
+    pub fn color(self) -> Color {
+        match self {
+            Difficulty::Easy => GREEN,
+            Difficulty::Normal => SKYBLUE,
+            Difficulty::Hard => ORANGE,
+            Difficulty::Hardcore => RED,
+        }
+    }
+}
+/// How the seed a level is built with gets picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedPolicy {
+    /// The same layout every time. Only 32 bits, since level files store numbers as floats.
+    Fixed(u32),
+    /// A new layout every time the game starts, kept through deaths.
+    PerSession,
+    /// A new layout every attempt, unless a checkpoint has been reached.
+    PerAttempt,
+}
+impl SeedPolicy {
+    /// The seed to build the next attempt with.
+    pub fn seed(self, session_seed: u64) -> u64 {
+        match self {
+            SeedPolicy::Fixed(seed) => seed as u64,
+            SeedPolicy::PerSession => session_seed,
+            SeedPolicy::PerAttempt => thread_rng().gen(),
+        }
+    }
+}
+
+/// Everything shown about a level outside of playing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelInfo {
+    pub name: String,
+    pub artist: String,
+    pub difficulty: Difficulty,
+    /// The beat the song preview starts at in the level select.
+    pub preview_beat: f64,
+    pub seed_policy: SeedPolicy,
+}
+impl Default for LevelInfo {
+    fn default() -> Self {
+        Self {
+            name: "Untitled".to_owned(),
+            artist: "Unknown".to_owned(),
+            difficulty: Difficulty::Normal,
+            preview_beat: 0.0,
+            seed_policy: SeedPolicy::PerSession,
+        }
+    }
+}
+
 pub struct LevelBuilder {
+    rng: StdRng,
+    info: LevelInfo,
     obstacles: Vec<Obstacle>,
+    palette: Palette,
+    backdrop: DynBackdrop,
 }
 impl LevelBuilder {
-    pub fn new() -> Self {
-        Self { obstacles: vec![] }
+    /// Anything random in a level should come from [`LevelBuilder::rng`],
+    /// so the same seed always builds the same level.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            info: LevelInfo::default(),
+            obstacles: vec![],
+            palette: Palette::default(),
+            backdrop: Fill.boxed(),
+        }
+    }
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
     }
-    pub fn obstacle(&mut self, add: Obstacle) -> &mut Self {
+    pub fn obstacle(&mut self, mut add: Obstacle) -> &mut Self {
+        add.behaviour.seed(&mut self.rng);
         self.obstacles.push(add);
         self
     }
+    pub fn info(&mut self, info: LevelInfo) -> &mut Self {
+        self.info = info;
+        self
+    }
+    pub fn palette(&mut self, palette: Palette) -> &mut Self {
+        self.palette = palette;
+        self
+    }
+    pub fn backdrop(&mut self, backdrop: DynBackdrop) -> &mut Self {
+        self.backdrop = backdrop;
+        self
+    }
     pub fn pop_last_obstacle(&mut self) -> Option<Obstacle> {
         self.obstacles.pop()
     }
-    pub fn build(
-        self,
-        song_data: &'static [u8],
-        bpm: f64,
-        start_time: f64,
-        checkpoints: &'static [f64],
-    ) -> Level {
+    pub fn build(self, song: Song, tempo: TempoMap, checkpoints: Vec<f64>) -> Level {
         Level {
+            info: self.info,
             obstacles: self.obstacles,
 
             shake: 0.0,
             jerk: Vec2::ZERO,
+            view: View::NEUTRAL,
 
-            foreground_color: FnWrap(|_| Color {
-                r: 1.0,
-                g: 0.0,
-                b: 0.5,
-                a: 1.0,
-            })
-            .boxed(),
-            background_color: FnWrap(|_| BLACK).boxed(),
+            palette: self.palette,
+            backdrop: self.backdrop,
 
             last_beat: 0.0,
 
-            song_data,
-            bpm,
-            start_time,
+            song,
+            tempo,
 
             checkpoints,
         }
     }
 }
 
+/// Everything about a level that changes while it's played, saved so it can be gone back to.
+#[derive(Clone)]
+pub struct LevelSnapshot {
+    obstacles: Vec<Obstacle>,
+    palette: Palette,
+    backdrop: DynBackdrop,
+    shake: f64,
+    jerk: Vec2,
+    view: View,
+    last_beat: f64,
+}
+impl LevelSnapshot {
+    /// The beat the snapshot was taken at.
+    pub fn beat(&self) -> f64 {
+        self.last_beat
+    }
+}
+
 pub struct Level {
+    pub info: LevelInfo,
     pub shake: f64,
     pub jerk: Vec2,
+    /// Where obstacles have moved the camera to. Worked out again every update.
+    pub view: View,
     obstacles: Vec<Obstacle>,
 
-    foreground_color: Box<dyn Provider<Color>>,
-    background_color: Box<dyn Provider<Color>>,
+    palette: Palette,
+    backdrop: DynBackdrop,
 
     last_beat: f64,
 
-    pub song_data: &'static [u8],
-    pub bpm: f64,
-    pub start_time: f64,
+    pub song: Song,
+    pub tempo: TempoMap,
 
-    pub checkpoints: &'static [f64],
+    pub checkpoints: Vec<f64>,
 }
 impl Level {
-    pub fn update(&mut self, beat: f64) {
+    pub fn update(&mut self, beat: f64, player: &Player) {
         let dt = beat - self.last_beat;
         self.shake = transform::time_independent_lerp(self.shake, 0.0, 0.1, dt);
         self.jerk = transform::time_independent_vec2_lerp(self.jerk, Vec2::ZERO, 0.1, dt);
-        let mut shared = Shared::new();
+        let mut shared = Shared::new(player.target(self.tempo.bpm_at(beat)));
         for i in &mut self.obstacles {
             i.update(&mut shared, beat);
         }
@@ -101,11 +251,13 @@ impl Level {
         }
         self.shake += shared.shake();
         self.jerk += shared.jerk();
-        if let Some(new_background) = mem::take(&mut shared.new_background) {
-            self.background_color = new_background;
+        self.view = shared.view();
+        self.palette.settle(beat);
+        for swap in mem::take(&mut shared.swaps) {
+            self.palette.swap(swap, beat);
         }
-        if let Some(new_foreground) = mem::take(&mut shared.new_foreground) {
-            self.foreground_color = new_foreground;
+        if let Some(backdrop) = shared.backdrop.take() {
+            self.backdrop = backdrop;
         }
         for mut i in shared.consume_for_obstacles() {
             i.offset += beat;
@@ -121,12 +273,54 @@ impl Level {
         }
         false
     }
-    pub fn background_color(&self, beat: f64) -> Color {
-        self.background_color.get(beat)
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+    pub fn backdrop(&self) -> &DynBackdrop {
+        &self.backdrop
+    }
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+    /// Saves the current state of every obstacle, color and backdrop, to go back to with
+    /// [`Level::restore`].
+    pub fn snapshot(&self) -> LevelSnapshot {
+        LevelSnapshot {
+            obstacles: self.obstacles.clone(),
+            palette: self.palette.clone(),
+            backdrop: self.backdrop.clone(),
+            shake: self.shake,
+            jerk: self.jerk,
+            view: self.view,
+            last_beat: self.last_beat,
+        }
+    }
+    /// Puts the level back exactly how it was when the snapshot was taken.
+    pub fn restore(&mut self, snapshot: &LevelSnapshot) {
+        let snapshot = snapshot.clone();
+        self.obstacles = snapshot.obstacles;
+        self.palette = snapshot.palette;
+        self.backdrop = snapshot.backdrop;
+        self.shake = snapshot.shake;
+        self.jerk = snapshot.jerk;
+        self.view = snapshot.view;
+        self.last_beat = snapshot.last_beat;
+    }
+    /// Writes this level to a level file. Fails if any part of it can't be serialized.
+    #[allow(dead_code)]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LevelWriteError> {
+        let path = path.as_ref();
+        let mut out = BufWriter::new(File::create(path)?);
+        levelfile::write_level(self, &mut out, path.parent().unwrap_or(Path::new("")))
+    }
+    /// Draws the backdrop, which goes behind everything else.
+    pub fn draw_background(&self, beat: f64) {
+        self.backdrop.draw(&self.palette.swatch(beat), beat);
     }
     pub fn draw(&mut self, beat: f64) {
+        let swatch = self.palette.swatch(beat);
         for i in &self.obstacles {
-            i.draw(self.foreground_color.get(beat), beat);
+            i.draw(&swatch, beat);
         }
     }
     #[allow(dead_code)]
@@ -136,8 +330,8 @@ impl Level {
         let horizontal_resolution = 100;
         let vertical_resolution = 100;
 
-        let fragment_width = screen_width() / horizontal_resolution as f32;
-        let fragment_height = screen_height() / vertical_resolution as f32;
+        let fragment_width = arena::WIDTH as f32 / horizontal_resolution as f32;
+        let fragment_height = arena::HEIGHT as f32 / vertical_resolution as f32;
 
         for xi in 0..horizontal_resolution {
             let x = xi as f32 * fragment_width;
@@ -153,10 +347,9 @@ impl Level {
             }
         }
     }
-    /// Updates and kills objects as needed. Killed objects can only change colors.
-    pub fn update_to(&mut self, music: &Music) {
-        let mut shared = Shared::new();
-        let beat = music.beat();
+    /// Updates and kills objects as needed. Killed objects can only change colors and the backdrop.
+    pub fn update_to(&mut self, beat: f64, player: &Player) {
+        let mut shared = Shared::new(player.target(self.tempo.bpm_at(beat)));
         for i in &mut self.obstacles {
             i.update(&mut shared, beat);
         }
@@ -169,11 +362,101 @@ impl Level {
                 i += 1;
             }
         }
-        if let Some(bg) = mem::take(&mut shared.new_background) {
-            self.background_color = bg;
+        self.palette.settle(beat);
+        for swap in mem::take(&mut shared.swaps) {
+            self.palette.swap(swap, beat);
+        }
+        if let Some(backdrop) = shared.backdrop.take() {
+            self.backdrop = backdrop;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jut::extensions::Boxed;
+    use macroquad::math::dvec2;
+    use rand::Rng;
+
+    use crate::{
+        levelfile::{self, BehaviourDefinition},
+        levels::dpm,
+        obstacle::lasers::SlamLaser,
+    };
+
+    use super::*;
+
+    fn random_level(seed: u64) -> Level {
+        let mut builder = LevelBuilder::new(seed);
+        for i in 0..32 {
+            let start = dvec2(builder.rng().gen_range(0.0..800.0), -50.0);
+            let end = dvec2(builder.rng().gen_range(0.0..800.0), 650.0);
+            let start_pos = if builder.rng().gen() { start } else { end };
+            builder.obstacle(Obstacle::new(
+                i as f64,
+                SlamLaser::default()
+                    .start_pos(start_pos)
+                    .end_pos(end)
+                    .boxed(),
+            ));
         }
-        if let Some(fg) = mem::take(&mut shared.new_foreground) {
-            self.foreground_color = fg;
+        builder.build(
+            Song::builtin("during_pride_month").unwrap(),
+            TempoMap::new(120.0, 0.0),
+            vec![],
+        )
+    }
+
+    fn layout(level: &Level) -> Vec<(f64, Option<BehaviourDefinition>)> {
+        level
+            .obstacles()
+            .iter()
+            .map(|obstacle| (obstacle.offset, obstacle.behaviour.serialize()))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_builds_same_level() {
+        assert_eq!(layout(&random_level(1234)), layout(&random_level(1234)));
+        assert_ne!(layout(&random_level(1234)), layout(&random_level(4321)));
+    }
+
+    /// Steps through a whole level like the game would, sampling where the player would get hit
+    /// once a beat. Returns the samples and the beat the last obstacle was gone at.
+    fn step_through(mut level: Level) -> (Vec<bool>, f64) {
+        let mut samples = vec![];
+        let mut player = Player::new();
+        let mut beat = level.tempo.seconds_to_beats(0.0);
+        let mut step = 0;
+        while !level.obstacles().is_empty() {
+            assert!(beat < 2000.0, "level never ended");
+            level.update(beat, &player);
+            if step % 8 == 0 {
+                for x in (0..800).step_by(100) {
+                    for y in (0..600).step_by(100) {
+                        player.position = dvec2(x as f64 + 50.0, y as f64 + 50.0);
+                        samples.push(level.collide(&player, beat));
+                    }
+                }
+            }
+            beat += 0.125;
+            step += 1;
         }
+        (samples, beat)
+    }
+
+    #[test]
+    fn builtin_level_plays_the_same_every_time() {
+        let (samples, end) = step_through(dpm::build(99));
+        assert!(samples.contains(&true));
+        assert_eq!(step_through(dpm::build(99)), (samples, end));
+    }
+
+    #[test]
+    fn sample_level_ends() {
+        let level = levelfile::load("levels/sample.epar", 0).unwrap();
+        let (samples, end) = step_through(level);
+        assert!(samples.contains(&true));
+        assert!(end > 0.0);
     }
 }
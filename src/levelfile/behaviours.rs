@@ -0,0 +1,541 @@
+use jut::extensions::Boxed;
+use macroquad::{
+    color::Color,
+    math::{DVec2, Vec2},
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    backdrop::{Checkerboard, DynBackdrop, Fill, Grid, LinearGradient, RadialGradient, Stripes},
+    easing::Easing,
+    obstacle::{
+        lasers::{RotatingLaser, SlamLaser, WidenLaser},
+        Bomb, Camera, Circle, DynObstacleBehaviour, Edge, Group, Obstacle, Rectangle,
+        RectangleGenerator, Ring, SetBackdrop, SetPalette, Shake, Shape, Spawn, SpawnTemplate,
+        Spawner, Text, Wall,
+    },
+    palette::Slot,
+    polygon::presets::Preset,
+    provider::Provider,
+};
+
+use super::{
+    lexer::Span,
+    value::{self, FromValue, Value, ValueError},
+    LevelParseError, ParseErrorKind,
+};
+
+/// The level file representation of an obstacle behaviour: its kind and its builder fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BehaviourDefinition {
+    pub kind: String,
+    pub fields: Vec<(String, Value)>,
+}
+impl BehaviourDefinition {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_owned(),
+            fields: vec![],
+        }
+    }
+    pub fn field(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.fields.push((name.to_owned(), value.into()));
+        self
+    }
+    pub fn optional_field(self, name: &str, value: Option<impl Into<Value>>) -> Self {
+        match value {
+            Some(value) => self.field(name, value),
+            None => self,
+        }
+    }
+    /// Builds the obstacle this describes at `offset`, the same way a level file would.
+    /// There's no file for errors to point into, so they don't have a span.
+    pub fn build(&self, offset: f64) -> Result<Obstacle, LevelParseError> {
+        parse_obstacle(offset, self.to_fields()).map_err(LevelParseError::without_span)
+    }
+    /// Like [`BehaviourDefinition::build`], for a backdrop instead of an obstacle.
+    pub fn build_backdrop(&self) -> Result<DynBackdrop, LevelParseError> {
+        parse_backdrop(self.to_fields()).map_err(LevelParseError::without_span)
+    }
+    fn to_fields(&self) -> Fields {
+        // only there to fill in the fields, every error gets it taken off
+        let span = Span { line: 1, column: 1 };
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, value)| Field {
+                name: name.clone(),
+                value: value.clone(),
+                span,
+            })
+            .collect();
+        Fields::new(self.kind.clone(), span, fields)
+    }
+    /// Fills in the per-spawn values of a spawner's template, see [`value::resolve_spawn`].
+    pub fn resolve(&self, spawn: &mut Spawn) -> Result<Self, ValueError> {
+        Ok(Self {
+            kind: self.kind.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), value::resolve_spawn(value, spawn)?)))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Builds the first spawn of a template, so mistakes in it show up while loading.
+/// Errors point at the whole template, since its fields don't keep their position.
+fn check_template(template: &BehaviourDefinition, span: Span) -> Result<(), LevelParseError> {
+    let mut spawn = Spawn {
+        index: 0,
+        beat: 0.0,
+        rng: &mut StdRng::seed_from_u64(0),
+    };
+    let resolved = template
+        .resolve(&mut spawn)
+        .map_err(|error| LevelParseError::value(span, "template", error))?;
+    resolved
+        .build(0.0)
+        .map_err(|error| LevelParseError::new(span, error.kind))?;
+    Ok(())
+}
+
+pub struct Field {
+    pub name: String,
+    pub value: Value,
+    pub span: Span,
+}
+
+/// The fields of an obstacle while it's being parsed.
+/// Every field has to be taken before calling [`Fields::finish`], otherwise it's unknown.
+pub struct Fields {
+    kind: String,
+    span: Span,
+    fields: Vec<Field>,
+}
+impl Fields {
+    pub fn new(kind: String, span: Span, fields: Vec<Field>) -> Self {
+        Self { kind, span, fields }
+    }
+    fn take_field(&mut self, name: &str) -> Option<Field> {
+        let index = self.fields.iter().position(|field| field.name == name)?;
+        Some(self.fields.remove(index))
+    }
+    fn missing(&self, name: &str) -> LevelParseError {
+        LevelParseError::new(
+            self.span,
+            ParseErrorKind::MissingField {
+                kind: self.kind.clone(),
+                field: name.to_owned(),
+            },
+        )
+    }
+    pub fn take<T: FromValue>(&mut self, name: &str) -> Result<Option<T>, LevelParseError> {
+        self.take_field(name)
+            .map(|field| {
+                T::from_value(&field.value)
+                    .map_err(|error| LevelParseError::value(field.span, name, error))
+            })
+            .transpose()
+    }
+    pub fn require<T: FromValue>(&mut self, name: &str) -> Result<T, LevelParseError> {
+        self.take(name)?.ok_or_else(|| self.missing(name))
+    }
+    pub fn take_provider<T: FromValue>(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<Box<dyn Provider<T>>>, LevelParseError> {
+        self.take_field(name)
+            .map(|field| {
+                value::provider(&field.value)
+                    .map_err(|error| LevelParseError::value(field.span, name, error))
+            })
+            .transpose()
+    }
+    pub fn require_provider<T: FromValue>(
+        &mut self,
+        name: &str,
+    ) -> Result<Box<dyn Provider<T>>, LevelParseError> {
+        self.take_provider(name)?.ok_or_else(|| self.missing(name))
+    }
+    /// Errors on the first field that hasn't been taken.
+    pub fn finish(self) -> Result<(), LevelParseError> {
+        match self.fields.into_iter().next() {
+            Some(field) => Err(LevelParseError::new(
+                field.span,
+                ParseErrorKind::UnknownField {
+                    kind: self.kind,
+                    field: field.name,
+                },
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the children of a group: obstacles starting with the group, or `at(beat, obstacle)`.
+/// Like templates, errors point at the whole list.
+fn group_children(field: Field) -> Result<Vec<Obstacle>, LevelParseError> {
+    let invalid = |error| LevelParseError::value(field.span, "children", error);
+    let Value::List(values) = &field.value else {
+        return Err(invalid(ValueError::Mismatch {
+            expected: "a list",
+            found: field.value.kind_name(),
+        }));
+    };
+    values
+        .iter()
+        .map(|value| {
+            let (offset, definition) = match value {
+                Value::Call(name, args) if name == "at" => {
+                    let [offset, definition] =
+                        value::arguments::<2>(name, args).map_err(invalid)?;
+                    (
+                        f64::from_value(offset).map_err(invalid)?,
+                        BehaviourDefinition::from_value(definition).map_err(invalid)?,
+                    )
+                }
+                value => (
+                    0.0,
+                    BehaviourDefinition::from_value(value).map_err(invalid)?,
+                ),
+            };
+            definition
+                .build(offset)
+                .map_err(|error| LevelParseError::new(field.span, error.kind))
+        })
+        .collect()
+}
+
+/// Reads a backdrop nested in a field, like a `SetBackdrop`'s. Errors point at the whole backdrop.
+fn backdrop_field(field: Field) -> Result<DynBackdrop, LevelParseError> {
+    BehaviourDefinition::from_value(&field.value)
+        .map_err(|error| LevelParseError::value(field.span, &field.name, error))?
+        .build_backdrop()
+        .map_err(|error| LevelParseError::new(field.span, error.kind))
+}
+
+/// Sets every field of a builder-style behaviour that's present.
+macro_rules! optional_fields {
+    ($fields:ident, $behaviour:ident, $($field:ident: $type:ty),* $(,)?) => {
+        $(
+            if let Some(value) = $fields.take::<$type>(stringify!($field))? {
+                $behaviour = $behaviour.$field(value);
+            }
+        )*
+    };
+}
+
+/// Like [`optional_fields`], for builder fields that take providers.
+macro_rules! optional_providers {
+    ($fields:ident, $behaviour:ident, $($field:ident: $type:ty),* $(,)?) => {
+        $(
+            if let Some(provider) = $fields.take_provider::<$type>(stringify!($field))? {
+                $behaviour = $behaviour.$field(provider);
+            }
+        )*
+    };
+}
+
+/// Builds an obstacle out of the fields in a level file. Any kind can pick a palette `slot`.
+pub fn parse_obstacle(offset: f64, mut fields: Fields) -> Result<Obstacle, LevelParseError> {
+    let slot = fields.take::<Slot>("slot")?.unwrap_or_default();
+    Ok(Obstacle::new(offset, parse_behaviour(fields)?).slot(slot))
+}
+
+/// Builds a behaviour out of the fields in a level file.
+fn parse_behaviour(mut fields: Fields) -> Result<DynObstacleBehaviour, LevelParseError> {
+    let behaviour: DynObstacleBehaviour = match fields.kind.as_str() {
+        "SlamLaser" => {
+            let mut laser = SlamLaser::default();
+            optional_fields!(
+                fields, laser,
+                start_pos: DVec2,
+                end_pos: DVec2,
+                warn_time: f64,
+                lifetime: f64,
+                leave_time: f64,
+                thickness: f64,
+                flash_time: f64,
+                shake: f64,
+                jerk: Vec2,
+            );
+            laser.boxed()
+        }
+        "WidenLaser" => {
+            let mut laser = WidenLaser::default();
+            optional_fields!(
+                fields, laser,
+                start_pos: DVec2,
+                end_pos: DVec2,
+                warn_time: f64,
+                grow_time: f64,
+                lifetime: f64,
+                shrink_time: f64,
+                thickness: f64,
+                flash_time: f64,
+                shake: f64,
+                jerk: Vec2,
+            );
+            laser.boxed()
+        }
+        "RotatingLaser" => {
+            let mut laser = RotatingLaser::default();
+            optional_providers!(
+                fields, laser,
+                pivot: DVec2,
+                angle: f64,
+                length: f64,
+                thickness: f64,
+            );
+            optional_fields!(
+                fields, laser,
+                warn_time: f64,
+                lifetime: f64,
+                leave_time: f64,
+                flash_time: f64,
+                shake: f64,
+                jerk: Vec2,
+            );
+            laser.boxed()
+        }
+        "Ring" => {
+            let mut ring = Ring::default();
+            optional_providers!(
+                fields, ring,
+                center: DVec2,
+                inner_radius: f64,
+                outer_radius: f64,
+                rotation: f64,
+            );
+            optional_fields!(
+                fields, ring,
+                gap_count: usize,
+                gap_width: f64,
+                warn_time: f64,
+                lifetime: f64,
+                flash_time: f64,
+                shake: f64,
+            );
+            ring.boxed()
+        }
+        "Wall" => {
+            let mut wall = Wall::default();
+            optional_providers!(fields, wall, speed: f64);
+            optional_fields!(
+                fields, wall,
+                from: Edge,
+                thickness: f64,
+                gaps: Vec<DVec2>,
+                gap_spacing: f64,
+                gap_width: f64,
+                gap_offset: f64,
+                warn_time: f64,
+            );
+            wall.boxed()
+        }
+        "Bomb" => Bomb {
+            start_position: fields.require("start_position")?,
+            end_position: fields.require("end_position")?,
+            lifetime: fields.require("lifetime")?,
+            radius_per_beat: fields.require("radius_per_beat")?,
+            projectile_count: fields.require("projectile_count")?,
+            projectile_radius: fields.require("projectile_radius")?,
+            projectile_speed: fields.require("projectile_speed")?,
+            aimed: fields.take("aimed")?.unwrap_or(false),
+        }
+        .boxed(),
+        "Circle" => Circle::new(
+            fields.require_provider::<DVec2>("position")?,
+            fields.require("radius")?,
+            fields.take("lifetime")?,
+        )
+        .boxed(),
+        "Rectangle" => Rectangle {
+            center: fields.require_provider("center")?,
+            size: fields.require_provider("size")?,
+            rotation: fields.require_provider("rotation")?,
+            lifetime: fields.require("lifetime")?,
+            warn_time: fields.require("warn_time")?,
+            leave_time: fields.require("leave_time")?,
+        }
+        .boxed(),
+        "Shape" => {
+            let preset: Preset = fields.require("polygon")?;
+            Shape {
+                polygon: preset.polygon(),
+                preset: Some(preset),
+                position: fields.require_provider("position")?,
+                rotation: fields.require_provider("rotation")?,
+                scale: fields.require_provider("scale")?,
+                lifetime: fields.require("lifetime")?,
+                warn_time: fields.require("warn_time")?,
+                leave_time: fields.require("leave_time")?,
+            }
+            .boxed()
+        }
+        "Text" => {
+            let mut text = Text::default().text(fields.require::<String>("text")?);
+            optional_providers!(
+                fields, text,
+                position: DVec2,
+                rotation: f64,
+                scale: DVec2,
+            );
+            optional_fields!(
+                fields, text,
+                warn_time: f64,
+                lifetime: f64,
+                leave_time: f64,
+                flash_time: f64,
+                shake: f64,
+            );
+            text.boxed()
+        }
+        "RectangleGenerator" => RectangleGenerator {
+            interval: fields.require("interval")?,
+            lifetime: fields.require("lifetime")?,
+            spawned: 0,
+            spawned_center: fields.require_provider("spawned_center")?,
+            spawned_size: fields.require_provider("spawned_size")?,
+            spawned_rotation: fields.require_provider("spawned_rotation")?,
+            spawned_lifetime: fields.require("spawned_lifetime")?,
+            spawned_warn_time: fields.require("spawned_warn_time")?,
+        }
+        .boxed(),
+        "Spawner" => {
+            let Some(template) = fields.take_field("template") else {
+                return Err(fields.missing("template"));
+            };
+            let definition = BehaviourDefinition::from_value(&template.value)
+                .map_err(|error| LevelParseError::value(template.span, "template", error))?;
+            check_template(&definition, template.span)?;
+            let mut spawner = Spawner::new(
+                SpawnTemplate::Definition(definition),
+                fields.require("interval")?,
+                fields.require("count")?,
+            );
+            optional_fields!(fields, spawner, lead_time: f64);
+            spawner.boxed()
+        }
+        "Group" => {
+            let mut group = Group::default();
+            if let Some(children) = fields.take_field("children") {
+                group = group.children(group_children(children)?);
+            }
+            optional_providers!(fields, group, position: DVec2, rotation: f64, scale: f64);
+            group.boxed()
+        }
+        "Camera" => {
+            let mut camera = Camera::default();
+            optional_providers!(fields, camera, zoom: f64, rotation: f64, pan: DVec2);
+            optional_fields!(
+                fields, camera,
+                duration: f64,
+                blend_time: f64,
+                easing: Easing,
+            );
+            camera.boxed()
+        }
+        "SetPalette" => {
+            let mut palette = SetPalette::default();
+            for slot in Slot::ALL {
+                if let Some(color) = fields.take_provider(slot.name())? {
+                    palette = palette.color(slot, color);
+                }
+            }
+            optional_fields!(fields, palette, duration: f64, easing: Easing);
+            palette.boxed()
+        }
+        // from before palettes, when there was only a foreground and background
+        "SetForeground" => SetPalette::default()
+            .color(Slot::Primary, fields.require_provider("color")?)
+            .boxed(),
+        "SetBackground" => SetPalette::default()
+            .color(Slot::Background, fields.require_provider("color")?)
+            .boxed(),
+        "SetBackdrop" => match fields.take_field("backdrop") {
+            Some(backdrop) => SetBackdrop(backdrop_field(backdrop)?),
+            None => SetBackdrop::default(),
+        }
+        .boxed(),
+        "Shake" => Shake(fields.require("amount")?).boxed(),
+        _ => {
+            return Err(LevelParseError::new(
+                fields.span,
+                ParseErrorKind::UnknownBehaviour(fields.kind),
+            ))
+        }
+    };
+    fields.finish()?;
+    Ok(behaviour)
+}
+
+/// Builds a backdrop out of the fields in a level file.
+pub fn parse_backdrop(mut fields: Fields) -> Result<DynBackdrop, LevelParseError> {
+    let backdrop: DynBackdrop = match fields.kind.as_str() {
+        "Fill" => Fill.boxed(),
+        "LinearGradient" => {
+            let mut gradient = LinearGradient::default();
+            optional_providers!(fields, gradient, from: Color, to: Color, angle: f64);
+            gradient.boxed()
+        }
+        "RadialGradient" => {
+            let mut gradient = RadialGradient::default();
+            optional_providers!(
+                fields, gradient,
+                inner: Color,
+                outer: Color,
+                center: DVec2,
+                radius: f64,
+            );
+            gradient.boxed()
+        }
+        "Stripes" => {
+            let mut stripes = Stripes::default();
+            optional_providers!(
+                fields, stripes,
+                a: Color,
+                b: Color,
+                width: f64,
+                rotation: f64,
+                scroll: f64,
+            );
+            stripes.boxed()
+        }
+        "Checkerboard" => {
+            let mut checkerboard = Checkerboard::default();
+            optional_providers!(
+                fields, checkerboard,
+                a: Color,
+                b: Color,
+                size: f64,
+                rotation: f64,
+                scroll: DVec2,
+            );
+            checkerboard.boxed()
+        }
+        "Grid" => {
+            let mut grid = Grid::default();
+            optional_providers!(
+                fields, grid,
+                background: Color,
+                line: Color,
+                spacing: f64,
+                thickness: f64,
+                rotation: f64,
+                scroll: DVec2,
+            );
+            grid.boxed()
+        }
+        _ => {
+            return Err(LevelParseError::new(
+                fields.span,
+                ParseErrorKind::UnknownBackdrop(fields.kind),
+            ))
+        }
+    };
+    fields.finish()?;
+    Ok(backdrop)
+}
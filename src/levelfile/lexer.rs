@@ -0,0 +1,217 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+use macroquad::color::Color;
+
+use super::{LevelParseError, ParseErrorKind};
+
+/// A position in a level file. Both fields start at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(f64),
+    String(String),
+    Color(Color),
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Eof,
+}
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{ident}`"),
+            Token::Number(number) => write!(f, "`{number}`"),
+            Token::String(string) => write!(f, "{}", Quoted(string)),
+            Token::Color(_) => write!(f, "a color"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::LBrace => write!(f, "`{{`"),
+            Token::RBrace => write!(f, "`}}`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+/// Writes a string as a string literal, with only the escapes the lexer reads back.
+pub struct Quoted<'a>(pub &'a str);
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\r' => write!(f, "\\r")?,
+                other => write!(f, "{other}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+pub struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+    fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+        }
+    }
+    fn bump(&mut self) -> Option<char> {
+        let next = self.chars.next()?;
+        if next == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(next)
+    }
+    fn take_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> String {
+        let mut out = String::new();
+        while let Some(&next) = self.chars.peek() {
+            if !predicate(next) {
+                break;
+            }
+            out.push(next);
+            self.bump();
+        }
+        out
+    }
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some(next) if next.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.peek() != Some(&'/') {
+                        return;
+                    }
+                    self.take_while(|c| c != '\n');
+                }
+                _ => return,
+            }
+        }
+    }
+    /// Lexes the next token, returning it alongside where it started.
+    pub fn next_token(&mut self) -> Result<(Token, Span), LevelParseError> {
+        self.skip_whitespace_and_comments();
+        let span = self.span();
+        let error = |kind| Err(LevelParseError::new(span, kind));
+        let Some(&next) = self.chars.peek() else {
+            return Ok((Token::Eof, span));
+        };
+        let token = match next {
+            '(' | ')' | '{' | '}' | '[' | ']' | ',' => {
+                self.bump();
+                match next {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '{' => Token::LBrace,
+                    '}' => Token::RBrace,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    _ => Token::Comma,
+                }
+            }
+            '"' => {
+                self.bump();
+                let mut string = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some('r') => string.push('\r'),
+                            Some(escaped @ ('"' | '\\')) => string.push(escaped),
+                            Some(other) => return error(ParseErrorKind::InvalidEscape(other)),
+                            None => return error(ParseErrorKind::UnterminatedString),
+                        },
+                        Some(other) => string.push(other),
+                        None => return error(ParseErrorKind::UnterminatedString),
+                    }
+                }
+                Token::String(string)
+            }
+            '#' => {
+                self.bump();
+                let hex = self.take_while(|c| c.is_ascii_alphanumeric());
+                match parse_hex_color(&hex) {
+                    Some(color) => Token::Color(color),
+                    None => return error(ParseErrorKind::InvalidColor(hex)),
+                }
+            }
+            '-' | '+' | '.' | '0'..='9' => {
+                let mut last = '\0';
+                let text = self.take_while(|c| {
+                    // exponents can carry their own sign
+                    let accept = c.is_ascii_alphanumeric()
+                        || c == '.'
+                        || c == '_'
+                        || ((c == '-' || c == '+') && (last == '\0' || last == 'e' || last == 'E'));
+                    last = c;
+                    accept
+                });
+                match text.replace('_', "").parse() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => return error(ParseErrorKind::InvalidNumber(text)),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                Token::Ident(self.take_while(|c| c.is_alphanumeric() || c == '_'))
+            }
+            other => return error(ParseErrorKind::UnexpectedCharacter(other)),
+        };
+        Ok((token, span))
+    }
+}
+
+fn parse_hex_color(hex: &str) -> Option<Color> {
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| -> Option<f32> {
+        Some(u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()? as f32 / 255.0)
+    };
+    Some(Color::new(
+        channel(0)?,
+        channel(1)?,
+        channel(2)?,
+        if hex.len() == 8 { channel(3)? } else { 1.0 },
+    ))
+}
@@ -0,0 +1,425 @@
+//! The text level format, so levels can be made without recompiling.
+//!
+//! A level file is a list of directives. Whitespace and newlines don't matter, and `//` starts a
+//! comment that runs to the end of the line.
+//!
+//! ```text
+//! name "...during pride month?"       // shown in the level select, defaults to "Untitled"
+//! artist "Jane Remover (Leroy)"       // defaults to "Unknown"
+//! difficulty hard                     // easy, normal, hard or hardcore, defaults to normal
+//! preview 224                         // the beat the level select previews, defaults to 0
+//! seed per_session                    // a fixed seed up to 4294967295, per_session or
+//!                                     // per_attempt, defaults to per_session
+//! song builtin "during_pride_month"   // or `song "relative/path/to/song.ogg"`
+//! bpm 193                             // the tempo at beat 0
+//! start_time 2.4963                   // seconds of audio before beat 0, defaults to 0
+//! tempo_changes [[64, 96.5]]          // [beat, bpm] from then on, defaults to none
+//! time_signatures [[0, 4, 4]]         // [beat, beats per bar, note value], defaults to 4/4
+//! checkpoints [94, 216, 318, 396]     // defaults to none
+//! primary #ff0080                     // the palette, see below. Defaults to #ff0080
+//! accent #ffffff                      // defaults to #ffffff
+//! bullet #ff0080                      // defaults to #ff0080
+//! background #000000                  // defaults to #000000
+//! backdrop Grid { spacing 40 }        // see below, defaults to `Fill {}`
+//!
+//! at 32 SlamLaser {
+//!     start_pos (-50, 300)
+//!     end_pos (850, 300)
+//!     warn_time 4
+//!     thickness 100
+//!     shake 20
+//! }
+//! ```
+//!
+//! `song` and `bpm` are required. Every `at <beat> <Kind> { ... }` adds an obstacle at that beat,
+//! with each field being a name followed by a value. Positions are in arena coordinates, with
+//! (0, 0) at the top left and (800, 600) at the bottom right.
+//!
+//! Beats are quarter notes whatever the time signature, so a bar of 7/8 is 3.5 beats long. Time
+//! signatures only change the bar:beat:tick positions shown in the editor and practice setup.
+//!
+//! # Values
+//! - Numbers: `4`, `-0.5`, `1e3`
+//! - Vectors: `(x, y)`
+//! - Colors: `#rrggbb`, `#rrggbbaa`, `rgb(r, g, b)` or `rgba(r, g, b, a)` with channels from 0 to 1
+//! - Strings: `"..."`, with `\"`, `\\`, `\n`, `\t` and `\r` escapes
+//! - Booleans: `true` and `false`
+//! - Lists: `[a, b, c]`
+//! - Obstacles: `Kind { field value ... }`, for templates like a `Spawner`'s, and backdrops
+//!
+//! Fields marked as providers can change over the obstacle's lifetime. A plain value is constant,
+//! otherwise one of these can be used:
+//! - `constant(value)`
+//! - `offset(provider, beats)`, which delays another provider
+//! - `velocity(start, velocity)` for vectors, moving by `velocity` every beat
+//! - `linear(start, rate)` for numbers, changing by `rate` every beat
+//! - `aimed(start, speed)` for `Circle` positions, flying from `start` at where the player is
+//!   when it appears, moving `speed` every beat
+//! - `lead_aimed(start, speed)`, like `aimed` but at where the player is going to be
+//! - `homing(start, direction, speed, turn_rate)`, chasing the player while turning at most
+//!   `turn_rate` radians every beat, starting out going `direction`
+//! - `keyframes([beat, value, easing], ...)` for numbers, vectors and colors, easing from each
+//!   keyframe into the next and holding still outside of them. Easings are `linear` (the default),
+//!   `step`, or one of `quad`, `cubic`, `expo`, `back`, `elastic` and `bounce` followed by `_in`,
+//!   `_out` or `_in_out`
+//! - `switch(before, beat, after)`, changing to `after` from `beat` on, and `then(before, beat,
+//!   after)`, which also starts `after` from its beat 0 so providers play one after another
+//! - `loop(provider, period)`, repeating its first `period` beats, which has to be positive
+//! - `time_scale(provider, scale)`, playing `scale` times as fast
+//! - `blend(from, to, factor)` for numbers, vectors and colors, going from `from` at a factor of 0
+//!   to `to` at 1
+//! - `add(a, b)` and `multiply(a, b)` for numbers and vectors, where vectors can be multiplied by
+//!   numbers or other vectors
+//! - `clamp(provider, min, max)` for numbers and vectors
+//! - `jitter(provider, amount, rate, seed)` for numbers and vectors, shaking by up to `amount`
+//!   and jumping somewhere new `rate` times a beat, with a seed up to 4294967295
+//! - `sinebow(period)` for colors, going around the rainbow every `period` beats
+//! - `cycle([colors], [timings], flash, flash_time)` for colors, stepping to the next color at
+//!   each timing and flashing in from `flash` over `flash_time` beats, wrapping around at the end
+//!
+//! # Palettes
+//! Levels are drawn in a palette of four colors: `primary`, `accent`, `bullet` and `background`.
+//! Each is a color provider set with the directive of the same name, getting the level's beat.
+//! Every obstacle is drawn in the `primary` color unless it picks another with a `slot` field,
+//! like `slot bullet`. The `background` fills the arena behind everything, unless a backdrop
+//! covers it.
+//!
+//! # Backdrops
+//! The backdrop is drawn behind every obstacle, and never hurts. It's set with the `backdrop`
+//! directive and switched with `SetBackdrop`. Every field is an optional provider, getting the
+//! level's beat like the palette. Rotations are in radians clockwise, and scrolls are how far the
+//! pattern has moved, so `linear` and `velocity` make it scroll.
+//! - `Fill`: the palette's `background` color
+//! - `LinearGradient`: `from`, `to`, `angle` (the way it goes, from left to right at 0)
+//! - `RadialGradient`: `inner`, `outer`, `center`, `radius` (where it reaches `outer`)
+//! - `Stripes`: `a`, `b`, `width`, `rotation` (straight down at 0), `scroll` (a number)
+//! - `Checkerboard`: `a`, `b`, `size`, `rotation`, `scroll` (a vector)
+//! - `Grid`: `background`, `line`, `spacing`, `thickness`, `rotation`, `scroll` (a vector)
+//!
+//! # Obstacles
+//! Fields in brackets are optional, and default to the values in the `Default` implementation.
+//! - `SlamLaser`: \[`start_pos`\], \[`end_pos`\], \[`warn_time`\], \[`lifetime`\],
+//!   \[`leave_time`\], \[`thickness`\], \[`flash_time`\], \[`shake`\], \[`jerk`\]
+//! - `WidenLaser`: \[`start_pos`\], \[`end_pos`\], \[`warn_time`\], \[`grow_time`\],
+//!   \[`lifetime`\], \[`shrink_time`\], \[`thickness`\], \[`flash_time`\], \[`shake`\], \[`jerk`\]
+//! - `RotatingLaser`: \[`pivot`\], \[`angle`\], \[`length`\], \[`thickness`\] (all providers,
+//!   with `angle` in radians clockwise from pointing right), \[`warn_time`\], \[`lifetime`\],
+//!   \[`leave_time`\], \[`flash_time`\], \[`shake`\], \[`jerk`\]
+//! - `Bomb`: `start_position`, `end_position`, `lifetime`, `radius_per_beat`, `projectile_count`,
+//!   `projectile_radius`, `projectile_speed`, \[`aimed`\] (turns the burst towards the player)
+//! - `Ring`: \[`center`\], \[`inner_radius`\], \[`outer_radius`\] (all providers), \[`gap_count`\],
+//!   \[`gap_width`\] (radians), \[`rotation`\] (provider, turning the gaps), \[`warn_time`\],
+//!   \[`lifetime`\], \[`flash_time`\], \[`shake`\]
+//! - `Wall`: \[`from`\] (`left`, `right`, `top` or `bottom`), \[`speed`\] (provider),
+//!   \[`thickness`\], \[`gaps`\] (a list of `(center, width)`), \[`gap_spacing`\] (repeats a gap
+//!   of \[`gap_width`\] this far apart, starting from \[`gap_offset`\]), \[`warn_time`\]
+//! - `Circle`: `position` (provider), `radius`, \[`lifetime`\] (dies offscreen if left out)
+//! - `Rectangle`: `center`, `size`, `rotation` (all providers), `lifetime`, `warn_time`,
+//!   `leave_time`
+//! - `Shape`: `polygon` (`regular(sides)`, `star(points, inner_radius)` or `heart(points)`),
+//!   `position`, `rotation`, `scale` (all providers), `lifetime`, `warn_time`, `leave_time`
+//! - `Text`: `text` (a string, with `\n` starting a new line), \[`position`\], \[`rotation`\],
+//!   \[`scale`\] (all providers, with a line of text being as tall as the scale's y), \[`warn_time`\],
+//!   \[`lifetime`\], \[`leave_time`\], \[`flash_time`\], \[`shake`\]. Only the letters hurt
+//! - `RectangleGenerator`: `interval`, `lifetime`, `spawned_center`, `spawned_size`,
+//!   `spawned_rotation` (all providers), `spawned_lifetime`, `spawned_warn_time`
+//! - `Group`: \[`children`\] (a list of obstacles laid out around (0, 0), with
+//!   `at(beat, obstacle)` starting one later than the group), \[`position`\], \[`rotation`\],
+//!   \[`scale`\] (all providers, moving every child at once)
+//! - `Spawner`: `template` (an obstacle), `interval`, `count`, \[`lead_time`\] (how many beats
+//!   before each spawn it's added, so it can warn)
+//! - `SetPalette`: \[`primary`\], \[`accent`\], \[`bullet`\], \[`background`\] (all providers,
+//!   swapping just the slots that are given), \[`duration`\], \[`easing`\] (how it blends from the
+//!   old colors, instantly by default)
+//! - `SetForeground`, `SetBackground`: `color` (provider), the same as a `SetPalette` that only
+//!   sets `primary` or `background`
+//! - `SetBackdrop`: \[`backdrop`\] (a backdrop like `Stripes { width 20 }`, going back to
+//!   `Fill {}` if left out)
+//! - `Shake`: `amount`
+//! - `Camera`: \[`zoom`\], \[`rotation`\], \[`pan`\] (all providers, with `pan` being the point
+//!   the camera looks at, moved with the group inside one), \[`duration`\], \[`blend_time`\],
+//!   \[`easing`\] (how it blends in from and back out to the normal view at either end). Player
+//!   controls turn with the camera
+//!
+//! # Spawners
+//! A `Spawner` adds a copy of its template every `interval` beats. Inside the template, these get
+//! filled in separately for every spawn, and can be used anywhere a value can:
+//! - `by_index(provider)`, the provider's value at the spawn's index, counting from 0
+//! - `by_beat(provider)`, the provider's value at the beat of the spawn, relative to the spawner
+//! - `random(min, max)`, a random number or vector between the two, decided by the level's seed
+//!
+//! ```text
+//! at 44 Spawner {
+//!     interval 0.5
+//!     count 8
+//!     template SlamLaser {
+//!         start_pos by_index(velocity((100, -50), (80, 0)))
+//!         end_pos by_index(velocity((100, 650), (80, 0)))
+//!         thickness random(20, 60)
+//!     }
+//! }
+//! ```
+
+use std::{fs, io, path::Path};
+
+use thiserror::Error;
+
+use crate::{
+    backdrop::DynBackdrop,
+    level::{Level, LevelBuilder, LevelInfo, Song, SongSource},
+    obstacle::Obstacle,
+    palette::Palette,
+    tempo::TempoMap,
+};
+
+mod behaviours;
+mod lexer;
+mod parser;
+mod value;
+mod writer;
+
+pub use behaviours::BehaviourDefinition;
+pub use lexer::Span;
+pub use value::{Value, ValueError};
+pub use writer::{write_level, LevelWriteError};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseErrorKind {
+    #[error("unexpected character `{0}`")]
+    UnexpectedCharacter(char),
+    #[error("unterminated string")]
+    UnterminatedString,
+    #[error("invalid escape `\\{0}`")]
+    InvalidEscape(char),
+    #[error("invalid number `{0}`")]
+    InvalidNumber(String),
+    #[error("invalid color `#{0}`")]
+    InvalidColor(String),
+    #[error("expected {expected}, found {found}")]
+    Expected {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("unknown directive `{0}`")]
+    UnknownDirective(String),
+    #[error("missing `{0}` directive")]
+    MissingDirective(&'static str),
+    #[error("unknown builtin song `{0}`")]
+    UnknownSong(String),
+    #[error("unknown obstacle kind `{0}`")]
+    UnknownBehaviour(String),
+    #[error("unknown backdrop kind `{0}`")]
+    UnknownBackdrop(String),
+    #[error("unknown field `{field}` for `{kind}`")]
+    UnknownField { kind: String, field: String },
+    #[error("missing field `{field}` for `{kind}`")]
+    MissingField { kind: String, field: String },
+    #[error("duplicate field `{0}`")]
+    DuplicateField(String),
+    #[error("invalid value for `{what}`: {error}")]
+    InvalidValue { what: String, error: ValueError },
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("{}{kind}", span.map_or(String::new(), |span| format!("{span}: ")))]
+pub struct LevelParseError {
+    /// Where in the file it went wrong. Obstacles that weren't read from a file don't have this.
+    pub span: Option<Span>,
+    pub kind: ParseErrorKind,
+}
+impl LevelParseError {
+    pub fn new(span: Span, kind: ParseErrorKind) -> Self {
+        Self {
+            span: Some(span),
+            kind,
+        }
+    }
+    pub fn without_span(self) -> Self {
+        Self { span: None, ..self }
+    }
+    pub fn value(span: Span, what: &str, error: ValueError) -> Self {
+        Self::new(
+            span,
+            ParseErrorKind::InvalidValue {
+                what: what.to_owned(),
+                error,
+            },
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LevelLoadError {
+    #[error("couldn't read level file")]
+    Io(#[from] io::Error),
+    #[error("couldn't parse level file: {0}")]
+    Parse(#[from] LevelParseError),
+    #[error("couldn't read song `{}`", .0.display())]
+    Song(std::path::PathBuf, #[source] io::Error),
+}
+
+/// A parsed level file whose song hasn't been loaded yet.
+pub struct LevelFile {
+    pub info: LevelInfo,
+    pub song: SongSource,
+    pub tempo: TempoMap,
+    pub checkpoints: Vec<f64>,
+    pub palette: Palette,
+    pub backdrop: DynBackdrop,
+    pub obstacles: Vec<Obstacle>,
+}
+impl LevelFile {
+    /// Parses the source of a level file. Relative song paths are resolved against `base_directory`.
+    pub fn parse(source: &str, base_directory: &Path) -> Result<Self, LevelParseError> {
+        parser::Parser::new(source)?.level(base_directory)
+    }
+    /// Loads the song this level file refers to.
+    pub fn load_song(&self) -> Result<Song, LevelLoadError> {
+        match &self.song {
+            SongSource::Builtin(name) => {
+                Ok(Song::builtin(name).expect("builtin songs are checked while parsing"))
+            }
+            SongSource::File(path) => {
+                Song::load(path).map_err(|error| LevelLoadError::Song(path.clone(), error))
+            }
+        }
+    }
+    /// Loads the song and turns this into a playable level, built with `seed`.
+    pub fn build(self, seed: u64) -> Result<Level, LevelLoadError> {
+        let song = self.load_song()?;
+        let mut builder = LevelBuilder::new(seed);
+        builder.info(self.info);
+        builder.palette(self.palette);
+        builder.backdrop(self.backdrop);
+        for obstacle in self.obstacles {
+            builder.obstacle(obstacle);
+        }
+        Ok(builder.build(song, self.tempo, self.checkpoints))
+    }
+}
+
+/// Reads and parses a level file, without loading its song.
+pub fn read(path: impl AsRef<Path>) -> Result<LevelFile, LevelLoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base_directory = path.parent().unwrap_or(Path::new(""));
+    Ok(LevelFile::parse(&source, base_directory)?)
+}
+/// Reads, parses and builds a level file.
+pub fn load(path: impl AsRef<Path>, seed: u64) -> Result<Level, LevelLoadError> {
+    read(path)?.build(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        level::SeedPolicy,
+        provider::{Constant, Provider, ProviderExt},
+    };
+
+    use super::*;
+
+    fn write(level: &Level) -> String {
+        let mut out = vec![];
+        write_level(level, &mut out, Path::new("levels")).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn parse_error(source: &str) -> LevelParseError {
+        match LevelFile::parse(source, Path::new("")) {
+            Ok(_) => panic!("{source:?} should fail to parse"),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn sample_writes_itself_back_out() {
+        let written = write(&load("levels/sample.epar", 0).unwrap());
+        let reread = LevelFile::parse(&written, Path::new("levels"))
+            .unwrap()
+            .build(0)
+            .unwrap();
+        assert_eq!(write(&reread), written);
+    }
+
+    #[test]
+    fn strings_survive_a_round_trip() {
+        let mut level = load("levels/sample.epar", 0).unwrap();
+        let name = "tab\there, \"quotes\", back\\slash\r\nand e\u{301} \0";
+        level.info.name = name.to_owned();
+        let reread = LevelFile::parse(&write(&level), Path::new("levels")).unwrap();
+        assert_eq!(reread.info.name, name);
+    }
+
+    #[test]
+    fn seeds_fit_in_32_bits() {
+        let seed = |seed: &str| {
+            let source = format!("song builtin \"during_pride_month\" bpm 120 seed {seed}");
+            LevelFile::parse(&source, Path::new("")).map(|file| file.info.seed_policy)
+        };
+        assert_eq!(seed("4294967295"), Ok(SeedPolicy::Fixed(u32::MAX)));
+        assert!(seed("4294967296").is_err());
+        assert!(seed("1.5").is_err());
+    }
+
+    #[test]
+    fn provider_arguments_are_checked() {
+        let jitter = Constant(1.0)
+            .jitter(2.0, 4.0, u32::MAX)
+            .serialize()
+            .unwrap();
+        let reread = value::provider::<f64>(&jitter).unwrap();
+        assert_eq!(reread.serialize(), Some(jitter));
+        let looped = |period: f64| {
+            let value = Value::call("loop", [Value::Number(1.0), Value::Number(period)]);
+            value::provider::<f64>(&value).map(|provider| provider.get(0.5))
+        };
+        assert_eq!(looped(1.0), Ok(1.0));
+        assert_eq!(looped(0.0).err(), Some(ValueError::OutOfRange(0.0)));
+        assert_eq!(looped(-1.0).err(), Some(ValueError::OutOfRange(-1.0)));
+    }
+
+    #[test]
+    fn bpm_has_to_be_positive() {
+        let error = parse_error("song builtin \"during_pride_month\"\nbpm 0");
+        assert_eq!(error.span, Some(Span { line: 2, column: 5 }));
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::InvalidValue {
+                what: "bpm".to_owned(),
+                error: ValueError::OutOfRange(0.0)
+            }
+        );
+    }
+
+    #[test]
+    fn built_definitions_have_no_position() {
+        let error = BehaviourDefinition::new("Shake").build(0.0).err().unwrap();
+        assert_eq!(error.span, None);
+        assert_eq!(error.to_string(), "missing field `amount` for `Shake`");
+    }
+
+    #[test]
+    fn errors_point_at_line_and_column() {
+        let source = "song builtin \"during_pride_month\"\nbpm 120\n\nat 4 Nope {}";
+        let error = parse_error(source);
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::UnknownBehaviour("Nope".to_owned())
+        );
+        assert_eq!(error.to_string(), "4:6: unknown obstacle kind `Nope`");
+
+        let source =
+            "song builtin \"during_pride_month\"\nbpm 120\nat 4 Shake {\n  amount 1\n  nope 2\n}";
+        assert_eq!(parse_error(source).span, Some(Span { line: 5, column: 3 }));
+
+        let error = parse_error("name \"a\\q\"");
+        assert_eq!(
+            (error.span, error.kind),
+            (
+                Some(Span { line: 1, column: 6 }),
+                ParseErrorKind::InvalidEscape('q')
+            )
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use jut::extensions::Boxed;
+
+use crate::{
+    backdrop::{DynBackdrop, Fill},
+    level::{LevelInfo, SongSource},
+    obstacle::Obstacle,
+    palette::{Palette, Slot},
+    provider::Provider,
+    res::songs,
+    tempo::{SignatureChange, TempoChange, TempoMap},
+};
+
+use super::{
+    behaviours::{self, BehaviourDefinition, Field, Fields},
+    lexer::{Lexer, Span, Token},
+    value::{self, FromValue, Value, ValueError},
+    LevelFile, LevelParseError, ParseErrorKind,
+};
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    current: Token,
+    current_span: Span,
+}
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Result<Self, LevelParseError> {
+        let mut lexer = Lexer::new(source);
+        let (current, current_span) = lexer.next_token()?;
+        Ok(Self {
+            lexer,
+            current,
+            current_span,
+        })
+    }
+    fn advance(&mut self) -> Result<(Token, Span), LevelParseError> {
+        let (next, next_span) = self.lexer.next_token()?;
+        let current = std::mem::replace(&mut self.current, next);
+        let span = std::mem::replace(&mut self.current_span, next_span);
+        Ok((current, span))
+    }
+    fn unexpected(&self, expected: &'static str) -> LevelParseError {
+        LevelParseError::new(
+            self.current_span,
+            ParseErrorKind::Expected {
+                expected,
+                found: self.current.to_string(),
+            },
+        )
+    }
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<Span, LevelParseError> {
+        if self.current != token {
+            return Err(self.unexpected(expected));
+        }
+        Ok(self.advance()?.1)
+    }
+    fn ident(&mut self) -> Result<(String, Span), LevelParseError> {
+        match self.current {
+            Token::Ident(_) => match self.advance()? {
+                (Token::Ident(ident), span) => Ok((ident, span)),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+    /// Parses a value and converts it, reporting errors at the value's start.
+    fn typed<T: FromValue>(&mut self, what: &str) -> Result<T, LevelParseError> {
+        let span = self.current_span;
+        let value = self.value()?;
+        T::from_value(&value).map_err(|error| LevelParseError::value(span, what, error))
+    }
+    fn provider<T: FromValue>(
+        &mut self,
+        what: &str,
+    ) -> Result<Box<dyn Provider<T>>, LevelParseError> {
+        let span = self.current_span;
+        let value = self.value()?;
+        value::provider(&value).map_err(|error| LevelParseError::value(span, what, error))
+    }
+    /// Parses comma separated values up to and including `end`.
+    fn sequence(
+        &mut self,
+        end: Token,
+        expected: &'static str,
+    ) -> Result<Vec<Value>, LevelParseError> {
+        let mut values = vec![];
+        while self.current != end {
+            values.push(self.value()?);
+            if self.current == Token::Comma {
+                self.advance()?;
+            } else if self.current != end {
+                return Err(self.unexpected(expected));
+            }
+        }
+        self.advance()?;
+        Ok(values)
+    }
+    pub fn value(&mut self) -> Result<Value, LevelParseError> {
+        let (token, span) = self.advance()?;
+        Ok(match token {
+            Token::Number(number) => Value::Number(number),
+            Token::String(string) => Value::String(string),
+            Token::Color(color) => Value::Color(color),
+            Token::LBracket => Value::List(self.sequence(Token::RBracket, "`,` or `]`")?),
+            Token::LParen => {
+                let x = self.value()?;
+                self.expect(Token::Comma, "`,`")?;
+                let y = self.value()?;
+                self.expect(Token::RParen, "`)`")?;
+                match (x, y) {
+                    (Value::Number(x), Value::Number(y)) => Value::Vector((x, y).into()),
+                    _ => {
+                        return Err(LevelParseError::new(
+                            span,
+                            ParseErrorKind::Expected {
+                                expected: "a vector of two numbers",
+                                found: "something else".to_owned(),
+                            },
+                        ))
+                    }
+                }
+            }
+            Token::Ident(ident) => {
+                if self.current == Token::LParen {
+                    self.advance()?;
+                    Value::Call(ident, self.sequence(Token::RParen, "`,` or `)`")?)
+                } else if self.current == Token::LBrace {
+                    let fields = self.fields()?;
+                    Value::Obstacle(BehaviourDefinition {
+                        kind: ident,
+                        fields: fields
+                            .into_iter()
+                            .map(|field| (field.name, field.value))
+                            .collect(),
+                    })
+                } else {
+                    Value::Ident(ident)
+                }
+            }
+            other => {
+                return Err(LevelParseError::new(
+                    span,
+                    ParseErrorKind::Expected {
+                        expected: "a value",
+                        found: other.to_string(),
+                    },
+                ))
+            }
+        })
+    }
+    /// Parses the fields of an obstacle, braces included.
+    fn fields(&mut self) -> Result<Vec<Field>, LevelParseError> {
+        self.expect(Token::LBrace, "`{`")?;
+        let mut fields: Vec<Field> = vec![];
+        while self.current != Token::RBrace {
+            let (name, span) = self.ident()?;
+            if fields.iter().any(|field| field.name == name) {
+                return Err(LevelParseError::new(
+                    span,
+                    ParseErrorKind::DuplicateField(name),
+                ));
+            }
+            let value = self.value()?;
+            fields.push(Field { name, value, span });
+        }
+        self.advance()?;
+        Ok(fields)
+    }
+    fn obstacle(&mut self) -> Result<Obstacle, LevelParseError> {
+        let offset = self.typed::<f64>("the beat")?;
+        let (kind, kind_span) = self.ident()?;
+        let fields = self.fields()?;
+        behaviours::parse_obstacle(offset, Fields::new(kind, kind_span, fields))
+    }
+    fn backdrop(&mut self) -> Result<DynBackdrop, LevelParseError> {
+        let (kind, kind_span) = self.ident()?;
+        let fields = self.fields()?;
+        behaviours::parse_backdrop(Fields::new(kind, kind_span, fields))
+    }
+    /// Parses an entire level file. Relative song paths are resolved against `base_directory`.
+    pub fn level(mut self, base_directory: &Path) -> Result<LevelFile, LevelParseError> {
+        let mut info = LevelInfo::default();
+        let mut song = None;
+        let mut bpm = None;
+        let mut start_time = None;
+        let mut tempo_changes: Vec<TempoChange> = vec![];
+        let mut time_signatures: Vec<SignatureChange> = vec![];
+        let mut checkpoints = vec![];
+        let mut palette = Palette::default();
+        let mut backdrop: DynBackdrop = Fill.boxed();
+        let mut obstacles = vec![];
+
+        while self.current != Token::Eof {
+            let (directive, span) = self.ident()?;
+            match directive.as_str() {
+                "name" => info.name = self.typed("name")?,
+                "artist" => info.artist = self.typed("artist")?,
+                "difficulty" => info.difficulty = self.typed("difficulty")?,
+                "preview" => info.preview_beat = self.typed("preview")?,
+                "seed" => info.seed_policy = self.typed("seed")?,
+                "song" => {
+                    song = Some(if self.current == Token::Ident("builtin".to_owned()) {
+                        self.advance()?;
+                        let name_span = self.current_span;
+                        let name = self.typed::<String>("the song name")?;
+                        let Some(&(name, _)) =
+                            songs::BUILTIN.iter().find(|(builtin, _)| *builtin == name)
+                        else {
+                            return Err(LevelParseError::new(
+                                name_span,
+                                ParseErrorKind::UnknownSong(name),
+                            ));
+                        };
+                        SongSource::Builtin(name)
+                    } else {
+                        SongSource::File(
+                            base_directory.join(self.typed::<String>("the song path")?),
+                        )
+                    });
+                }
+                "bpm" => {
+                    let span = self.current_span;
+                    let value: f64 = self.typed("bpm")?;
+                    if value <= 0.0 {
+                        return Err(LevelParseError::value(
+                            span,
+                            "bpm",
+                            ValueError::OutOfRange(value),
+                        ));
+                    }
+                    bpm = Some(value);
+                }
+                "start_time" => start_time = Some(self.typed("start_time")?),
+                "checkpoints" => checkpoints = self.typed("checkpoints")?,
+                "tempo_changes" => tempo_changes = self.typed("tempo_changes")?,
+                "time_signatures" => time_signatures = self.typed("time_signatures")?,
+                // the primary slot used to be the only foreground color
+                "primary" | "foreground" => {
+                    palette.set(Slot::Primary, self.provider("primary")?);
+                }
+                "accent" => {
+                    palette.set(Slot::Accent, self.provider("accent")?);
+                }
+                "bullet" => {
+                    palette.set(Slot::Bullet, self.provider("bullet")?);
+                }
+                "background" => {
+                    palette.set(Slot::Background, self.provider("background")?);
+                }
+                "backdrop" => backdrop = self.backdrop()?,
+                "at" => obstacles.push(self.obstacle()?),
+                _ => {
+                    return Err(LevelParseError::new(
+                        span,
+                        ParseErrorKind::UnknownDirective(directive),
+                    ))
+                }
+            }
+        }
+        let end = self.current_span;
+        let missing =
+            |directive| LevelParseError::new(end, ParseErrorKind::MissingDirective(directive));
+        let song = song.ok_or_else(|| missing("song"))?;
+        let mut tempo = TempoMap::new(
+            bpm.ok_or_else(|| missing("bpm"))?,
+            start_time.unwrap_or(0.0),
+        );
+        for change in tempo_changes {
+            tempo = tempo.change(change.beat, change.bpm);
+        }
+        for change in time_signatures {
+            tempo = tempo.signature(change.beat, change.signature);
+        }
+        Ok(LevelFile {
+            info,
+            song,
+            tempo,
+            checkpoints,
+            palette,
+            backdrop,
+            obstacles,
+        })
+    }
+}
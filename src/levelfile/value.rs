@@ -0,0 +1,745 @@
+use std::{
+    any::Any,
+    fmt,
+    ops::{Add, Mul},
+};
+
+use jut::extensions::Boxed;
+use macroquad::{
+    color::Color,
+    math::{DVec2, Vec2},
+};
+use rand::Rng;
+use thiserror::Error;
+
+use crate::{
+    easing::Easing,
+    level::{Difficulty, SeedPolicy},
+    obstacle::{Edge, Spawn},
+    palette::{Cycle, Sinebow, Slot},
+    polygon::presets::Preset,
+    provider::{
+        combinators::{Clamp, Jitter},
+        Aimed, Constant, Homing, Keyframes, Linear, Provider, ProviderExt, ProviderOffset,
+        Velocity,
+    },
+    tempo::{SignatureChange, TempoChange, TimeSignature},
+    transform::Lerp,
+};
+
+use super::{lexer::Quoted, BehaviourDefinition};
+
+/// A single value in a level file, before it gets turned into a field of an obstacle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Vector(DVec2),
+    Color(Color),
+    String(String),
+    /// A bare word, like `true` or `false`.
+    Ident(String),
+    List(Vec<Value>),
+    /// `name(arg, arg, ...)`, used for colors and non-constant providers.
+    Call(String, Vec<Value>),
+    /// `Kind { field value ... }`, an obstacle that isn't placed by itself, like a spawner's template.
+    Obstacle(BehaviourDefinition),
+}
+impl Value {
+    pub fn call(name: &str, args: impl IntoIterator<Item = Value>) -> Self {
+        Self::Call(name.to_owned(), args.into_iter().collect())
+    }
+    /// Converts anything with a level file representation into a [`Value`].
+    /// Used by generic providers like [`Constant`] that don't know their type.
+    pub fn from_any(any: &dyn Any) -> Option<Self> {
+        if let Some(&number) = any.downcast_ref::<f64>() {
+            Some(number.into())
+        } else if let Some(&vector) = any.downcast_ref::<DVec2>() {
+            Some(vector.into())
+        } else if let Some(&vector) = any.downcast_ref::<Vec2>() {
+            Some(vector.into())
+        } else if let Some(&color) = any.downcast_ref::<Color>() {
+            Some(color.into())
+        } else if let Some(&boolean) = any.downcast_ref::<bool>() {
+            Some(boolean.into())
+        } else {
+            None
+        }
+    }
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Vector(_) => "a vector",
+            Value::Color(_) => "a color",
+            Value::String(_) => "a string",
+            Value::Ident(_) => "a word",
+            Value::List(_) => "a list",
+            Value::Call(..) => "a call",
+            Value::Obstacle(_) => "an obstacle",
+        }
+    }
+    fn mismatch(&self, expected: &'static str) -> ValueError {
+        ValueError::Mismatch {
+            expected,
+            found: self.kind_name(),
+        }
+    }
+}
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<DVec2> for Value {
+    fn from(value: DVec2) -> Self {
+        Self::Vector(value)
+    }
+}
+impl From<Vec2> for Value {
+    fn from(value: Vec2) -> Self {
+        Self::Vector(value.as_dvec2())
+    }
+}
+impl From<Color> for Value {
+    fn from(value: Color) -> Self {
+        Self::Color(value)
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Ident(if value { "true" } else { "false" }.to_owned())
+    }
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+impl From<Edge> for Value {
+    fn from(value: Edge) -> Self {
+        Self::Ident(value.name().to_owned())
+    }
+}
+impl From<Slot> for Value {
+    fn from(value: Slot) -> Self {
+        Self::Ident(value.name().to_owned())
+    }
+}
+impl From<Easing> for Value {
+    fn from(value: Easing) -> Self {
+        Self::Ident(value.name())
+    }
+}
+impl From<Preset> for Value {
+    fn from(value: Preset) -> Self {
+        match value {
+            Preset::Regular { sides } => Value::call("regular", [sides.into()]),
+            Preset::Star {
+                points,
+                inner_radius,
+            } => Value::call("star", [points.into(), inner_radius.into()]),
+            Preset::Heart { points } => Value::call("heart", [points.into()]),
+        }
+    }
+}
+impl From<TempoChange> for Value {
+    fn from(value: TempoChange) -> Self {
+        Self::List(vec![value.beat.into(), value.bpm.into()])
+    }
+}
+impl From<SignatureChange> for Value {
+    fn from(value: SignatureChange) -> Self {
+        let TimeSignature {
+            beats_per_bar,
+            note_value,
+        } = value.signature;
+        Self::List(vec![
+            value.beat.into(),
+            (beats_per_bar as usize).into(),
+            (note_value as usize).into(),
+        ])
+    }
+}
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Self::List(value.into_iter().map(Into::into).collect())
+    }
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{number}"),
+            Value::Vector(vector) => write!(f, "({}, {})", vector.x, vector.y),
+            // written out as floats, since hex colors can't round trip
+            Value::Color(color) => {
+                write!(
+                    f,
+                    "rgba({}, {}, {}, {})",
+                    color.r, color.g, color.b, color.a
+                )
+            }
+            Value::String(string) => write!(f, "{}", Quoted(string)),
+            Value::Ident(ident) => write!(f, "{ident}"),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Call(name, args) => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Obstacle(definition) => {
+                write!(f, "{} {{", definition.kind)?;
+                for (name, value) in &definition.fields {
+                    write!(f, " {name} {value}")?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ValueError {
+    #[error("expected {expected}, found {found}")]
+    Mismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("`{name}` takes {expected} argument(s), found {found}")]
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("unknown provider `{0}`")]
+    UnknownProvider(String),
+    #[error("{0} is out of range")]
+    OutOfRange(f64),
+}
+
+/// Types that can be read out of a level file [`Value`].
+pub trait FromValue: Sized + Clone + 'static {
+    fn from_value(value: &Value) -> Result<Self, ValueError>;
+    /// Parses providers that only make sense for this type, like `velocity` for vectors.
+    #[allow(unused_variables)]
+    fn provider_call(
+        name: &str,
+        args: &[Value],
+    ) -> Option<Result<Box<dyn Provider<Self>>, ValueError>> {
+        None
+    }
+}
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Number(number) => Ok(*number),
+            other => Err(other.mismatch("a number")),
+        }
+    }
+    fn provider_call(
+        name: &str,
+        args: &[Value],
+    ) -> Option<Result<Box<dyn Provider<Self>>, ValueError>> {
+        match name {
+            "linear" => Some(arguments::<2>(name, args).and_then(|[start, rate]| {
+                Ok(
+                    Linear::new(f64::from_value(start)?, f64::from_value(rate)?).boxed()
+                        as Box<dyn Provider<f64>>,
+                )
+            })),
+            "keyframes" => Some(keyframes(args)),
+            _ => arithmetic(name, args),
+        }
+    }
+}
+impl FromValue for usize {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        let number = f64::from_value(value)?;
+        if number < 0.0 || number.fract() != 0.0 || number > u32::MAX as f64 {
+            return Err(ValueError::OutOfRange(number));
+        }
+        Ok(number as usize)
+    }
+}
+impl FromValue for u32 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        Ok(usize::from_value(value)? as u32)
+    }
+}
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Ident(ident) if ident == "true" => Ok(true),
+            Value::Ident(ident) if ident == "false" => Ok(false),
+            other => Err(other.mismatch("`true` or `false`")),
+        }
+    }
+}
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::String(string) => Ok(string.clone()),
+            other => Err(other.mismatch("a string")),
+        }
+    }
+}
+impl FromValue for DVec2 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Vector(vector) => Ok(*vector),
+            other => Err(other.mismatch("a vector")),
+        }
+    }
+    fn provider_call(
+        name: &str,
+        args: &[Value],
+    ) -> Option<Result<Box<dyn Provider<Self>>, ValueError>> {
+        match name {
+            "velocity" => Some(arguments::<2>(name, args).and_then(|[start, velocity]| {
+                Ok(
+                    Velocity::new(DVec2::from_value(start)?, DVec2::from_value(velocity)?).boxed()
+                        as Box<dyn Provider<DVec2>>,
+                )
+            })),
+            "aimed" | "lead_aimed" => Some(arguments::<2>(name, args).and_then(|[start, speed]| {
+                let (start, speed) = (DVec2::from_value(start)?, f64::from_value(speed)?);
+                Ok(if name == "aimed" {
+                    Aimed::new(start, speed)
+                } else {
+                    Aimed::lead(start, speed)
+                }
+                .boxed() as Box<dyn Provider<DVec2>>)
+            })),
+            "homing" => Some(arguments::<4>(name, args).and_then(
+                |[start, direction, speed, turn_rate]| {
+                    Ok(Homing::new(
+                        DVec2::from_value(start)?,
+                        DVec2::from_value(direction)?,
+                        f64::from_value(speed)?,
+                        f64::from_value(turn_rate)?,
+                    )
+                    .boxed() as Box<dyn Provider<DVec2>>)
+                },
+            )),
+            "keyframes" => Some(keyframes(args)),
+            // vectors can also be multiplied by other vectors, one component at a time
+            "multiply" => Some(arguments::<2>(name, args).and_then(|[value, by]| {
+                let value = provider::<DVec2>(value)?;
+                Ok(match provider::<f64>(by) {
+                    Ok(by) => value.times(by).boxed() as Box<dyn Provider<DVec2>>,
+                    Err(_) => value.times(provider::<DVec2>(by)?).boxed(),
+                })
+            })),
+            _ => arithmetic(name, args),
+        }
+    }
+}
+impl FromValue for Vec2 {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        DVec2::from_value(value).map(|vector| vector.as_vec2())
+    }
+}
+impl FromValue for Color {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Color(color) => Ok(*color),
+            Value::Call(name, args) if name == "rgba" => {
+                let [r, g, b, a] = arguments::<4>(name, args)?;
+                Ok(Color::new(
+                    f64::from_value(r)? as f32,
+                    f64::from_value(g)? as f32,
+                    f64::from_value(b)? as f32,
+                    f64::from_value(a)? as f32,
+                ))
+            }
+            Value::Call(name, args) if name == "rgb" => {
+                let [r, g, b] = arguments::<3>(name, args)?;
+                Ok(Color::new(
+                    f64::from_value(r)? as f32,
+                    f64::from_value(g)? as f32,
+                    f64::from_value(b)? as f32,
+                    1.0,
+                ))
+            }
+            other => Err(other.mismatch("a color")),
+        }
+    }
+    fn provider_call(
+        name: &str,
+        args: &[Value],
+    ) -> Option<Result<Box<dyn Provider<Self>>, ValueError>> {
+        match name {
+            "keyframes" => Some(keyframes(args)),
+            "blend" => Some(blend(args)),
+            "sinebow" => Some(arguments::<1>(name, args).and_then(|[period]| {
+                Ok(Sinebow {
+                    period: f64::from_value(period)?,
+                }
+                .boxed() as Box<dyn Provider<Color>>)
+            })),
+            "cycle" => Some(cycle(args)),
+            _ => None,
+        }
+    }
+}
+impl FromValue for Difficulty {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Ident(ident) => Difficulty::ALL
+                .into_iter()
+                .find(|difficulty| difficulty.name() == ident)
+                .ok_or_else(|| value.mismatch("`easy`, `normal`, `hard` or `hardcore`")),
+            other => Err(other.mismatch("`easy`, `normal`, `hard` or `hardcore`")),
+        }
+    }
+}
+impl FromValue for SeedPolicy {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        const EXPECTED: &str = "a seed, `per_session` or `per_attempt`";
+        match value {
+            Value::Number(_) => Ok(SeedPolicy::Fixed(u32::from_value(value)?)),
+            Value::Ident(ident) if ident == "per_session" => Ok(SeedPolicy::PerSession),
+            Value::Ident(ident) if ident == "per_attempt" => Ok(SeedPolicy::PerAttempt),
+            other => Err(other.mismatch(EXPECTED)),
+        }
+    }
+}
+impl FromValue for Edge {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        const EXPECTED: &str = "`left`, `right`, `top` or `bottom`";
+        match value {
+            Value::Ident(ident) => Edge::ALL
+                .into_iter()
+                .find(|edge| edge.name() == ident)
+                .ok_or_else(|| value.mismatch(EXPECTED)),
+            other => Err(other.mismatch(EXPECTED)),
+        }
+    }
+}
+impl FromValue for Slot {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        const EXPECTED: &str = "`primary`, `accent`, `bullet` or `background`";
+        match value {
+            Value::Ident(ident) => Slot::from_name(ident).ok_or_else(|| value.mismatch(EXPECTED)),
+            other => Err(other.mismatch(EXPECTED)),
+        }
+    }
+}
+impl FromValue for Easing {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        const EXPECTED: &str = "an easing, like `linear`, `step` or `quad_in_out`";
+        match value {
+            Value::Ident(ident) => Easing::from_name(ident).ok_or_else(|| value.mismatch(EXPECTED)),
+            other => Err(other.mismatch(EXPECTED)),
+        }
+    }
+}
+impl FromValue for Preset {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        const EXPECTED: &str = "`regular(sides)`, `star(points, inner_radius)` or `heart(points)`";
+        let preset = match value {
+            Value::Call(name, args) if name == "regular" => {
+                let [sides] = arguments::<1>(name, args)?;
+                Preset::Regular {
+                    sides: usize::from_value(sides)?,
+                }
+            }
+            Value::Call(name, args) if name == "star" => {
+                let [points, inner_radius] = arguments::<2>(name, args)?;
+                Preset::Star {
+                    points: usize::from_value(points)?,
+                    inner_radius: f64::from_value(inner_radius)?,
+                }
+            }
+            Value::Call(name, args) if name == "heart" => {
+                let [points] = arguments::<1>(name, args)?;
+                Preset::Heart {
+                    points: usize::from_value(points)?,
+                }
+            }
+            other => return Err(other.mismatch(EXPECTED)),
+        };
+        Ok(preset)
+    }
+}
+impl FromValue for BehaviourDefinition {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::Obstacle(definition) => Ok(definition.clone()),
+            other => Err(other.mismatch("an obstacle")),
+        }
+    }
+}
+impl FromValue for TempoChange {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::List(fields) if fields.len() == 2 => {
+                let bpm = f64::from_value(&fields[1])?;
+                if bpm <= 0.0 {
+                    return Err(ValueError::OutOfRange(bpm));
+                }
+                Ok(TempoChange {
+                    beat: f64::from_value(&fields[0])?,
+                    bpm,
+                })
+            }
+            other => Err(other.mismatch("`[beat, bpm]`")),
+        }
+    }
+}
+impl FromValue for SignatureChange {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::List(fields) if fields.len() == 3 => {
+                let count = |value| match usize::from_value(value)? {
+                    0 => Err(ValueError::OutOfRange(0.0)),
+                    count => Ok(count as u32),
+                };
+                Ok(SignatureChange {
+                    beat: f64::from_value(&fields[0])?,
+                    signature: TimeSignature {
+                        beats_per_bar: count(&fields[1])?,
+                        note_value: count(&fields[2])?,
+                    },
+                })
+            }
+            other => Err(other.mismatch("`[beat, beats_per_bar, note_value]`")),
+        }
+    }
+}
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, ValueError> {
+        match value {
+            Value::List(values) => values.iter().map(T::from_value).collect(),
+            other => Err(other.mismatch("a list")),
+        }
+    }
+}
+
+/// Checks the argument count of a call and splits it into an array.
+pub fn arguments<'a, const N: usize>(
+    name: &str,
+    args: &'a [Value],
+) -> Result<&'a [Value; N], ValueError> {
+    args.try_into().map_err(|_| ValueError::ArgumentCount {
+        name: name.to_owned(),
+        expected: N,
+        found: args.len(),
+    })
+}
+
+/// Reads `keyframes([beat, value, easing], ...)`, with the easing being optional and linear by default.
+fn keyframes<T: FromValue + Lerp>(args: &[Value]) -> Result<Box<dyn Provider<T>>, ValueError> {
+    let mut keyframes: Option<Keyframes<T>> = None;
+    for key in args {
+        let (beat, value, easing) = match key {
+            Value::List(fields) if fields.len() == 2 || fields.len() == 3 => (
+                f64::from_value(&fields[0])?,
+                T::from_value(&fields[1])?,
+                fields
+                    .get(2)
+                    .map(Easing::from_value)
+                    .transpose()?
+                    .unwrap_or_default(),
+            ),
+            other => return Err(other.mismatch("`[beat, value, easing]`")),
+        };
+        keyframes = Some(match keyframes {
+            Some(keyframes) => keyframes.key(beat, value, easing),
+            None => Keyframes::new(beat, value, easing),
+        });
+    }
+    keyframes
+        .map(|keyframes| keyframes.boxed() as Box<dyn Provider<T>>)
+        .ok_or(ValueError::ArgumentCount {
+            name: "keyframes".to_owned(),
+            expected: 1,
+            found: 0,
+        })
+}
+
+/// Reads `cycle(colors, timings, flash, flash_time)`, which needs at least one color.
+fn cycle(args: &[Value]) -> Result<Box<dyn Provider<Color>>, ValueError> {
+    let [colors, timings, flash, flash_time] = arguments::<4>("cycle", args)?;
+    let colors = Vec::<Color>::from_value(colors)?;
+    if colors.is_empty() {
+        return Err(ValueError::ArgumentCount {
+            name: "cycle".to_owned(),
+            expected: 1,
+            found: 0,
+        });
+    }
+    Ok(Cycle::new(colors, Vec::from_value(timings)?)
+        .flash(Color::from_value(flash)?, f64::from_value(flash_time)?)
+        .boxed())
+}
+
+/// Reads `blend(from, to, factor)`.
+fn blend<T: FromValue + Lerp>(args: &[Value]) -> Result<Box<dyn Provider<T>>, ValueError> {
+    let [from, to, factor] = arguments::<3>("blend", args)?;
+    Ok(provider::<T>(from)?
+        .blend(provider::<T>(to)?, provider::<f64>(factor)?)
+        .boxed())
+}
+
+/// Reads the providers that do math on numbers and vectors.
+fn arithmetic<T>(name: &str, args: &[Value]) -> Option<Result<Box<dyn Provider<T>>, ValueError>>
+where
+    T: FromValue + Lerp + Add<Output = T> + Mul<f64, Output = T>,
+    Clamp<T>: Provider<T>,
+    Jitter<T>: Provider<T>,
+{
+    let result = match name {
+        "add" => arguments::<2>(name, args).and_then(|[value, other]| {
+            Ok(provider::<T>(value)?.plus(provider::<T>(other)?).boxed() as Box<dyn Provider<T>>)
+        }),
+        "multiply" => arguments::<2>(name, args).and_then(|[value, by]| {
+            Ok(provider::<T>(value)?.times(provider::<f64>(by)?).boxed() as Box<dyn Provider<T>>)
+        }),
+        "blend" => blend(args),
+        "clamp" => arguments::<3>(name, args).and_then(|[value, min, max]| {
+            Ok(provider::<T>(value)?
+                .clamp(T::from_value(min)?, T::from_value(max)?)
+                .boxed() as Box<dyn Provider<T>>)
+        }),
+        "jitter" => arguments::<4>(name, args).and_then(|[value, amount, rate, seed]| {
+            Ok(provider::<T>(value)?
+                .jitter(
+                    f64::from_value(amount)?,
+                    f64::from_value(rate)?,
+                    u32::from_value(seed)?,
+                )
+                .boxed() as Box<dyn Provider<T>>)
+        }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Reads a provider out of a value.
+/// Plain values become [`Constant`]s, while calls name a provider type.
+pub fn provider<T: FromValue>(value: &Value) -> Result<Box<dyn Provider<T>>, ValueError> {
+    match value {
+        Value::Call(name, args) => {
+            if let Some(provider) = T::provider_call(name, args) {
+                return provider;
+            }
+            match name.as_str() {
+                "constant" => {
+                    let [value] = arguments::<1>(name, args)?;
+                    Ok(Constant(T::from_value(value)?).boxed())
+                }
+                "offset" => {
+                    let [inner, offset] = arguments::<2>(name, args)?;
+                    Ok(ProviderOffset(provider(inner)?, f64::from_value(offset)?).boxed())
+                }
+                "switch" | "then" => {
+                    let [before, at, after] = arguments::<3>(name, args)?;
+                    let (before, at, after) = (
+                        provider::<T>(before)?,
+                        f64::from_value(at)?,
+                        provider::<T>(after)?,
+                    );
+                    Ok(if name == "switch" {
+                        before.switch_at(at, after)
+                    } else {
+                        before.then(at, after)
+                    }
+                    .boxed())
+                }
+                "loop" => {
+                    let [inner, period] = arguments::<2>(name, args)?;
+                    let period = f64::from_value(period)?;
+                    if period <= 0.0 {
+                        return Err(ValueError::OutOfRange(period));
+                    }
+                    Ok(provider::<T>(inner)?.looped(period).boxed())
+                }
+                "time_scale" => {
+                    let [inner, scale] = arguments::<2>(name, args)?;
+                    Ok(provider::<T>(inner)?
+                        .time_scale(f64::from_value(scale)?)
+                        .boxed())
+                }
+                // calls can also be plain values, like `rgba(...)`
+                _ => match T::from_value(value) {
+                    Ok(value) => Ok(Constant(value).boxed()),
+                    Err(_) => Err(ValueError::UnknownProvider(name.clone())),
+                },
+            }
+        }
+        value => Ok(Constant(T::from_value(value)?).boxed()),
+    }
+}
+
+/// Fills in the per-spawn parts of a spawner's template field:
+/// `by_index(provider)` and `by_beat(provider)` become the provider's value at the spawn's index or beat,
+/// and `random(min, max)` becomes a random number or vector between the two.
+/// Nested obstacles are left alone, so they can vary per spawn of their own.
+pub fn resolve_spawn(value: &Value, spawn: &mut Spawn) -> Result<Value, ValueError> {
+    Ok(match value {
+        Value::Call(name, args) if name == "by_index" => {
+            let [inner] = arguments::<1>(name, args)?;
+            evaluate(&resolve_spawn(inner, spawn)?, spawn.index as f64)?
+        }
+        Value::Call(name, args) if name == "by_beat" => {
+            let [inner] = arguments::<1>(name, args)?;
+            evaluate(&resolve_spawn(inner, spawn)?, spawn.beat)?
+        }
+        Value::Call(name, args) if name == "random" => {
+            let [min, max] = arguments::<2>(name, args)?;
+            let (min, max) = (resolve_spawn(min, spawn)?, resolve_spawn(max, spawn)?);
+            let mut between = |min: f64, max: f64| min + (max - min) * spawn.rng.gen::<f64>();
+            match (&min, &max) {
+                (Value::Number(min), Value::Number(max)) => Value::Number(between(*min, *max)),
+                (Value::Vector(min), Value::Vector(max)) => {
+                    Value::Vector((between(min.x, max.x), between(min.y, max.y)).into())
+                }
+                _ => return Err(min.mismatch("two numbers or two vectors")),
+            }
+        }
+        Value::Call(name, args) => Value::Call(
+            name.clone(),
+            args.iter()
+                .map(|arg| resolve_spawn(arg, spawn))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::List(values) => Value::List(
+            values
+                .iter()
+                .map(|value| resolve_spawn(value, spawn))
+                .collect::<Result<_, _>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Reads a provider of whichever type fits and gets its value at `beat`.
+fn evaluate(value: &Value, beat: f64) -> Result<Value, ValueError> {
+    if let Ok(provider) = provider::<f64>(value) {
+        Ok(provider.get(beat).into())
+    } else if let Ok(provider) = provider::<DVec2>(value) {
+        Ok(provider.get(beat).into())
+    } else if let Ok(provider) = provider::<Color>(value) {
+        Ok(provider.get(beat).into())
+    } else {
+        Err(value.mismatch("a number, vector or color provider"))
+    }
+}
@@ -0,0 +1,96 @@
+use std::{io, path::Path};
+
+use thiserror::Error;
+
+use crate::{
+    level::{Level, SeedPolicy, SongSource},
+    palette::Slot,
+    tempo::TimeSignature,
+};
+
+use super::{lexer::Quoted, Value};
+
+#[derive(Debug, Error)]
+pub enum LevelWriteError {
+    #[error("io error while writing level")]
+    Io(#[from] io::Error),
+    #[error("the {0} color can't be written to a level file")]
+    UnserializableColor(&'static str),
+    #[error("the backdrop can't be written to a level file")]
+    UnserializableBackdrop,
+    #[error("obstacle {index} (at beat {beat}) can't be written to a level file")]
+    UnserializableObstacle { index: usize, beat: f64 },
+}
+
+/// Writes a level in the text format, so it can be read back with [`super::load`].
+/// Song paths are written relative to `base_directory` when possible.
+pub fn write_level(
+    level: &Level,
+    out: &mut impl io::Write,
+    base_directory: &Path,
+) -> Result<(), LevelWriteError> {
+    writeln!(out, "name {}", Quoted(&level.info.name))?;
+    writeln!(out, "artist {}", Quoted(&level.info.artist))?;
+    writeln!(out, "difficulty {}", level.info.difficulty.name())?;
+    writeln!(out, "preview {}", level.info.preview_beat)?;
+    match level.info.seed_policy {
+        SeedPolicy::Fixed(seed) => writeln!(out, "seed {seed}")?,
+        SeedPolicy::PerSession => writeln!(out, "seed per_session")?,
+        SeedPolicy::PerAttempt => writeln!(out, "seed per_attempt")?,
+    }
+    match &level.song.source {
+        SongSource::Builtin(name) => writeln!(out, "song builtin {}", Quoted(name))?,
+        SongSource::File(path) => {
+            let path = path.strip_prefix(base_directory).unwrap_or(path);
+            writeln!(out, "song {}", Quoted(&path.to_string_lossy()))?
+        }
+    }
+    let tempos = level.tempo.tempos();
+    writeln!(out, "bpm {}", tempos[0].bpm)?;
+    writeln!(out, "start_time {}", level.tempo.start_time())?;
+    if tempos.len() > 1 {
+        writeln!(out, "tempo_changes {}", Value::from(tempos[1..].to_vec()))?;
+    }
+    // 4/4 all the way through is the default, so it isn't worth writing
+    let signatures = level.tempo.signatures();
+    if signatures.len() > 1 || signatures[0].signature != TimeSignature::COMMON {
+        writeln!(out, "time_signatures {}", Value::from(signatures.to_vec()))?;
+    }
+    writeln!(
+        out,
+        "checkpoints {}",
+        Value::from(level.checkpoints.clone())
+    )?;
+    for slot in Slot::ALL {
+        let color = level
+            .palette()
+            .color(slot)
+            .serialize()
+            .ok_or(LevelWriteError::UnserializableColor(slot.name()))?;
+        writeln!(out, "{} {color}", slot.name())?;
+    }
+    let backdrop = level
+        .backdrop()
+        .serialize()
+        .ok_or(LevelWriteError::UnserializableBackdrop)?;
+    // a plain fill is the default, so it isn't worth writing
+    if backdrop.kind != "Fill" {
+        writeln!(out, "backdrop {}", Value::Obstacle(backdrop))?;
+    }
+
+    for (index, obstacle) in level.obstacles().iter().enumerate() {
+        let definition = obstacle
+            .serialize()
+            .ok_or(LevelWriteError::UnserializableObstacle {
+                index,
+                beat: obstacle.offset,
+            })?;
+        writeln!(out)?;
+        writeln!(out, "at {} {} {{", obstacle.offset, definition.kind)?;
+        for (name, value) in &definition.fields {
+            writeln!(out, "    {name} {value}")?;
+        }
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
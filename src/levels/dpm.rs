@@ -2,99 +2,78 @@
 //!
 //! WTF IS A LEVEL EDITOR!!!!!!!!!!!
 
-use std::f64::consts::{E, FRAC_PI_2, FRAC_PI_4, TAU};
+use std::{
+    f64::consts::{E, FRAC_PI_2, FRAC_PI_4, TAU},
+    rc::Rc,
+};
 
 use jut::extensions::Boxed;
 use macroquad::{
-    color::{Color, BLACK},
+    color::{Color, BLACK, WHITE},
     math::{dvec2, DVec2},
-    window::{screen_height, screen_width},
 };
 use obstacles::{PolygonPayload, TheShapes};
-use providers::Homosexuality;
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use providers::RandomGrid;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{
-    level::{Level, LevelBuilder},
+    arena,
+    easing::{Curve, Easing},
+    ext::ColorExt,
+    level::{Difficulty, Level, LevelBuilder, LevelInfo, SeedPolicy, Song},
+    levelfile::LevelLoadError,
     obstacle::{
         lasers::{SlamLaser, WidenLaser},
-        Bomb, Obstacle, ObstacleBehaviour, Rectangle, RectangleGenerator, SetBackground,
-        SetForeground, Shake,
+        Bomb, Group, Obstacle, Rectangle, RectangleGenerator, SetPalette, Shake, SpawnTemplate,
+        Spawner,
     },
+    palette::{self, Cycle, Slot},
     polygon::{
         self,
-        presets::{generate_heart, generate_polygon, generate_spokes},
+        presets::{generate_polygon, generate_spokes},
         Polygon,
     },
-    provider::{Constant, FnWrap, Provider, ProviderOffset},
-    res::songs,
+    provider::{Constant, FnWrap, Keyframes, Linear, Provider, ProviderExt, ProviderOffset},
+    res::shapes,
+    tempo::TempoMap,
 };
 
+use super::LevelDefinition;
+
 mod providers {
-    use std::cell::Cell;
+    use std::cell::RefCell;
 
     use jut::extensions::Boxed;
-    use macroquad::color::{Color, BLACK, WHITE};
+    use macroquad::math::DVec2;
+    use rand::{rngs::StdRng, Rng};
 
-    use crate::{ext::ColorExt, provider::Provider};
+    use crate::{arena, provider::Provider};
 
-    #[rustfmt::skip]
-    pub const GAY_COLORS: [Color; 10] = [
-        Color::new(1.0, 0.0, 0.0, 1.0),
-        Color::new(1.0, 0.5, 0.0, 1.0),
-        Color::new(1.0, 1.0, 0.0, 1.0),
-        Color::new(0.0, 1.0, 0.0, 1.0),
-        Color::new(0.0, 0.0, 1.0, 1.0),
-        Color::new(0.5, 0.0, 1.0, 1.0),
-        //
-        Color::new(1.0, 0.5, 0.8, 1.0),
-        Color::new(0.5, 0.8, 1.0, 1.0),
-        Color::new(0.8, 0.8, 0.8, 1.0),
-        //
-        Color::new(0.5, 0.25, 0.0, 1.0),
-    ];
+    /// A random point on a grid covering the screen, different every time it's sampled.
+    /// Clones keep drawing the same sequence, so the same seed always gives the same points.
     #[derive(Clone)]
-    pub struct Homosexuality {
-        pub timings: Vec<f64>,
-        pub current_index: Cell<usize>,
-        pub is_background: bool,
+    pub struct RandomGrid {
+        pub rng: RefCell<StdRng>,
+        pub spacing: f64,
     }
-    impl Homosexuality {
-        pub fn new(mut timings: Vec<f64>, is_background: bool) -> Self {
-            timings.insert(0, 0.0);
+    impl RandomGrid {
+        pub fn new(rng: StdRng, spacing: f64) -> Self {
             Self {
-                timings,
-                is_background,
-                current_index: Cell::new(1),
+                rng: RefCell::new(rng),
+                spacing,
             }
         }
     }
-    impl Provider<Color> for Homosexuality {
-        fn box_clone(&self) -> Box<dyn Provider<Color>> {
+    impl Provider<DVec2> for RandomGrid {
+        fn box_clone(&self) -> Box<dyn Provider<DVec2>> {
             self.clone().boxed()
         }
-        fn get(&self, beat: f64) -> Color {
-            while self.current_index.get() < self.timings.len() {
-                if beat > self.timings[self.current_index.get()] {
-                    self.current_index.set(self.current_index.get() + 1);
-                } else {
-                    break;
-                }
-            }
-            let designator_color = if self.is_background { BLACK } else { WHITE };
-            let base_color = GAY_COLORS[(self.current_index.get() - 1) % GAY_COLORS.len()]
-                .mix(designator_color, 0.5);
-            let flash_color = designator_color.mix(WHITE, 0.5);
-            let timing = if self.timings.is_empty() {
-                0.0
-            } else {
-                self.timings[self
-                    .current_index
-                    .get()
-                    .min(self.timings.len())
-                    .saturating_sub(1)]
-            };
-            flash_color.mix(base_color, ((beat - timing) * 2.0).min(1.0))
+        fn get(&self, _beat: f64) -> DVec2 {
+            let mut rng = self.rng.borrow_mut();
+            DVec2::new(
+                (rng.gen_range(0.0..arena::WIDTH) / self.spacing).round() * self.spacing,
+                (rng.gen_range(0.0..arena::HEIGHT) / self.spacing).round() * self.spacing,
+            )
         }
     }
 }
@@ -109,7 +88,6 @@ mod obstacles {
     };
 
     use crate::{
-        collide,
         ext::ColorExt,
         obstacle::{Circle, DynObstacleBehaviour, Obstacle, ObstacleBehaviour},
         polygon::Polygon,
@@ -170,12 +148,18 @@ mod obstacles {
         }
         fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
             beat > 0.0
-                && collide::circle_circle(
-                    circle_pos,
-                    circle_radius,
-                    self.position.as_dvec2(),
-                    self.scale,
-                )
+                && self
+                    .polygons
+                    .get(self.current_index)
+                    .is_some_and(|payload| {
+                        payload.polygon.collides(
+                            circle_pos,
+                            circle_radius,
+                            self.position,
+                            0.0,
+                            Vec2::splat(self.scale as f32),
+                        )
+                    })
         }
         fn draw(&self, mut main_color: Color, beat: f64) {
             if beat < 0.0 {
@@ -245,30 +229,79 @@ mod obstacles {
     }
 }
 
-pub fn build() -> Level {
-    let mut level_builder = LevelBuilder::new();
+pub const BPM: f64 = 193.0;
+pub const START_TIME: f64 = 2.4963;
+
+pub fn tempo() -> TempoMap {
+    TempoMap::new(BPM, START_TIME)
+}
+
+pub fn info() -> LevelInfo {
+    LevelInfo {
+        name: "...during pride month?".to_owned(),
+        artist: "Jane Remover (Leroy)".to_owned(),
+        difficulty: Difficulty::Hard,
+        preview_beat: 224.0,
+        seed_policy: SeedPolicy::PerSession,
+    }
+}
+
+pub struct DuringPrideMonth {
+    info: LevelInfo,
+    tempo: TempoMap,
+}
+impl DuringPrideMonth {
+    pub fn new() -> Self {
+        Self {
+            info: info(),
+            tempo: tempo(),
+        }
+    }
+}
+impl LevelDefinition for DuringPrideMonth {
+    fn info(&self) -> &LevelInfo {
+        &self.info
+    }
+    fn tempo(&self) -> &TempoMap {
+        &self.tempo
+    }
+    fn song(&self) -> Result<Song, LevelLoadError> {
+        Ok(Song::builtin("during_pride_month").unwrap())
+    }
+    fn build(&self, seed: u64) -> Result<Level, LevelLoadError> {
+        Ok(build(seed))
+    }
+}
+
+/// Adds a copy of a breakdown generator that spawns on its own random grid.
+fn add_generator(level_builder: &mut LevelBuilder, offset: f64, generator: &RectangleGenerator) {
+    let mut generator = generator.clone();
+    let seed = level_builder.rng().gen();
+    generator.spawned_center = RandomGrid::new(StdRng::seed_from_u64(seed), 50.0).boxed();
+    level_builder.obstacle(Obstacle::new(offset, generator.boxed()));
+}
+
+pub fn build(seed: u64) -> Level {
+    let mut level_builder = LevelBuilder::new(seed);
+    level_builder.info(info());
     // intro
-    let scr_width = screen_width() as f64;
-    let scr_height = screen_height() as f64;
+    let scr_width = arena::WIDTH;
+    let scr_height = arena::HEIGHT;
     let scr_size = DVec2::new(scr_width, scr_height);
     let chords = 4;
     for (offset, lifetime) in [(0.0, 2.0), (2.0, 1.5), (3.5, 1.0), (4.5, 1.5), (6.0, 2.0)] {
         for i in 0..4 {
             for _ in 0..chords {
+                let start_x = level_builder.rng().gen_range(-scr_width..scr_width * 2.0);
+                let end_x = level_builder.rng().gen_range(-scr_width..scr_width * 2.0);
                 level_builder.obstacle(Obstacle::new(
                     offset + i as f64 * 8.0,
                     WidenLaser::default()
                         .warn_time(4.0)
                         .thickness(50.0)
                         .lifetime(lifetime)
-                        .start_pos(DVec2::new(
-                            thread_rng().gen_range(-scr_width..scr_width * 2.0),
-                            -50.0,
-                        ))
-                        .end_pos(DVec2::new(
-                            thread_rng().gen_range(-scr_width..scr_width * 2.0),
-                            scr_height as f64 + 50.0,
-                        ))
+                        .start_pos(DVec2::new(start_x, -50.0))
+                        .end_pos(DVec2::new(end_x, scr_height as f64 + 50.0))
                         .boxed(),
                 ));
             }
@@ -294,26 +327,20 @@ pub fn build() -> Level {
     // the little drop
     for (idx, (time, horizontal)) in cardinal_slam_data.into_iter().enumerate() {
         let [start, end] = if horizontal {
-            let mut x = thread_rng().gen_range(50.0..screen_width() as f64 * 0.5);
+            let mut x = level_builder.rng().gen_range(50.0..arena::WIDTH * 0.5);
             if idx % 2 == 0 {
-                x += screen_width() as f64 * 0.5 - 100.0;
+                x += arena::WIDTH * 0.5 - 100.0;
             }
-            let mut out = [
-                DVec2::new(x, -50.0),
-                DVec2::new(x, screen_height() as f64 + 50.0),
-            ];
-            out.shuffle(&mut thread_rng());
+            let mut out = [DVec2::new(x, -50.0), DVec2::new(x, arena::HEIGHT + 50.0)];
+            out.shuffle(level_builder.rng());
             out
         } else {
-            let mut y = thread_rng().gen_range(0.0..screen_height() as f64 * 0.5);
+            let mut y = level_builder.rng().gen_range(0.0..arena::HEIGHT * 0.5);
             if idx % 2 == 0 {
-                y += screen_height() as f64 * 0.5;
+                y += arena::HEIGHT * 0.5;
             }
-            let mut out = [
-                DVec2::new(-50.0, y),
-                DVec2::new(screen_width() as f64 + 50.0, y),
-            ];
-            out.shuffle(&mut thread_rng());
+            let mut out = [DVec2::new(-50.0, y), DVec2::new(arena::WIDTH + 50.0, y)];
+            out.shuffle(level_builder.rng());
             out
         };
         level_builder.obstacle(Obstacle::new(
@@ -333,18 +360,21 @@ pub fn build() -> Level {
         if i == 4 || i == 12 {
             continue;
         }
-        let y = thread_rng().gen_range(50.0f64..screen_height() as f64 - 50.0f64);
-        let y_start_offset = thread_rng().gen_range(-20.0f64..20.0f64);
+        let y = level_builder
+            .rng()
+            .gen_range(50.0f64..arena::HEIGHT - 50.0f64);
+        let y_start_offset = level_builder.rng().gen_range(-20.0f64..20.0f64);
         level_builder.obstacle(Obstacle::new(
             i as f64 * 4.0 + 33.0,
             Bomb {
-                start_position: DVec2::new(screen_width() as f64, y + y_start_offset),
-                end_position: DVec2::new(screen_width() as f64 - 150.0, y),
+                start_position: DVec2::new(arena::WIDTH, y + y_start_offset),
+                end_position: DVec2::new(arena::WIDTH - 150.0, y),
                 lifetime: 1.0,
                 radius_per_beat: 10.0,
                 projectile_count: 12,
                 projectile_radius: 5.0,
                 projectile_speed: 200.0,
+                aimed: false,
             }
             .boxed(),
         ));
@@ -362,30 +392,24 @@ pub fn build() -> Level {
     for (idx, (time, horizontal)) in uncardinal_slam_data.into_iter().enumerate() {
         let time = time + 32.0;
         let [start, end] = if horizontal {
-            let mut x = thread_rng().gen_range(50.0..screen_width() as f64 * 0.5);
-            let mut x2 = thread_rng().gen_range(50.0..screen_width() as f64 * 0.5);
+            let mut x = level_builder.rng().gen_range(50.0..arena::WIDTH * 0.5);
+            let mut x2 = level_builder.rng().gen_range(50.0..arena::WIDTH * 0.5);
             if idx % 2 == 0 {
-                x += screen_width() as f64 * 0.5 - 100.0;
-                x2 += screen_width() as f64 * 0.5 - 100.0;
+                x += arena::WIDTH * 0.5 - 100.0;
+                x2 += arena::WIDTH * 0.5 - 100.0;
             }
-            let mut out = [
-                DVec2::new(x, -50.0),
-                DVec2::new(x2, screen_height() as f64 + 50.0),
-            ];
-            out.shuffle(&mut thread_rng());
+            let mut out = [DVec2::new(x, -50.0), DVec2::new(x2, arena::HEIGHT + 50.0)];
+            out.shuffle(level_builder.rng());
             out
         } else {
-            let mut y = thread_rng().gen_range(50.0..screen_height() as f64 * 0.5);
-            let mut y2 = thread_rng().gen_range(50.0..screen_height() as f64 * 0.5);
+            let mut y = level_builder.rng().gen_range(50.0..arena::HEIGHT * 0.5);
+            let mut y2 = level_builder.rng().gen_range(50.0..arena::HEIGHT * 0.5);
             if idx % 2 == 0 {
-                y += screen_height() as f64 * 0.5 - 100.0;
-                y2 += screen_height() as f64 * 0.5 - 100.0;
+                y += arena::HEIGHT * 0.5 - 100.0;
+                y2 += arena::HEIGHT * 0.5 - 100.0;
             }
-            let mut out = [
-                DVec2::new(-50.0, y),
-                DVec2::new(screen_width() as f64 + 50.0, y2),
-            ];
-            out.shuffle(&mut thread_rng());
+            let mut out = [DVec2::new(-50.0, y), DVec2::new(arena::WIDTH + 50.0, y2)];
+            out.shuffle(level_builder.rng());
             out
         };
         level_builder.obstacle(Obstacle::new(
@@ -457,7 +481,9 @@ pub fn build() -> Level {
         Rectangle {
             center: Constant::<DVec2>(scr_size * 0.5).boxed(),
             size: Constant::<DVec2>(dvec2(150.0, 150.0)).boxed(),
-            rotation: FnWrap(|beat| -(beat - 1.0).max(0.0) * TAU * 2.0).boxed(),
+            rotation: Constant(0.0)
+                .then(1.0, Linear::new(0.0, -TAU * 2.0))
+                .boxed(),
             lifetime: 2.0,
             warn_time: 4.0,
             leave_time: 0.1,
@@ -475,7 +501,7 @@ pub fn build() -> Level {
     // the kick lasers
     for timing in timings {
         for i in [96.0, 336.0, 352.0, 368.0] {
-            let x = thread_rng().gen_range(0.0..scr_width);
+            let x = level_builder.rng().gen_range(0.0..scr_width);
             level_builder.obstacle(Obstacle::new(
                 timing + i,
                 WidenLaser::default()
@@ -488,7 +514,7 @@ pub fn build() -> Level {
                     .warn_time(4.0)
                     .boxed(),
             ));
-            let x = thread_rng().gen_range(0.0..scr_width);
+            let x = level_builder.rng().gen_range(0.0..scr_width);
             level_builder.obstacle(Obstacle::new(
                 timing + i,
                 WidenLaser::default()
@@ -503,8 +529,8 @@ pub fn build() -> Level {
             ));
         }
         for i in [112.0, 384.0] {
-            let x = thread_rng().gen_range(0.0..scr_width);
-            let x2 = thread_rng().gen_range(0.0..scr_width);
+            let x = level_builder.rng().gen_range(0.0..scr_width);
+            let x2 = level_builder.rng().gen_range(0.0..scr_width);
             level_builder.obstacle(Obstacle::new(
                 timing + i,
                 WidenLaser::default()
@@ -518,8 +544,8 @@ pub fn build() -> Level {
                     .warn_time(4.0)
                     .boxed(),
             ));
-            let x = thread_rng().gen_range(0.0..scr_width);
-            let x2 = thread_rng().gen_range(0.0..scr_width);
+            let x = level_builder.rng().gen_range(0.0..scr_width);
+            let x2 = level_builder.rng().gen_range(0.0..scr_width);
             level_builder.obstacle(Obstacle::new(
                 timing + i,
                 WidenLaser::default()
@@ -542,7 +568,7 @@ pub fn build() -> Level {
     for coarse in [320.0, 336.0, 352.0] {
         for fine in bomb_timings {
             for projectiles in 8..=10 {
-                let y = thread_rng().gen_range(50.0..scr_height - 50.0);
+                let y = level_builder.rng().gen_range(50.0..scr_height - 50.0);
                 level_builder.obstacle(Obstacle::new(
                     coarse + fine - 2.0,
                     Bomb {
@@ -553,44 +579,41 @@ pub fn build() -> Level {
                         projectile_count: projectiles,
                         projectile_radius: 5.0,
                         projectile_speed: 100.0,
+                        aimed: false,
                     }
                     .boxed(),
                 ));
             }
         }
     }
-    // the boingy lasers
-    for i in 0..31 {
-        if i == 15 {
-            continue;
-        }
-        level_builder.obstacle(Obstacle::new(
-            i as f64 * 1.0 + 352.0,
-            WidenLaser::default()
-                .start_pos(dvec2(-50.0, 0.0))
-                .end_pos(dvec2(scr_width + 50.0, 0.0))
-                .thickness(125.0)
-                .grow_time(0.125)
-                .shrink_time(0.125)
-                .lifetime(0.5)
-                .flash_time(0.0)
-                .warn_time(if i == 0 { 4.0 } else { 0.0 })
-                .boxed(),
-        ));
-        level_builder.obstacle(Obstacle::new(
-            i as f64 * 1.0 + 352.5,
-            WidenLaser::default()
-                .start_pos(dvec2(-50.0, scr_height))
-                .end_pos(dvec2(scr_width + 50.0, scr_height))
-                .thickness(125.0)
-                .grow_time(0.125)
-                .shrink_time(0.125)
-                .lifetime(0.5)
-                .flash_time(0.0)
-                .warn_time(if i == 0 { 4.0 } else { 0.0 })
-                .boxed(),
-        ));
-    }
+    // the boingy lasers, alternating between the top and the bottom
+    level_builder.obstacle(Obstacle::new(
+        352.0,
+        Spawner::new(
+            SpawnTemplate::Build(Rc::new(move |spawn| {
+                let (beat, bottom) = (spawn.index / 2, spawn.index % 2 == 1);
+                if beat == 15 {
+                    return None;
+                }
+                let y = if bottom { scr_height } else { 0.0 };
+                Some(
+                    WidenLaser::default()
+                        .start_pos(dvec2(-50.0, y))
+                        .end_pos(dvec2(scr_width + 50.0, y))
+                        .thickness(125.0)
+                        .grow_time(0.125)
+                        .shrink_time(0.125)
+                        .lifetime(0.5)
+                        .flash_time(0.0)
+                        .warn_time(if beat == 0 { 4.0 } else { 0.0 })
+                        .boxed(),
+                )
+            })),
+            0.5,
+            62,
+        )
+        .boxed(),
+    ));
 
     // the breakdown
     // the part with no words
@@ -598,13 +621,8 @@ pub fn build() -> Level {
         interval: 0.25,
         lifetime: 8.0,
         spawned: 0,
-        spawned_center: FnWrap(|_| {
-            DVec2::new(
-                (thread_rng().gen_range(0.0..screen_width()) as f64 / 50.0).round() * 50.0,
-                (thread_rng().gen_range(0.0..screen_height()) as f64 / 50.0).round() * 50.0,
-            )
-        })
-        .boxed(),
+        // replaced with a differently seeded grid for every generator added
+        spawned_center: Constant(DVec2::ZERO).boxed(),
         spawned_size: Constant(dvec2(50.0, 50.0)).boxed(),
         spawned_rotation: Constant(0.0).boxed(),
         spawned_lifetime: 1.0,
@@ -618,24 +636,27 @@ pub fn build() -> Level {
 
     for _ in 0..3 {
         tall_generator.lifetime = 8.0;
-        level_builder.obstacle(Obstacle::new(128.0, wide_generator.box_clone()));
-        level_builder.obstacle(Obstacle::new(136.0, tall_generator.box_clone()));
-        level_builder.obstacle(Obstacle::new(144.0, wide_generator.box_clone()));
+        add_generator(&mut level_builder, 128.0, &wide_generator);
+        add_generator(&mut level_builder, 136.0, &tall_generator);
+        add_generator(&mut level_builder, 144.0, &wide_generator);
         tall_generator.lifetime = 6.0;
-        level_builder.obstacle(Obstacle::new(152.0, tall_generator.box_clone()));
+        add_generator(&mut level_builder, 152.0, &tall_generator);
     }
 
-    let gen = FnWrap(|beat| (beat * 4.0).round() / 16.0 * TAU).boxed();
+    // snaps to sixteenths of a turn, four times a beat
+    let gen = Linear::new(0.0, 4.0)
+        .map(|quarters: f64| quarters.round() / 16.0 * TAU)
+        .dyn_boxed();
     wide_generator.spawned_rotation = gen.box_clone();
     tall_generator.spawned_rotation = gen.box_clone();
 
     for _ in 0..3 {
         tall_generator.lifetime = 8.0;
-        level_builder.obstacle(Obstacle::new(160.0, wide_generator.box_clone()));
-        level_builder.obstacle(Obstacle::new(168.0, tall_generator.box_clone()));
-        level_builder.obstacle(Obstacle::new(176.0, wide_generator.box_clone()));
+        add_generator(&mut level_builder, 160.0, &wide_generator);
+        add_generator(&mut level_builder, 168.0, &tall_generator);
+        add_generator(&mut level_builder, 176.0, &wide_generator);
         tall_generator.lifetime = 6.0;
-        level_builder.obstacle(Obstacle::new(184.0, tall_generator.box_clone()));
+        add_generator(&mut level_builder, 184.0, &tall_generator);
     }
 
     // face is a blur
@@ -656,7 +677,7 @@ pub fn build() -> Level {
                 DVec2::splat(factor * 300.0)
             })
             .boxed(),
-            rotation: FnWrap(|beat| -beat * 0.5).boxed(),
+            rotation: Linear::new(0.0, -0.5).boxed(),
             lifetime: 16.0,
             warn_time: 4.0,
             leave_time: 0.01,
@@ -680,7 +701,7 @@ pub fn build() -> Level {
                 DVec2::splat(factor * 300.0)
             })
             .boxed(),
-            rotation: FnWrap(|beat| -beat * 0.5).boxed(),
+            rotation: Linear::new(0.0, -0.5).boxed(),
             lifetime: 16.0,
             warn_time: 4.0,
             leave_time: 0.01,
@@ -690,6 +711,7 @@ pub fn build() -> Level {
 
     // oop, big drop time, load up all the polygons now
     let the_big_drop_time = 224.0;
+    let heart = Polygon::from_bytes(shapes::HEART).expect("the embedded heart should decode");
     #[rustfmt::skip]
     let polies = vec![
         PolygonPayload::new(Polygon::from_arrays(generate_polygon::<50>(0.0)), 0.0, 20.0, 25,),
@@ -704,7 +726,7 @@ pub fn build() -> Level {
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<4>(0.2, 0.2, FRAC_PI_2 as f32 * 2.0)), 7.0, 0.0, 0,),
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<5>(0.2, 0.2, FRAC_PI_2 as f32 * 3.0)), 7.5, 0.0, 0,),
 
-        PolygonPayload::new(Polygon::from_arrays(generate_heart::<50>()), 8.0, 30.0, 25),
+        PolygonPayload::new(heart.clone(), 8.0, 30.0, 25),
         PolygonPayload::new(Polygon::from_arrays(generate_polygon::<5>(FRAC_PI_2 * 1.5)), 9.0, 20.0, 15,),
         PolygonPayload::new(Polygon::from_arrays(generate_polygon::<5>(FRAC_PI_2 * -1.5)), 10.0, 20.0, 20,),
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<3>(0.0, 0.4, 0.0)), 10.5, 10.0, 10,),
@@ -725,7 +747,7 @@ pub fn build() -> Level {
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<4>(0.2, 0.2, FRAC_PI_2 as f32 * 2.0)), 23.0, 0.0, 0,),
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<5>(0.2, 0.2, FRAC_PI_2 as f32 * 3.0)), 23.5, 0.0, 0,),
 
-        PolygonPayload::new(Polygon::from_arrays(generate_heart::<50>()), 24.0, 30.0, 25),
+        PolygonPayload::new(heart.clone(), 24.0, 30.0, 25),
         PolygonPayload::new(Polygon::from_arrays(generate_polygon::<5>(FRAC_PI_2 * 1.5)), 25.0, 20.0, 15,),
         PolygonPayload::new(Polygon::from_arrays(generate_polygon::<5>(FRAC_PI_2 * -1.5)), 26.0, 20.0, 20,),
         PolygonPayload::new(Polygon::from_arrays(generate_spokes::<3>(0.0, 0.4, 0.0)), 26.5, 10.0, 10,),
@@ -742,8 +764,7 @@ pub fn build() -> Level {
         TheShapes::new(polies, 32.0, scr_size.as_vec2() * 0.5, 100.0, 8.0).boxed(),
     ));
     #[rustfmt::skip]
-    let gay_foreground = Homosexuality::new(
-        vec![
+    let gay_timings = vec![
             1.0, 2.0, 2.5, 3.5, 4.5, 5.0, 6.0,
             8.0, 9.0, 10.0, 10.5, 11.5, 12.5, 13.0, 14.0, 14.5, 15.0, 15.5,
             16.0, 19.5, 20.5, 21.0, 22.0,
@@ -754,33 +775,33 @@ pub fn build() -> Level {
             40.0, 41.0, 42.0, 42.5, 43.5, 44.5, 45.0, 46.0, 46.5, 47.0, 47.5,
             48.0, 51.5, 52.5, 53.0, 54.0, 54.5, 55.0, 55.5,
             56.0, 58.0, 60.0, 61.0,
-        ],
-        false,
-    );
-    let mut gay_background = gay_foreground.clone();
-    gay_background.is_background = true;
-    level_builder.obstacle(Obstacle::new(
-        the_big_drop_time,
-        SetForeground(ProviderOffset(gay_foreground.boxed(), the_big_drop_time).boxed()).boxed(),
-    ));
+        ];
+    // the foreground is tinted towards white and the background towards black
+    let gay = |tint: Color| {
+        let colors = palette::PRIDE.map(|color| color.mix(tint, 0.5)).to_vec();
+        let cycle = Cycle::new(colors, gay_timings.clone()).flash(tint.mix(WHITE, 0.5), 0.5);
+        ProviderOffset(cycle.boxed(), the_big_drop_time).boxed()
+    };
     level_builder.obstacle(Obstacle::new(
         the_big_drop_time,
-        SetBackground(ProviderOffset(gay_background.boxed(), the_big_drop_time).boxed()).boxed(),
+        SetPalette::default()
+            .color(Slot::Primary, gay(WHITE))
+            .color(Slot::Background, gay(BLACK))
+            .boxed(),
     ));
     for coarse in [0, 1, 2, 5, 6] {
         let coarse_time = coarse as f64 * 4.0 + 1.0 + the_big_drop_time;
         for fine in 0..3 {
             let fine_time = fine as f64 * 0.125;
             for _ in 0..3 {
-                let x = thread_rng().gen_range(0.0..scr_width);
+                let x = level_builder.rng().gen_range(0.0..scr_width);
+                let start_x = x + level_builder.rng().gen_range(-50.0..50.0);
+                let end_x = x + level_builder.rng().gen_range(-50.0..50.0);
                 level_builder.obstacle(Obstacle::new(
                     coarse_time + fine_time,
                     SlamLaser::default()
-                        .start_pos(DVec2::new(x + thread_rng().gen_range(-50.0..50.0), -50.0))
-                        .end_pos(DVec2::new(
-                            x + thread_rng().gen_range(-50.0..50.0),
-                            scr_height + 50.0,
-                        ))
+                        .start_pos(DVec2::new(start_x, -50.0))
+                        .end_pos(DVec2::new(end_x, scr_height + 50.0))
                         .warn_time(4.0)
                         .thickness(25.0)
                         .lifetime(1.0)
@@ -790,42 +811,39 @@ pub fn build() -> Level {
         }
     }
 
+    // two rings of rectangles orbiting in opposite directions around a wobbling center
     let ring_count = 20;
-    for rect in 0..ring_count {
-        let period = TAU * rect as f64 / ring_count as f64;
-        level_builder.obstacle(Obstacle::new(
-            258.0,
-            Rectangle {
-                center: FnWrap(move |beat| {
-                    DVec2::new((period + beat).sin(), (period + beat).cos()) * scr_width * 0.4
-                        - DVec2::new((beat * 0.25).sin(), (beat * 0.4).cos()) * 250.0
-                        + scr_size / 2.0
-                })
-                .boxed(),
-                size: Constant(dvec2(50.0, 50.0)).boxed(),
-                rotation: FnWrap(move |beat| period - beat * 2.0).boxed(),
-                lifetime: 22.0,
-                warn_time: 4.0,
-                leave_time: 0.25,
-            }
-            .boxed(),
-        ));
+    for (radius, spin) in [(scr_width * 0.4, -1.0), (scr_width * 0.5, 1.0)] {
+        let children = (0..ring_count)
+            .map(|rect| {
+                let period = TAU * rect as f64 / ring_count as f64;
+                Obstacle::new(
+                    0.0,
+                    Rectangle {
+                        center: Constant(DVec2::new(period.sin(), period.cos()) * radius).boxed(),
+                        size: Constant(dvec2(50.0, 50.0)).boxed(),
+                        // the ring's turning gets added on top of this
+                        rotation: Linear::new(period, spin - 2.0).boxed(),
+                        lifetime: 22.0,
+                        warn_time: 4.0,
+                        leave_time: 0.25,
+                    }
+                    .boxed(),
+                )
+            })
+            .collect();
         level_builder.obstacle(Obstacle::new(
             258.0,
-            Rectangle {
-                center: FnWrap(move |beat| {
-                    DVec2::new((period - beat).sin(), (period - beat).cos()) * scr_width * 0.5
-                        - DVec2::new((beat * 0.25).sin(), (beat * 0.4).cos()) * 250.0
-                        + scr_size / 2.0
-                })
+            Group::default()
+                .children(children)
+                .position(
+                    FnWrap(move |beat| {
+                        scr_size / 2.0 - DVec2::new((beat * 0.25).sin(), (beat * 0.4).cos()) * 250.0
+                    })
+                    .boxed(),
+                )
+                .rotation(Linear::new(0.0, spin).boxed())
                 .boxed(),
-                size: Constant(dvec2(50.0, 50.0)).boxed(),
-                rotation: FnWrap(move |beat| period - beat * 2.0).boxed(),
-                lifetime: 22.0,
-                warn_time: 4.0,
-                leave_time: 0.25,
-            }
-            .boxed(),
         ));
     }
     let bomb_steps = 5;
@@ -847,6 +865,7 @@ pub fn build() -> Level {
                     projectile_count: 16,
                     projectile_radius: 5.0,
                     projectile_speed: 300.0,
+                    aimed: false,
                 }
                 .boxed(),
             ));
@@ -862,11 +881,11 @@ pub fn build() -> Level {
         56.0, 58.0, 60.0, 61.0, 56.0, 58.0, 60.0, 61.0, // doubled up lasers
     ];
     for i in laser_timings {
-        let is_horizontal = thread_rng().gen::<bool>();
-        let swap_sides = thread_rng().gen::<bool>();
+        let is_horizontal = level_builder.rng().gen::<bool>();
+        let swap_sides = level_builder.rng().gen::<bool>();
         let max_size = if is_horizontal { scr_width } else { scr_height };
         let opposite_size = if is_horizontal { scr_height } else { scr_width };
-        let position = thread_rng().gen_range(0.0..max_size);
+        let position = level_builder.rng().gen_range(0.0..max_size);
 
         let (mut start_x, mut end_x) = (-50.0, opposite_size + 50.0);
         let (mut start_y, mut end_y) = (position, position);
@@ -892,19 +911,21 @@ pub fn build() -> Level {
     }
     level_builder.obstacle(Obstacle::new(
         286.0,
-        SetForeground(Constant(Color::new(1.0, 0.0, 0.5, 1.0)).boxed()).boxed(),
-    ));
-    level_builder.obstacle(Obstacle::new(
-        286.0,
-        SetBackground(Constant(BLACK).boxed()).boxed(),
+        SetPalette::default()
+            .color(
+                Slot::Primary,
+                Constant(Color::new(1.0, 0.0, 0.5, 1.0)).boxed(),
+            )
+            .color(Slot::Background, Constant(BLACK).boxed())
+            .boxed(),
     ));
     for i in 0..121 {
         let offset = i as f64 / 4.0 + 288.0;
-        let is_horizontal = thread_rng().gen::<bool>();
-        let swap_sides = thread_rng().gen::<bool>();
+        let is_horizontal = level_builder.rng().gen::<bool>();
+        let swap_sides = level_builder.rng().gen::<bool>();
         let max_size = if is_horizontal { scr_width } else { scr_height };
         let opposite_size = if is_horizontal { scr_height } else { scr_width };
-        let position = thread_rng().gen_range(0.0..max_size);
+        let position = level_builder.rng().gen_range(0.0..max_size);
 
         let (mut start_x, mut end_x) = (-50.0, opposite_size + 50.0);
         let (mut start_y, mut end_y) = (position, position);
@@ -941,6 +962,7 @@ pub fn build() -> Level {
                 projectile_count: i * 3,
                 projectile_radius: 10.0,
                 projectile_speed: i as f64 * 10.0,
+                aimed: false,
             }
             .boxed(),
         ));
@@ -950,10 +972,10 @@ pub fn build() -> Level {
         400.0,
         Rectangle {
             center: Constant(scr_size * 0.5).boxed(),
-            size: FnWrap(|beat| {
-                DVec2::splat(beat * (beat - 27.0).max(0.0).powf(6.0)) * 0.005 + 100.0
-            })
-            .boxed(),
+            // stays put, then blows up to fill the screen
+            size: Keyframes::new(27.0, DVec2::splat(100.0), Easing::In(Curve::Expo))
+                .key(32.0, DVec2::splat(2600.0), Easing::Linear)
+                .boxed(),
             rotation: FnWrap(|beat| beat * 1.25f64.powf(beat - 27.0).max(1.0)).boxed(),
             lifetime: 32.0,
             warn_time: 4.0,
@@ -963,9 +985,8 @@ pub fn build() -> Level {
     ));
 
     level_builder.build(
-        songs::DURING_PRIDE_MONTH,
-        193.0,
-        2.4963,
-        &[94.0, 216.0, 318.0, 396.0],
+        Song::builtin("during_pride_month").unwrap(),
+        tempo(),
+        vec![94.0, 216.0, 318.0, 396.0],
     )
 }
@@ -1 +1,77 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    level::{Level, LevelInfo, Song},
+    levelfile::{self, LevelFile, LevelLoadError},
+    tempo::TempoMap,
+};
+
 pub mod dpm;
+
+/// Where level files are picked up from, relative to the working directory.
+pub const LEVEL_DIRECTORY: &str = "levels";
+pub const LEVEL_EXTENSION: &str = "epar";
+
+/// A level that can be picked from the level select.
+pub trait LevelDefinition {
+    fn info(&self) -> &LevelInfo;
+    fn tempo(&self) -> &TempoMap;
+    fn song(&self) -> Result<Song, LevelLoadError>;
+    /// Builds a fresh copy of the level. The same seed always gives the same level.
+    fn build(&self, seed: u64) -> Result<Level, LevelLoadError>;
+}
+
+/// A level defined in a level file. The file is read again every time the level is built.
+pub struct FileLevel {
+    path: PathBuf,
+    file: LevelFile,
+}
+impl FileLevel {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, LevelLoadError> {
+        let path = path.into();
+        Ok(Self {
+            file: levelfile::read(&path)?,
+            path,
+        })
+    }
+}
+impl LevelDefinition for FileLevel {
+    fn info(&self) -> &LevelInfo {
+        &self.file.info
+    }
+    fn tempo(&self) -> &TempoMap {
+        &self.file.tempo
+    }
+    fn song(&self) -> Result<Song, LevelLoadError> {
+        self.file.load_song()
+    }
+    fn build(&self, seed: u64) -> Result<Level, LevelLoadError> {
+        levelfile::load(&self.path, seed)
+    }
+}
+
+/// Every level built into the game, followed by every level file in `directory`.
+/// Level files that fail to load are reported and skipped.
+pub fn registry(directory: impl AsRef<Path>) -> Vec<Box<dyn LevelDefinition>> {
+    let mut levels: Vec<Box<dyn LevelDefinition>> = vec![Box::new(dpm::DuringPrideMonth::new())];
+
+    let mut paths: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+            .collect(),
+        Err(_) => vec![],
+    };
+    paths.sort();
+    for path in paths {
+        match FileLevel::new(&path) {
+            Ok(level) => levels.push(Box::new(level)),
+            Err(error) => eprintln!("skipping {}: {error}", path.display()),
+        }
+    }
+    levels
+}
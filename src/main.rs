@@ -5,41 +5,77 @@
 #![feature(const_fn_floating_point_arithmetic)]
 #![feature(never_type)]
 
-use std::error::Error;
+use std::{error::Error, ffi::OsString};
 
+use arena::window_conf;
 use draw::draw_screen_centered_text;
+use level::{Level, LevelSnapshot};
+use levels::{FileLevel, LevelDefinition};
 use macroquad::{
-    camera::{set_camera, set_default_camera, Camera2D},
-    color::{BLACK, GREEN, WHITE},
+    camera::{set_camera, set_default_camera},
+    color::{BLACK, GRAY, GREEN, WHITE},
+    input::{is_key_down, is_key_pressed, KeyCode},
     math::Vec2,
-    miniquad::window::screen_size,
     text::draw_text,
     time::get_time,
-    window::{clear_background, next_frame, request_new_screen_size},
+    window::{clear_background, next_frame},
+    Window,
 };
 use music::Music;
-use player::Player;
+use player::{Input, Player};
+use practice::Practice;
 use rand::{thread_rng, Rng};
 use res::sfx;
+use select::Choice;
 use soloud::{AudioExt, LoadExt, Wav};
 
+mod arena;
+mod arrayreader;
+mod backdrop;
+mod checker;
 mod collide;
 mod draw;
+mod easing;
+mod editor;
 mod ext;
 mod level;
+mod levelfile;
 mod levels;
 mod macros;
 mod music;
 mod obstacle;
+mod onsets;
+mod palette;
 mod player;
 mod polygon;
+mod practice;
 mod provider;
 mod res;
+mod select;
 mod shared;
+mod tempo;
 mod transform;
 
-#[macroquad::main("Exclusively Polygons Alongside Rhythms")]
-async fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), Box<dyn Error>> {
+    // checking levels and finding onsets happen before a window gets opened, so they can run
+    // anywhere
+    let mut args = std::env::args_os().skip(1).peekable();
+    if args.next_if(|arg| arg == "--check").is_some() {
+        return checker::check_levels(args.next());
+    }
+    if args.next_if(|arg| arg == "--onsets").is_some() {
+        return onsets::suggest_timings(args);
+    }
+    let args: Vec<OsString> = args.collect();
+    Window::from_config(window_conf(), async move {
+        if let Err(error) = game(args).await {
+            eprintln!("{error}");
+        }
+    });
+    Ok(())
+}
+
+async fn game(args: Vec<OsString>) -> Result<(), Box<dyn Error>> {
     // notification of removal for in class
     // blocks further action
     // loop {
@@ -60,15 +96,121 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // }
 
     // the actual game
-    let mut level = levels::dpm::build();
-    let mut player = Player::new();
     let mut music = Music::new()?;
+    // levels that are random per session keep their layout until the game is closed
+    let session_seed = thread_rng().gen();
+
+    // a level file can be passed in to play it straight away, or with `--edit` to edit it
+    let mut args = args.into_iter();
+    match args.next() {
+        Some(flag) if flag == "--edit" => {
+            let path = args.next().ok_or("--edit needs a level file")?;
+            editor::edit(path, &mut music).await?;
+        }
+        Some(path) => play(&FileLevel::new(path)?, &mut music, session_seed, None).await?,
+        None => {}
+    }
+    let levels = levels::registry(levels::LEVEL_DIRECTORY);
+    let mut selected = 0;
+    let mut practice = Practice::default();
+    loop {
+        match select::level_select(&levels, &mut music, &mut selected).await? {
+            Choice::Play => play(&*levels[selected], &mut music, session_seed, None).await?,
+            Choice::Practice => {
+                if practice::practice_setup(&*levels[selected], &mut practice).await? {
+                    play(
+                        &*levels[selected],
+                        &mut music,
+                        session_seed,
+                        Some(&practice),
+                    )
+                    .await?;
+                }
+            }
+        }
+    }
+}
+
+/// What the keyboard says the player should do this frame.
+fn read_input() -> Input {
+    Input {
+        left: is_key_down(KeyCode::A),
+        right: is_key_down(KeyCode::D),
+        up: is_key_down(KeyCode::W),
+        down: is_key_down(KeyCode::S),
+        dash: is_key_pressed(KeyCode::Space),
+    }
+}
+fn checkpoints_before(level: &Level, beat: f64) -> usize {
+    level
+        .checkpoints
+        .iter()
+        .take_while(|&&checkpoint| checkpoint <= beat)
+        .count()
+}
+/// Starts a level over from `beat`.
+fn restart(
+    definition: &dyn LevelDefinition,
+    seed: u64,
+    music: &mut Music,
+    music_track: &Wav,
+    beat: f64,
+    speed: f64,
+) -> Result<(Level, Player), Box<dyn Error>> {
+    let mut level = definition.build(seed)?;
+    music.play(music_track, &level.tempo);
+    music.seek(beat)?;
+    music.set_speed(speed)?;
+    let player = Player::new();
+    level.update_to(music.beat(), &player);
+    Ok((level, player))
+}
+/// Puts a level back the way it was when `snapshot` was taken, and plays from there.
+fn restore(
+    level: &mut Level,
+    snapshot: &LevelSnapshot,
+    music: &mut Music,
+    music_track: &Wav,
+    speed: f64,
+) -> Result<Player, Box<dyn Error>> {
+    level.restore(snapshot);
+    music.play(music_track, &level.tempo);
+    music.seek(snapshot.beat())?;
+    music.set_speed(speed)?;
+    Ok(Player::new())
+}
 
+/// Plays a level until it's completed and dismissed, or escape is pressed.
+/// With practice settings, the level can start later, loop, play slower and not hurt the player.
+async fn play(
+    definition: &dyn LevelDefinition,
+    music: &mut Music,
+    session_seed: u64,
+    practice: Option<&Practice>,
+) -> Result<(), Box<dyn Error>> {
+    let seed_policy = definition.info().seed_policy;
     let mut music_track = Wav::default();
-    music_track.load_mem(&level.song_data)?;
-    music.play(&music_track, level.bpm, level.start_time);
-    music.seek(0.0)?;
-    level.update_to(&music);
+    music_track.load_mem(&definition.song()?.data)?;
+    let speed = practice.map_or(1.0, |practice| practice.speed);
+
+    let mut current_checkpoint = match practice {
+        Some(practice) => practice.start,
+        None => definition.tempo().seconds_to_beats(0.01),
+    };
+    let (mut level, mut player) = restart(
+        definition,
+        seed_policy.seed(session_seed),
+        music,
+        &music_track,
+        current_checkpoint,
+        speed,
+    )?;
+    player.no_damage = practice.is_some_and(|practice| practice.no_damage);
+    // checkpoints before a practice start don't count
+    let mut next_checkpoint_index = checkpoints_before(&level, current_checkpoint);
+    let mut last_loop_hits = None;
+    // taken as checkpoints are crossed, so dying puts everything back how it was
+    let mut checkpoint_snapshot: Option<LevelSnapshot> = None;
 
     let mut checkpoint_sound = Wav::default();
     checkpoint_sound.load_mem(sfx::CHECKPOINT)?;
@@ -77,52 +219,94 @@ async fn main() -> Result<(), Box<dyn Error>> {
     death_sound.load_mem(sfx::DIE)?;
     death_sound.set_volume(3.0);
 
-    let mut camera = Camera2D::default();
-    let mut current_checkpoint = -(level.start_time - 0.01) * level.bpm / 60.0;
-    let mut next_checkpoint_index = 0;
-
     loop {
-        request_new_screen_size(800.0, 600.0);
+        arena::update_fullscreen();
+        // waits out the frame before leaving, so the level select doesn't see the same key press
+        if is_key_pressed(KeyCode::Escape) {
+            next_frame().await;
+            return Ok(());
+        }
         if music.finished() {
             set_default_camera();
             clear_background(BLACK);
 
             draw_screen_centered_text("Level complete!", 0.0, -90.0, 50, GREEN);
-            draw_screen_centered_text("Song by Jane Remover (Leroy)", 0.0, -50.0, 25, WHITE);
-            draw_screen_centered_text("`...during pride month?`", 0.0, -30.0, 25, WHITE);
+            draw_screen_centered_text(
+                &format!("Song by {}", level.info.artist),
+                0.0,
+                -50.0,
+                25,
+                WHITE,
+            );
+            draw_screen_centered_text(&format!("`{}`", level.info.name), 0.0, -30.0, 25, WHITE);
             draw_screen_centered_text("Made by Cerulity32K", 0.0, 10.0, 25, WHITE);
             draw_screen_centered_text("github.com/cerulity32k", 0.0, 30.0, 25, WHITE);
             draw_screen_centered_text("youtube.com/@cerulity32k", 0.0, 50.0, 25, WHITE);
             draw_screen_centered_text("Inspired by Just Shapes and Beats", 0.0, 90.0, 25, WHITE);
+            draw_screen_centered_text("Press Enter to continue", 0.0, 130.0, 20, GRAY);
+            if is_key_pressed(KeyCode::Enter) {
+                next_frame().await;
+                return Ok(());
+            }
             next_frame().await;
         } else {
-            let screen_size: Vec2 = screen_size().into();
-            camera.zoom = 2.0 / screen_size;
             level.shake = level.shake.abs();
             let shake_x = thread_rng().gen_range(-level.shake..=level.shake);
             let shake_y = thread_rng().gen_range(-level.shake..=level.shake);
-            camera.target =
-                screen_size / 2.0 + Vec2::new(shake_x as f32, shake_y as f32) + level.jerk;
-            set_camera(&camera);
+            clear_background(BLACK);
+            set_camera(&arena::camera(
+                Vec2::new(shake_x as f32, shake_y as f32) + level.jerk,
+                level.view,
+            ));
             let time = get_time();
             let beat = music.beat();
-            clear_background(level.background_color(beat));
-            level.update(beat);
-            if player.update(time, beat, &level) {
-                level = levels::dpm::build();
-                player = Player::new();
+            level.draw_background(beat);
+            level.update(beat, &player);
+            if player.update(time, beat, &level, read_input()) {
+                match &checkpoint_snapshot {
+                    Some(snapshot) => {
+                        player = restore(&mut level, snapshot, music, &music_track, speed)?
+                    }
+                    None => {
+                        (level, player) = restart(
+                            definition,
+                            seed_policy.seed(session_seed),
+                            music,
+                            &music_track,
+                            current_checkpoint,
+                            speed,
+                        )?
+                    }
+                }
                 player.last_hit_time = time;
-                music.play(&music_track, level.bpm, level.start_time);
-                music.seek(current_checkpoint + level.start_time * level.bpm / 60.0)?;
                 music.soloud.play(&death_sound);
-                level.update_to(&music);
                 next_frame().await;
                 continue;
             }
+            if let Some(practice) = practice {
+                if practice.end.is_some_and(|end| beat > end) {
+                    last_loop_hits = Some(player.hits);
+                    checkpoint_snapshot = None;
+                    current_checkpoint = practice.start;
+                    next_checkpoint_index = checkpoints_before(&level, current_checkpoint);
+                    (level, player) = restart(
+                        definition,
+                        seed_policy.seed(session_seed),
+                        music,
+                        &music_track,
+                        current_checkpoint,
+                        speed,
+                    )?;
+                    player.no_damage = practice.no_damage;
+                    next_frame().await;
+                    continue;
+                }
+            }
             if let Some(&next_checkpoint) = level.checkpoints.get(next_checkpoint_index) {
                 if beat > next_checkpoint {
                     current_checkpoint = next_checkpoint;
                     next_checkpoint_index += 1;
+                    checkpoint_snapshot = Some(level.snapshot());
                     println!("{next_checkpoint} {beat}");
                     music.soloud.play(&checkpoint_sound);
                 }
@@ -134,6 +318,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
             if next_checkpoint_index != 0 && current_checkpoint + 2.0 > beat {
                 draw_text("Checkpoint!", 0.0, 32.0, 32.0, WHITE);
             }
+            if let Some(practice) = practice {
+                let mut text = format!("Practice - {:.0}% speed", practice.speed * 100.0);
+                if practice.no_damage {
+                    text += &format!(" - {} hits", player.hits);
+                    if let Some(hits) = last_loop_hits {
+                        text += &format!(" ({hits} last loop)");
+                    }
+                }
+                draw_text(&text, 0.0, 64.0, 24.0, GRAY);
+            }
             next_frame().await;
         }
     }
@@ -2,28 +2,27 @@ use std::error::Error;
 
 use soloud::{Backend, Handle, Soloud, SoloudError, SoloudFlag, Wav};
 
+use crate::tempo::TempoMap;
+
 pub struct Music {
     pub soloud: Soloud,
     current_handle: Option<Handle>,
-    bpm: f64,
-    start_time: f64,
+    tempo: TempoMap,
 }
 impl Music {
     pub fn new() -> Result<Self, SoloudError> {
         Ok(Music {
             soloud: Soloud::new(SoloudFlag::empty(), Backend::Auto, 44100, 1024, 2)?,
             current_handle: None,
-            bpm: 0.0,
-            start_time: 0.0,
+            tempo: TempoMap::new(60.0, 0.0),
         })
     }
-    pub fn play(&mut self, source: &Wav, bpm: f64, start_time: f64) {
+    pub fn play(&mut self, source: &Wav, tempo: &TempoMap) {
         if let Some(handle) = self.current_handle {
             self.soloud.stop(handle);
         }
         self.current_handle = Some(self.soloud.play(source));
-        self.bpm = bpm;
-        self.start_time = start_time;
+        self.tempo = tempo.clone();
     }
     /*pub fn play_offset(&mut self, source: &Wav, bpm: f64, start_time: f64, offset: f64) {
         if let Some(handle) = self.current_handle {
@@ -33,14 +32,26 @@ impl Music {
         self.bpm = bpm;
         self.start_time = start_time - offset;
     }*/
-    pub fn seek(&mut self, beats: f64) -> Result<(), Box<dyn Error>> {
+    /// Jumps to a beat of the level, going by the tempo map of the current song.
+    pub fn seek(&mut self, beat: f64) -> Result<(), Box<dyn Error>> {
         if let Some(handle) = self.current_handle {
-            let seconds = beats / self.bpm * 60.0;
+            let seconds = self.tempo.beats_to_seconds(beat).max(0.0);
             self.soloud.seek(handle, seconds)?;
-            self.start_time -= seconds;
         }
         Ok(())
     }
+    /// Changes how fast the current song plays, with 1 being normal speed.
+    pub fn set_speed(&mut self, speed: f64) -> Result<(), SoloudError> {
+        if let Some(handle) = self.current_handle {
+            self.soloud.set_relative_play_speed(handle, speed as f32)?;
+        }
+        Ok(())
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        if let Some(handle) = self.current_handle {
+            self.soloud.set_pause(handle, paused);
+        }
+    }
     pub fn finished(&self) -> bool {
         if let Some(handle) = self.current_handle {
             !self.soloud.is_valid_voice_handle(handle)
@@ -50,8 +61,9 @@ impl Music {
     }
     pub fn beat(&self) -> f64 {
         if let Some(handle) = self.current_handle {
-            let raw_time = self.soloud.stream_time(handle);
-            (raw_time - self.start_time) * self.bpm / 60.0
+            // the position in the audio, which keeps up with seeking and play speed
+            let raw_time = self.soloud.stream_position(handle);
+            self.tempo.seconds_to_beats(raw_time)
         } else {
             0.0
         }
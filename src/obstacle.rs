@@ -1,27 +1,43 @@
-use std::f64::consts::TAU as TAU64;
+use std::{f64::consts::TAU as TAU64, rc::Rc};
 
 use jut::extensions::Boxed;
 use macroquad::{
     color::{Color, WHITE},
-    math::{dvec2, DVec2},
-    shapes::draw_circle,
-    window::{screen_height, screen_width},
+    math::{dvec2, vec3, DAffine2, DVec2, Mat4, Quat, Vec2},
+    shapes::{draw_arc, draw_circle},
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
+    arena,
+    backdrop::{DynBackdrop, Fill},
     collide, draw,
+    easing::{Curve, Easing},
     ext::ColorExt,
-    provider::{Constant, Provider, Velocity},
-    shared::Shared,
+    levelfile::{BehaviourDefinition, Value},
+    palette::{Slot, Swap, Swatch},
+    polygon::{self, presets::Preset, Polygon},
+    provider::{Constant, Linear, Provider, Velocity},
+    shared::{Shared, Target, View},
 };
 
 pub struct Obstacle {
     pub offset: f64,
+    /// Which of the palette's colors it's drawn in.
+    pub slot: Slot,
     pub behaviour: DynObstacleBehaviour,
 }
 impl Obstacle {
     pub fn new(offset: f64, behaviour: DynObstacleBehaviour) -> Self {
-        Self { offset, behaviour }
+        Self {
+            offset,
+            slot: Slot::default(),
+            behaviour,
+        }
+    }
+    pub fn slot(mut self, slot: Slot) -> Self {
+        self.slot = slot;
+        self
     }
     pub fn update(&mut self, shared: &mut Shared, beat: f64) {
         if self.should_enable(beat) {
@@ -31,9 +47,10 @@ impl Obstacle {
     pub fn should_enable(&self, beat: f64) -> bool {
         self.behaviour.should_enable(beat - self.offset)
     }
-    pub fn draw(&self, main_color: Color, beat: f64) {
+    pub fn draw(&self, swatch: &Swatch, beat: f64) {
         if self.should_enable(beat) {
-            self.behaviour.draw(main_color, beat - self.offset);
+            self.behaviour
+                .draw_with_swatch(swatch, swatch[self.slot], beat - self.offset);
         }
     }
     pub fn collides(&self, beat: f64, player_position: DVec2, player_radius: f64) -> bool {
@@ -48,6 +65,15 @@ impl Obstacle {
     pub fn kill(&mut self, shared: &mut Shared, beat: f64) {
         self.behaviour.kill(shared, beat - self.offset);
     }
+    /// The behaviour's level file representation, with the slot added unless it's the default.
+    pub fn serialize(&self) -> Option<BehaviourDefinition> {
+        let definition = self.behaviour.serialize()?;
+        Some(if self.slot == Slot::default() {
+            definition
+        } else {
+            definition.field("slot", self.slot)
+        })
+    }
 }
 impl Clone for Obstacle {
     fn clone(&self) -> Self {
@@ -64,6 +90,10 @@ pub type DynObstacleBehaviour = Box<dyn ObstacleBehaviour + 'static>;
 pub trait ObstacleBehaviour {
     fn update(&mut self, shared: &mut Shared, beat: f64) {}
     fn draw(&self, main_color: Color, beat: f64);
+    /// Like `draw`, with every slot's color at hand for behaviours that draw other obstacles.
+    fn draw_with_swatch(&self, swatch: &Swatch, main_color: Color, beat: f64) {
+        self.draw(main_color, beat)
+    }
     fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
         false
     }
@@ -73,6 +103,12 @@ pub trait ObstacleBehaviour {
     }
     fn should_kill(&self, beat: f64) -> bool;
     fn kill(&mut self, shared: &mut Shared, beat: f64) {}
+    /// Takes whatever randomness this behaviour needs, so the level's seed decides it.
+    fn seed(&mut self, rng: &mut StdRng) {}
+    /// Converts this behaviour into its level file representation, if it has one.
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        None
+    }
 }
 macro_rules! builder {
     ($ident:tt: $type:ty) => {
@@ -83,18 +119,81 @@ macro_rules! builder {
         }
     };
 }
+pub(crate) use builder;
 pub mod lasers {
-    use std::f64::consts::PI;
-
     use macroquad::{
         color::{Color, WHITE},
         math::{DVec2, Vec2},
         shapes::draw_line,
     };
 
-    use crate::{collide, ext::ColorExt, shared::Shared};
+    use jut::extensions::Boxed;
+
+    use crate::{
+        collide,
+        ext::ColorExt,
+        levelfile::BehaviourDefinition,
+        provider::{Constant, Provider},
+        shared::Shared,
+    };
+
+    use super::{warn_color, DynObstacleBehaviour, ObstacleBehaviour};
+
+    /// How far along a slamming laser reaches: a fifth of the way while warning,
+    /// all the way during its lifetime, then back to nothing while leaving.
+    fn slam_factor(beat: f64, warn_time: f64, lifetime: f64, leave_time: f64) -> f64 {
+        if beat < 0.0 {
+            (beat / warn_time + 1.0) * 0.2
+        } else if beat < lifetime {
+            1.0
+        } else {
+            let normalized = (beat - lifetime) / leave_time;
+            1.0 - normalized * normalized
+        }
+    }
+    /// Draws a slamming laser: a pulsing warning along its whole length,
+    /// then the laser itself out to `factor`, flashing white after it slams.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_slam(
+        start: DVec2,
+        end: DVec2,
+        thickness: f64,
+        factor: f64,
+        main_color: Color,
+        beat: f64,
+        warn_time: f64,
+        flash_time: f64,
+    ) {
+        let start = start.as_vec2();
+        let end = end.as_vec2();
+        let lerp = start.lerp(end, factor as f32);
+
+        let back_color = if beat > 0.0 {
+            Color {
+                a: 0.0,
+                ..main_color
+            }
+        } else {
+            warn_color(main_color, beat, warn_time)
+        };
+
+        let flash = if 0.0 < beat && beat < flash_time {
+            1.0 - beat / flash_time
+        } else {
+            0.0
+        };
+        let front_color = main_color.mix(WHITE, flash);
 
-    use super::{DynObstacleBehaviour, ObstacleBehaviour};
+        draw_line(start.x, start.y, end.x, end.y, thickness as f32, back_color);
+        draw_line(
+            start.x,
+            start.y,
+            lerp.x,
+            lerp.y,
+            thickness as f32,
+            front_color,
+        );
+    }
 
     #[derive(Clone, Copy)]
     pub struct SlamLaser {
@@ -139,14 +238,7 @@ pub mod lasers {
         builder!(jerk: Vec2);
 
         pub fn lerp_factor(&self, beat: f64) -> f64 {
-            if beat < 0.0 {
-                (beat / self.warn_time + 1.0) * 0.2
-            } else if beat < self.lifetime {
-                1.0
-            } else {
-                let normalized = (beat - self.lifetime) / self.leave_time;
-                1.0 - normalized * normalized
-            }
+            slam_factor(beat, self.warn_time, self.lifetime, self.leave_time)
         }
     }
     impl ObstacleBehaviour for SlamLaser {
@@ -154,40 +246,15 @@ pub mod lasers {
             Box::new(*self)
         }
         fn draw(&self, main_color: Color, beat: f64) {
-            let start = self.start_pos.as_vec2();
-            let end = self.end_pos.as_vec2();
-            let lerp = start.lerp(end, self.lerp_factor(beat) as f32);
-
-            let mut back_color = main_color.mix(WHITE, (beat * PI * 2.0).sin() * 0.5 + 0.5);
-            if beat > 0.0 {
-                back_color.a = 0.0;
-            } else {
-                back_color = back_color.faded();
-                back_color.a *= (beat / self.warn_time + 1.0).min(1.0) as f32 * 1.5;
-            }
-
-            let factor = if 0.0 < beat && beat < self.flash_time {
-                1.0 - beat / self.flash_time
-            } else {
-                0.0
-            };
-            let front_color = main_color.mix(WHITE, factor);
-
-            draw_line(
-                start.x,
-                start.y,
-                end.x,
-                end.y,
-                self.thickness as f32,
-                back_color,
-            );
-            draw_line(
-                start.x,
-                start.y,
-                lerp.x,
-                lerp.y,
-                self.thickness as f32,
-                front_color,
+            draw_slam(
+                self.start_pos,
+                self.end_pos,
+                self.thickness,
+                self.lerp_factor(beat),
+                main_color,
+                beat,
+                self.warn_time,
+                self.flash_time,
             );
         }
         fn update(&mut self, shared: &mut Shared, beat: f64) {
@@ -212,6 +279,20 @@ pub mod lasers {
                 self.thickness,
             )
         }
+        fn serialize(&self) -> Option<BehaviourDefinition> {
+            Some(
+                BehaviourDefinition::new("SlamLaser")
+                    .field("start_pos", self.start_pos)
+                    .field("end_pos", self.end_pos)
+                    .field("warn_time", self.warn_time)
+                    .field("lifetime", self.lifetime)
+                    .field("leave_time", self.leave_time)
+                    .field("thickness", self.thickness)
+                    .field("flash_time", self.flash_time)
+                    .field("shake", self.shake)
+                    .field("jerk", self.jerk),
+            )
+        }
     }
 
     #[derive(Clone, Copy)]
@@ -281,13 +362,14 @@ pub mod lasers {
             let start = self.start_pos.as_vec2();
             let end = self.end_pos.as_vec2();
 
-            let mut back_color = main_color.mix(WHITE, (beat * PI * 2.0).sin() * 0.5 + 0.5);
-            if beat > 0.0 {
-                back_color.a = 0.0;
+            let back_color = if beat > 0.0 {
+                Color {
+                    a: 0.0,
+                    ..main_color
+                }
             } else {
-                back_color = back_color.faded();
-                back_color.a *= (beat / self.warn_time + 1.0).min(1.0) as f32 * 1.5;
-            }
+                warn_color(main_color, beat, self.warn_time)
+            };
 
             let factor = if 0.0 < beat && beat < self.flash_time {
                 1.0 - beat / self.flash_time
@@ -336,6 +418,138 @@ pub mod lasers {
                     self.thickness * self.thickness_factor(beat),
                 )
         }
+        fn serialize(&self) -> Option<BehaviourDefinition> {
+            Some(
+                BehaviourDefinition::new("WidenLaser")
+                    .field("start_pos", self.start_pos)
+                    .field("end_pos", self.end_pos)
+                    .field("warn_time", self.warn_time)
+                    .field("grow_time", self.grow_time)
+                    .field("lifetime", self.lifetime)
+                    .field("shrink_time", self.shrink_time)
+                    .field("thickness", self.thickness)
+                    .field("flash_time", self.flash_time)
+                    .field("shake", self.shake)
+                    .field("jerk", self.jerk),
+            )
+        }
+    }
+
+    /// A laser sticking out of `pivot` at `angle` radians, slamming like a [`SlamLaser`].
+    /// Every part of it is a provider, so it can sweep, spin, grow and move while it's out.
+    #[derive(Clone)]
+    pub struct RotatingLaser {
+        pub pivot: Box<dyn Provider<DVec2>>,
+        /// Clockwise from pointing right.
+        pub angle: Box<dyn Provider<f64>>,
+        pub length: Box<dyn Provider<f64>>,
+        pub thickness: Box<dyn Provider<f64>>,
+
+        pub warn_time: f64,
+        pub lifetime: f64,
+        pub leave_time: f64,
+        pub flash_time: f64,
+
+        pub shake: f64,
+        pub jerk: Vec2,
+        pub slam_done: bool,
+    }
+    impl Default for RotatingLaser {
+        fn default() -> Self {
+            Self {
+                pivot: Constant(DVec2::ZERO).boxed(),
+                angle: Constant(0.0).boxed(),
+                length: Constant(1000.0).boxed(),
+                thickness: Constant(50.0).boxed(),
+                warn_time: 2.0,
+                lifetime: 2.0,
+                leave_time: 1.0,
+                flash_time: 0.5,
+                shake: 0.0,
+                jerk: Vec2::ZERO,
+                slam_done: false,
+            }
+        }
+    }
+    impl RotatingLaser {
+        builder!(pivot: Box<dyn Provider<DVec2>>);
+        builder!(angle: Box<dyn Provider<f64>>);
+        builder!(length: Box<dyn Provider<f64>>);
+        builder!(thickness: Box<dyn Provider<f64>>);
+        builder!(warn_time: f64);
+        builder!(lifetime: f64);
+        builder!(leave_time: f64);
+        builder!(flash_time: f64);
+        builder!(shake: f64);
+        builder!(jerk: Vec2);
+
+        /// Where the laser would end if it was all the way out.
+        pub fn end(&self, beat: f64) -> DVec2 {
+            self.pivot.get(beat) + DVec2::from_angle(self.angle.get(beat)) * self.length.get(beat)
+        }
+        pub fn lerp_factor(&self, beat: f64) -> f64 {
+            slam_factor(beat, self.warn_time, self.lifetime, self.leave_time)
+        }
+    }
+    impl ObstacleBehaviour for RotatingLaser {
+        fn box_clone(&self) -> DynObstacleBehaviour {
+            self.clone().boxed()
+        }
+        fn draw(&self, main_color: Color, beat: f64) {
+            draw_slam(
+                self.pivot.get(beat),
+                self.end(beat),
+                self.thickness.get(beat),
+                self.lerp_factor(beat),
+                main_color,
+                beat,
+                self.warn_time,
+                self.flash_time,
+            );
+        }
+        fn update(&mut self, shared: &mut Shared, beat: f64) {
+            let target = shared.target();
+            self.pivot.update(target, beat);
+            self.angle.update(target, beat);
+            self.length.update(target, beat);
+            self.thickness.update(target, beat);
+            if beat > 0.0 && !self.slam_done {
+                self.slam_done = true;
+                shared.add_jerk(self.jerk);
+                shared.add_shake(self.shake);
+            }
+        }
+        fn should_enable(&self, beat: f64) -> bool {
+            -self.warn_time < beat && beat < self.lifetime + self.leave_time
+        }
+        fn should_kill(&self, beat: f64) -> bool {
+            beat > self.lifetime + self.leave_time
+        }
+        fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+            let pivot = self.pivot.get(beat);
+            collide::circle_line(
+                circle_pos,
+                circle_radius,
+                pivot,
+                pivot.lerp(self.end(beat), self.lerp_factor(beat)),
+                self.thickness.get(beat),
+            )
+        }
+        fn serialize(&self) -> Option<BehaviourDefinition> {
+            Some(
+                BehaviourDefinition::new("RotatingLaser")
+                    .field("pivot", self.pivot.serialize()?)
+                    .field("angle", self.angle.serialize()?)
+                    .field("length", self.length.serialize()?)
+                    .field("thickness", self.thickness.serialize()?)
+                    .field("warn_time", self.warn_time)
+                    .field("lifetime", self.lifetime)
+                    .field("leave_time", self.leave_time)
+                    .field("flash_time", self.flash_time)
+                    .field("shake", self.shake)
+                    .field("jerk", self.jerk),
+            )
+        }
     }
 }
 
@@ -348,6 +562,8 @@ pub struct Bomb {
     pub projectile_count: usize,
     pub projectile_radius: f64,
     pub projectile_speed: f64,
+    /// Turns the burst so one projectile flies straight at the player.
+    pub aimed: bool,
 }
 impl Bomb {
     pub fn pos(&self, beat: f64) -> DVec2 {
@@ -389,8 +605,14 @@ impl ObstacleBehaviour for Bomb {
     }
     fn kill(&mut self, shared: &mut Shared, beat: f64) {
         let pos = self.pos(beat);
+        let aim = if self.aimed {
+            let direction = shared.target().position - pos;
+            direction.x.atan2(direction.y)
+        } else {
+            0.0
+        };
         for i in 0..self.projectile_count {
-            let frac = i as f64 / self.projectile_count as f64 * TAU64;
+            let frac = i as f64 / self.projectile_count as f64 * TAU64 + aim;
             let proj = Circle::pellet(
                 self.projectile_radius,
                 pos,
@@ -399,6 +621,19 @@ impl ObstacleBehaviour for Bomb {
             shared.add_obstacle(Obstacle::new(0.0, proj.boxed()));
         }
     }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Bomb")
+                .field("start_position", self.start_position)
+                .field("end_position", self.end_position)
+                .field("lifetime", self.lifetime)
+                .field("radius_per_beat", self.radius_per_beat)
+                .field("projectile_count", self.projectile_count)
+                .field("projectile_radius", self.projectile_radius)
+                .field("projectile_speed", self.projectile_speed)
+                .optional_field("aimed", self.aimed.then_some(true)),
+        )
+    }
 }
 #[derive(Clone)]
 pub struct Circle<F: Provider<DVec2>> {
@@ -432,6 +667,9 @@ impl<F: Provider<DVec2> + Clone + 'static> ObstacleBehaviour for Circle<F> {
     fn box_clone(&self) -> DynObstacleBehaviour {
         self.clone().boxed()
     }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        self.position.update(shared.target(), beat);
+    }
     fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
         collide::circle_circle(
             circle_pos,
@@ -454,17 +692,390 @@ impl<F: Provider<DVec2> + Clone + 'static> ObstacleBehaviour for Circle<F> {
                 let pos = self.position.get(beat);
                 pos.x < -self.radius
                     || pos.y < -self.radius
-                    || pos.x > screen_width() as f64 + self.radius
-                    || pos.y > screen_height() as f64 + self.radius
+                    || pos.x > arena::WIDTH + self.radius
+                    || pos.y > arena::HEIGHT + self.radius
+            }
+        }
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Circle")
+                .field("position", self.position.serialize()?)
+                .field("radius", self.radius)
+                .optional_field("lifetime", self.lifetime),
+        )
+    }
+}
+
+/// A ring growing out from `center`, like a shockwave.
+/// It can have evenly spaced gaps in it to slip through.
+#[derive(Clone)]
+pub struct Ring {
+    pub center: Box<dyn Provider<DVec2>>,
+    pub inner_radius: Box<dyn Provider<f64>>,
+    pub outer_radius: Box<dyn Provider<f64>>,
+    pub gap_count: usize,
+    /// How wide each gap is, in radians.
+    pub gap_width: f64,
+    /// Turns the gaps, in radians clockwise. The first gap starts out centered on the right.
+    pub rotation: Box<dyn Provider<f64>>,
+
+    pub warn_time: f64,
+    pub lifetime: f64,
+    pub flash_time: f64,
+
+    pub shake: f64,
+    pub spawned: bool,
+}
+impl Default for Ring {
+    fn default() -> Self {
+        Self {
+            center: Constant(DVec2::ZERO).boxed(),
+            inner_radius: Linear::new(-40.0, 100.0).boxed(),
+            outer_radius: Linear::new(0.0, 100.0).boxed(),
+            gap_count: 0,
+            gap_width: 0.5,
+            rotation: Constant(0.0).boxed(),
+            warn_time: 2.0,
+            lifetime: 8.0,
+            flash_time: 0.5,
+            shake: 0.0,
+            spawned: false,
+        }
+    }
+}
+impl Ring {
+    builder!(center: Box<dyn Provider<DVec2>>);
+    builder!(inner_radius: Box<dyn Provider<f64>>);
+    builder!(outer_radius: Box<dyn Provider<f64>>);
+    builder!(gap_count: usize);
+    builder!(gap_width: f64);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(warn_time: f64);
+    builder!(lifetime: f64);
+    builder!(flash_time: f64);
+    builder!(shake: f64);
+
+    fn radii(&self, beat: f64) -> (f64, f64) {
+        let outer = self.outer_radius.get(beat).max(0.0);
+        (self.inner_radius.get(beat).clamp(0.0, outer), outer)
+    }
+    /// The solid parts between the gaps, as start angles and spans.
+    /// `None` if there aren't any gaps, so it's solid all the way around.
+    fn arcs(&self, beat: f64) -> Option<impl Iterator<Item = (f64, f64)>> {
+        if self.gap_count == 0 || self.gap_width <= 0.0 {
+            return None;
+        }
+        let spacing = TAU64 / self.gap_count as f64;
+        let span = (spacing - self.gap_width).max(0.0);
+        let first = self.rotation.get(beat) + self.gap_width * 0.5;
+        Some((0..self.gap_count).map(move |i| (first + i as f64 * spacing, span)))
+    }
+}
+impl ObstacleBehaviour for Ring {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        let target = shared.target();
+        self.center.update(target, beat);
+        self.inner_radius.update(target, beat);
+        self.outer_radius.update(target, beat);
+        self.rotation.update(target, beat);
+        if beat > 0.0 && !self.spawned {
+            self.spawned = true;
+            shared.add_shake(self.shake);
+        }
+    }
+    fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+        if beat <= 0.0 {
+            return false;
+        }
+        let center = self.center.get(beat);
+        let (inner, outer) = self.radii(beat);
+        match self.arcs(beat) {
+            None => collide::circle_annulus(circle_pos, circle_radius, center, inner, outer),
+            Some(mut arcs) => arcs.any(|(start, span)| {
+                collide::circle_annular_sector(
+                    circle_pos,
+                    circle_radius,
+                    center,
+                    inner,
+                    outer,
+                    start,
+                    span,
+                )
+            }),
+        }
+    }
+    fn draw(&self, main_color: Color, beat: f64) {
+        let center = self.center.get(beat).as_vec2();
+        let (color, (inner, outer)) = if beat < 0.0 {
+            // pulses where the ring starts out, at least big enough to be seen
+            let color = warn_color(main_color, beat, self.warn_time);
+            let (inner, outer) = self.radii(0.0);
+            (color, (inner, outer.max(inner + 10.0)))
+        } else {
+            let flash = if beat < self.flash_time {
+                1.0 - beat / self.flash_time
+            } else {
+                0.0
+            };
+            (main_color.mix(WHITE, flash), self.radii(beat))
+        };
+        let arcs: Vec<(f64, f64)> = match self.arcs(beat) {
+            Some(arcs) => arcs.collect(),
+            None => vec![(0.0, TAU64)],
+        };
+        for (start, span) in arcs {
+            draw_arc(
+                center.x,
+                center.y,
+                64,
+                inner as f32,
+                start.to_degrees() as f32,
+                (outer - inner) as f32,
+                span.to_degrees() as f32,
+                color,
+            );
+        }
+    }
+    fn should_enable(&self, beat: f64) -> bool {
+        -self.warn_time < beat && beat < self.lifetime
+    }
+    fn should_kill(&self, beat: f64) -> bool {
+        beat > self.lifetime
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Ring")
+                .field("center", self.center.serialize()?)
+                .field("inner_radius", self.inner_radius.serialize()?)
+                .field("outer_radius", self.outer_radius.serialize()?)
+                .field("gap_count", self.gap_count)
+                .field("gap_width", self.gap_width)
+                .field("rotation", self.rotation.serialize()?)
+                .field("warn_time", self.warn_time)
+                .field("lifetime", self.lifetime)
+                .field("flash_time", self.flash_time)
+                .field("shake", self.shake),
+        )
+    }
+}
+
+/// Which edge of the arena something comes in from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+impl Edge {
+    pub const ALL: [Edge; 4] = [Edge::Left, Edge::Right, Edge::Top, Edge::Bottom];
+    pub fn name(self) -> &'static str {
+        match self {
+            Edge::Left => "left",
+            Edge::Right => "right",
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+        }
+    }
+}
+
+/// A wall scrolling across the whole arena, with gaps to get through.
+#[derive(Clone)]
+pub struct Wall {
+    pub from: Edge,
+    /// How far it moves every beat.
+    pub speed: Box<dyn Provider<f64>>,
+    pub thickness: f64,
+    /// Gaps as `(center, width)`, in arena units along the wall from the top or left.
+    pub gaps: Vec<DVec2>,
+    /// Repeats a gap of `gap_width` this far apart along the whole wall, if it's above 0.
+    pub gap_spacing: f64,
+    pub gap_width: f64,
+    /// Where the first repeated gap is centered.
+    pub gap_offset: f64,
+    pub warn_time: f64,
+
+    travelled: f64,
+    last_beat: f64,
+}
+impl Default for Wall {
+    fn default() -> Self {
+        Self {
+            from: Edge::Left,
+            speed: Constant(100.0).boxed(),
+            thickness: 40.0,
+            gaps: vec![],
+            gap_spacing: 0.0,
+            gap_width: 100.0,
+            gap_offset: 0.0,
+            warn_time: 2.0,
+            travelled: 0.0,
+            last_beat: 0.0,
+        }
+    }
+}
+impl Wall {
+    /// How far apart speed changes are followed, like [`crate::provider::Homing`] turns.
+    const STEP: f64 = 1.0 / 16.0;
+
+    builder!(from: Edge);
+    builder!(speed: Box<dyn Provider<f64>>);
+    builder!(thickness: f64);
+    builder!(gaps: Vec<DVec2>);
+    builder!(gap_spacing: f64);
+    builder!(gap_width: f64);
+    builder!(gap_offset: f64);
+    builder!(warn_time: f64);
+
+    /// How far the wall goes to cross the arena, and how long it is.
+    fn extents(&self) -> (f64, f64) {
+        match self.from {
+            Edge::Left | Edge::Right => (arena::WIDTH, arena::HEIGHT),
+            Edge::Top | Edge::Bottom => (arena::HEIGHT, arena::WIDTH),
+        }
+    }
+    fn travelled(&self, beat: f64) -> f64 {
+        self.travelled + self.speed.get(beat) * (beat - self.last_beat).max(0.0)
+    }
+    /// The solid parts of the wall, as starts and ends along it.
+    fn segments(&self) -> Vec<(f64, f64)> {
+        let (_, length) = self.extents();
+        let mut gaps: Vec<(f64, f64)> = self
+            .gaps
+            .iter()
+            .map(|gap| (gap.x - gap.y * 0.5, gap.x + gap.y * 0.5))
+            .collect();
+        if self.gap_spacing > 0.0 {
+            let mut center = self.gap_offset.rem_euclid(self.gap_spacing) - self.gap_spacing;
+            while center - self.gap_width * 0.5 < length {
+                gaps.push((center - self.gap_width * 0.5, center + self.gap_width * 0.5));
+                center += self.gap_spacing;
+            }
+        }
+        gaps.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut segments = vec![];
+        let mut start = 0.0;
+        for (gap_start, gap_end) in gaps {
+            if gap_start > start {
+                segments.push((start, gap_start.min(length)));
+            }
+            start = start.max(gap_end);
+        }
+        if start < length {
+            segments.push((start, length));
+        }
+        segments.retain(|(start, end)| end > start);
+        segments
+    }
+    /// The solid parts of the wall as rectangle centers and sizes, `depth` into the arena.
+    fn rectangles(&self, depth: f64) -> Vec<(DVec2, DVec2)> {
+        let (across, _) = self.extents();
+        let depth = match self.from {
+            Edge::Left | Edge::Top => depth,
+            Edge::Right | Edge::Bottom => across - depth,
+        };
+        self.segments()
+            .into_iter()
+            .map(|(start, end)| {
+                let (middle, length) = ((start + end) * 0.5, end - start);
+                match self.from {
+                    Edge::Left | Edge::Right => {
+                        (dvec2(depth, middle), dvec2(self.thickness, length))
+                    }
+                    Edge::Top | Edge::Bottom => {
+                        (dvec2(middle, depth), dvec2(length, self.thickness))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+impl ObstacleBehaviour for Wall {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        self.speed.update(shared.target(), beat);
+        while self.last_beat < beat {
+            let dt = (beat - self.last_beat).min(Self::STEP);
+            self.travelled += self.speed.get(self.last_beat) * dt;
+            self.last_beat += dt;
+        }
+    }
+    fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+        beat > 0.0
+            && (self.rectangles(self.travelled(beat) - self.thickness * 0.5))
+                .into_iter()
+                .any(|(center, size)| {
+                    collide::circle_rectangle(circle_pos, circle_radius, center, size, 0.0)
+                })
+    }
+    fn draw(&self, main_color: Color, beat: f64) {
+        if beat < 0.0 {
+            // shows where the gaps are going to be along the edge it comes from
+            let color = warn_color(main_color, beat, self.warn_time);
+            for (center, size) in self.rectangles(self.thickness * 0.25) {
+                let size = match self.from {
+                    Edge::Left | Edge::Right => dvec2(size.x * 0.5, size.y),
+                    Edge::Top | Edge::Bottom => dvec2(size.x, size.y * 0.5),
+                };
+                draw::rotated_rect(center.as_vec2(), size.as_vec2(), 0.0, color);
+            }
+        } else {
+            for (center, size) in self.rectangles(self.travelled(beat) - self.thickness * 0.5) {
+                draw::rotated_rect(center.as_vec2(), size.as_vec2(), 0.0, main_color);
             }
         }
     }
+    fn should_enable(&self, beat: f64) -> bool {
+        beat > -self.warn_time
+    }
+    fn should_kill(&self, beat: f64) -> bool {
+        let (across, _) = self.extents();
+        // it can also go back out the way it came
+        let travelled = self.travelled(beat);
+        travelled > across + self.thickness || travelled < 0.0
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Wall")
+                .field("from", self.from)
+                .field("speed", self.speed.serialize()?)
+                .field("thickness", self.thickness)
+                .field("gaps", self.gaps.clone())
+                .field("gap_spacing", self.gap_spacing)
+                .field("gap_width", self.gap_width)
+                .field("gap_offset", self.gap_offset)
+                .field("warn_time", self.warn_time),
+        )
+    }
 }
 
-pub struct SetForeground(pub Box<dyn Provider<Color>>);
-impl ObstacleBehaviour for SetForeground {
+/// Swaps some of the palette's colors, blending from the old ones over `duration` beats.
+#[derive(Clone, Default)]
+pub struct SetPalette {
+    pub colors: Vec<(Slot, Box<dyn Provider<Color>>)>,
+    pub duration: f64,
+    pub easing: Easing,
+}
+impl SetPalette {
+    /// Swaps `slot` to `color`, replacing any color already set for it.
+    pub fn color(mut self, slot: Slot, color: Box<dyn Provider<Color>>) -> Self {
+        self.colors.retain(|(other, _)| *other != slot);
+        self.colors.push((slot, color));
+        self
+    }
+    builder!(duration: f64);
+    builder!(easing: Easing);
+}
+impl ObstacleBehaviour for SetPalette {
     fn box_clone(&self) -> DynObstacleBehaviour {
-        Self(self.0.box_clone()).boxed()
+        self.clone().boxed()
     }
     fn draw(&self, _main_color: Color, _beat: f64) {}
     fn should_kill(&self, beat: f64) -> bool {
@@ -472,14 +1083,39 @@ impl ObstacleBehaviour for SetForeground {
     }
     fn update(&mut self, shared: &mut Shared, beat: f64) {
         if beat > 0.0 {
-            shared.set_foreground(self.0.box_clone());
+            for (slot, color) in &self.colors {
+                shared.swap_color(Swap {
+                    slot: *slot,
+                    color: color.box_clone(),
+                    duration: self.duration,
+                    easing: self.easing,
+                });
+            }
+        }
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        let mut definition = BehaviourDefinition::new("SetPalette");
+        for (slot, color) in &self.colors {
+            definition = definition.field(slot.name(), color.serialize()?);
         }
+        Some(
+            definition
+                .field("duration", self.duration)
+                .field("easing", self.easing),
+        )
+    }
+}
+/// Switches the level's backdrop. Back to a plain [`Fill`] by default.
+#[derive(Clone)]
+pub struct SetBackdrop(pub DynBackdrop);
+impl Default for SetBackdrop {
+    fn default() -> Self {
+        Self(Fill.boxed())
     }
 }
-pub struct SetBackground(pub Box<dyn Provider<Color>>);
-impl ObstacleBehaviour for SetBackground {
+impl ObstacleBehaviour for SetBackdrop {
     fn box_clone(&self) -> DynObstacleBehaviour {
-        Self(self.0.box_clone()).boxed()
+        self.clone().boxed()
     }
     fn draw(&self, _main_color: Color, _beat: f64) {}
     fn should_kill(&self, beat: f64) -> bool {
@@ -487,9 +1123,15 @@ impl ObstacleBehaviour for SetBackground {
     }
     fn update(&mut self, shared: &mut Shared, beat: f64) {
         if beat > 0.0 {
-            shared.set_background(self.0.box_clone());
+            shared.set_backdrop(self.0.clone());
         }
     }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("SetBackdrop")
+                .field("backdrop", Value::Obstacle(self.0.serialize()?)),
+        )
+    }
 }
 pub struct Shake(pub f64);
 impl ObstacleBehaviour for Shake {
@@ -504,33 +1146,131 @@ impl ObstacleBehaviour for Shake {
         println!("{}", self.0);
         shared.add_shake(self.0);
     }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(BehaviourDefinition::new("Shake").field("amount", self.0))
+    }
 }
-pub struct Rectangle {
-    pub center: Box<dyn Provider<DVec2>>,
-    pub size: Box<dyn Provider<DVec2>>,
+/// Zooms, turns and pans the camera for `duration` beats, easing in from neutral and back out
+/// over `blend_time` beats at either end. Overlapping cameras stack.
+#[derive(Clone)]
+pub struct Camera {
+    /// How much closer the camera is, 2 being twice as close.
+    pub zoom: Box<dyn Provider<f64>>,
+    /// In radians, clockwise. Player controls turn with it, so they stay the same on screen.
+    pub rotation: Box<dyn Provider<f64>>,
+    /// The point in the arena the camera looks at.
+    pub pan: Box<dyn Provider<DVec2>>,
+    pub duration: f64,
+    pub blend_time: f64,
+    pub easing: Easing,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            zoom: Constant(1.0).boxed(),
+            rotation: Constant(0.0).boxed(),
+            pan: Constant(arena::CENTER).boxed(),
+            duration: 4.0,
+            blend_time: 1.0,
+            easing: Easing::InOut(Curve::Quad),
+        }
+    }
+}
+impl Camera {
+    builder!(zoom: Box<dyn Provider<f64>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(pan: Box<dyn Provider<DVec2>>);
+    builder!(duration: f64);
+    builder!(blend_time: f64);
+    builder!(easing: Easing);
+
+    /// How far from neutral the camera is, going from 0 to 1 and back.
+    fn amount(&self, beat: f64) -> f64 {
+        if self.blend_time <= 0.0 {
+            return 1.0;
+        }
+        let edge = beat.min(self.duration - beat);
+        self.easing.apply(edge / self.blend_time)
+    }
+    /// Inside groups, the pan point is moved into the arena with `shared`.
+    pub fn view(&self, shared: &Shared, beat: f64) -> View {
+        View {
+            zoom: self.zoom.get(beat),
+            rotation: self.rotation.get(beat),
+            pan: shared.to_arena(self.pan.get(beat)) - arena::CENTER,
+        }
+        .blend(self.amount(beat))
+    }
+}
+impl ObstacleBehaviour for Camera {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn draw(&self, _main_color: Color, _beat: f64) {}
+    fn should_kill(&self, beat: f64) -> bool {
+        beat > self.duration
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        let target = shared.target();
+        self.zoom.update(target, beat);
+        self.rotation.update(target, beat);
+        self.pan.update(target, beat);
+        shared.add_view(self.view(shared, beat));
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Camera")
+                .field("zoom", self.zoom.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("pan", self.pan.serialize()?)
+                .field("duration", self.duration)
+                .field("blend_time", self.blend_time)
+                .field("easing", self.easing),
+        )
+    }
+}
+pub struct Rectangle {
+    pub center: Box<dyn Provider<DVec2>>,
+    pub size: Box<dyn Provider<DVec2>>,
     pub rotation: Box<dyn Provider<f64>>,
     pub lifetime: f64,
     pub warn_time: f64,
     pub leave_time: f64,
 }
+/// How big a shape that pops in and shrinks away is, compared to its full size.
+fn pop_size_factor(beat: f64, lifetime: f64, leave_time: f64) -> f64 {
+    if (0.0..0.5).contains(&beat) {
+        1.25 - beat * 0.5
+    } else if (-leave_time..0.0).contains(&(beat - lifetime)) {
+        (lifetime - beat) / leave_time
+    } else {
+        1.0
+    }
+}
+/// How white a shape that pops in is, flashing while it warns.
+fn pop_color_mix_factor(beat: f64) -> f64 {
+    if (0.0..0.5).contains(&beat) {
+        1.0 - beat * 2.0
+    } else if beat < 0.0 {
+        (beat * TAU64).sin() * 0.5 + 0.5
+    } else {
+        0.0
+    }
+}
+/// Faded and pulsing, getting stronger over the `warn_time` beats before it hits.
+fn warn_color(main_color: Color, beat: f64, warn_time: f64) -> Color {
+    let mut color = main_color
+        .mix(WHITE, (beat * TAU64).sin() * 0.5 + 0.5)
+        .faded();
+    color.a *= (beat / warn_time + 1.0).min(1.0) as f32 * 1.5;
+    color
+}
 impl Rectangle {
     pub fn size_factor(&self, beat: f64) -> f64 {
-        if (0.0..0.5).contains(&beat) {
-            return 1.25 - beat * 0.5;
-        } else if (-self.leave_time..0.0).contains(&(beat - self.lifetime)) {
-            return (self.lifetime - beat) / self.leave_time;
-        } else {
-            return 1.0;
-        }
+        pop_size_factor(beat, self.lifetime, self.leave_time)
     }
     pub fn color_mix_factor(&self, beat: f64) -> f64 {
-        if (0.0..0.5).contains(&beat) {
-            return 1.0 - beat * 2.0;
-        } else if beat < 0.0 {
-            return (beat * TAU64).sin() * 0.5 + 0.5;
-        } else {
-            return 0.0;
-        }
+        pop_color_mix_factor(beat)
     }
 }
 impl ObstacleBehaviour for Rectangle {
@@ -576,6 +1316,206 @@ impl ObstacleBehaviour for Rectangle {
     fn should_kill(&self, beat: f64) -> bool {
         beat > self.lifetime
     }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Rectangle")
+                .field("center", self.center.serialize()?)
+                .field("size", self.size.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("lifetime", self.lifetime)
+                .field("warn_time", self.warn_time)
+                .field("leave_time", self.leave_time),
+        )
+    }
+}
+/// Any polygon, popping in and out like a [`Rectangle`]. It only hurts where it's drawn.
+#[derive(Clone)]
+pub struct Shape {
+    pub polygon: Polygon,
+    /// What `polygon` was made from. Shapes that aren't presets can't be saved to level files.
+    pub preset: Option<Preset>,
+    pub position: Box<dyn Provider<DVec2>>,
+    pub rotation: Box<dyn Provider<f64>>,
+    pub scale: Box<dyn Provider<DVec2>>,
+    pub lifetime: f64,
+    pub warn_time: f64,
+    pub leave_time: f64,
+}
+impl Shape {
+    /// The polygon's position, rotation and scale, with the scale multiplied by `size_factor`.
+    fn placement(&self, beat: f64, size_factor: f64) -> (Vec2, f32, Vec2) {
+        (
+            self.position.get(beat).as_vec2(),
+            self.rotation.get(beat) as f32,
+            (self.scale.get(beat) * size_factor).as_vec2(),
+        )
+    }
+    fn size_factor(&self, beat: f64) -> f64 {
+        pop_size_factor(beat, self.lifetime, self.leave_time)
+    }
+}
+impl ObstacleBehaviour for Shape {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        let target = shared.target();
+        self.position.update(target, beat);
+        self.rotation.update(target, beat);
+        self.scale.update(target, beat);
+    }
+    fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+        let (position, rotation, scale) = self.placement(beat, self.size_factor(beat).min(1.0));
+        beat > 0.0
+            && self
+                .polygon
+                .collides(circle_pos, circle_radius, position, rotation, scale)
+    }
+    fn should_enable(&self, beat: f64) -> bool {
+        beat > -self.warn_time
+    }
+    fn draw(&self, main_color: Color, beat: f64) {
+        let mut color = main_color.mix(WHITE, pop_color_mix_factor(beat));
+        if beat < 0.0 {
+            color = color.faded();
+            color.a *= ((beat + self.warn_time) / self.warn_time) as f32 * 1.5;
+        }
+        let (position, rotation, scale) = self.placement(beat, self.size_factor(beat));
+        self.polygon.draw(position, rotation, scale, color);
+    }
+    fn should_kill(&self, beat: f64) -> bool {
+        beat > self.lifetime
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Shape")
+                .field("polygon", self.preset?)
+                .field("position", self.position.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scale", self.scale.serialize()?)
+                .field("lifetime", self.lifetime)
+                .field("warn_time", self.warn_time)
+                .field("leave_time", self.leave_time),
+        )
+    }
+}
+/// Words slammed onto the screen, like lyrics. Only the letters hurt, not the space between them.
+#[derive(Clone)]
+pub struct Text {
+    /// Can be set with [`Text::text`], which lays it out again.
+    text: String,
+    polygon: Polygon,
+    pub position: Box<dyn Provider<DVec2>>,
+    pub rotation: Box<dyn Provider<f64>>,
+    /// How big the text is, with a line of text being `scale.y` tall.
+    pub scale: Box<dyn Provider<DVec2>>,
+
+    pub warn_time: f64,
+    pub lifetime: f64,
+    pub leave_time: f64,
+    pub flash_time: f64,
+
+    pub shake: f64,
+    pub spawned: bool,
+}
+impl Default for Text {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            polygon: Polygon {
+                vertices: vec![],
+                indices: vec![],
+            },
+            position: Constant(dvec2(arena::WIDTH * 0.5, arena::HEIGHT * 0.5)).boxed(),
+            rotation: Constant(0.0).boxed(),
+            scale: Constant(dvec2(60.0, 60.0)).boxed(),
+            warn_time: 2.0,
+            lifetime: 2.0,
+            leave_time: 0.25,
+            flash_time: 0.5,
+            shake: 0.0,
+            spawned: false,
+        }
+    }
+}
+impl Text {
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self.polygon = polygon::text::polygon(&self.text);
+        self
+    }
+    builder!(position: Box<dyn Provider<DVec2>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(scale: Box<dyn Provider<DVec2>>);
+    builder!(warn_time: f64);
+    builder!(lifetime: f64);
+    builder!(leave_time: f64);
+    builder!(flash_time: f64);
+    builder!(shake: f64);
+
+    /// The text's position, rotation and scale, with the scale multiplied by `size_factor`.
+    fn placement(&self, beat: f64, size_factor: f64) -> (Vec2, f32, Vec2) {
+        (
+            self.position.get(beat).as_vec2(),
+            self.rotation.get(beat) as f32,
+            (self.scale.get(beat) * size_factor).as_vec2(),
+        )
+    }
+}
+impl ObstacleBehaviour for Text {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        let target = shared.target();
+        self.position.update(target, beat);
+        self.rotation.update(target, beat);
+        self.scale.update(target, beat);
+        if beat > 0.0 && !self.spawned {
+            self.spawned = true;
+            shared.add_shake(self.shake);
+        }
+    }
+    fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+        let size_factor = pop_size_factor(beat, self.lifetime, self.leave_time).min(1.0);
+        let (position, rotation, scale) = self.placement(beat, size_factor);
+        beat > 0.0
+            && self
+                .polygon
+                .collides(circle_pos, circle_radius, position, rotation, scale)
+    }
+    fn draw(&self, main_color: Color, beat: f64) {
+        let color = if beat < 0.0 {
+            warn_color(main_color, beat, self.warn_time)
+        } else if beat < self.flash_time {
+            main_color.mix(WHITE, 1.0 - beat / self.flash_time)
+        } else {
+            main_color
+        };
+        let size_factor = pop_size_factor(beat, self.lifetime, self.leave_time);
+        let (position, rotation, scale) = self.placement(beat, size_factor);
+        self.polygon.draw(position, rotation, scale, color);
+    }
+    fn should_enable(&self, beat: f64) -> bool {
+        beat > -self.warn_time
+    }
+    fn should_kill(&self, beat: f64) -> bool {
+        beat > self.lifetime
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("Text")
+                .field("text", self.text.as_str())
+                .field("position", self.position.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scale", self.scale.serialize()?)
+                .field("warn_time", self.warn_time)
+                .field("lifetime", self.lifetime)
+                .field("leave_time", self.leave_time)
+                .field("flash_time", self.flash_time)
+                .field("shake", self.shake),
+        )
+    }
 }
 pub struct RectangleGenerator {
     pub interval: f64,
@@ -628,4 +1568,687 @@ impl ObstacleBehaviour for RectangleGenerator {
         }
     }
     fn draw(&self, _main_color: Color, _beat: f64) {}
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        Some(
+            BehaviourDefinition::new("RectangleGenerator")
+                .field("interval", self.interval)
+                .field("lifetime", self.lifetime)
+                .field("spawned_center", self.spawned_center.serialize()?)
+                .field("spawned_size", self.spawned_size.serialize()?)
+                .field("spawned_rotation", self.spawned_rotation.serialize()?)
+                .field("spawned_lifetime", self.spawned_lifetime)
+                .field("spawned_warn_time", self.spawned_warn_time),
+        )
+    }
+}
+
+/// What a [`Spawner`] is spawning, for templates to vary each spawn by.
+pub struct Spawn<'a> {
+    /// Counts up from 0.
+    pub index: usize,
+    /// When the spawned obstacle starts, relative to the spawner.
+    pub beat: f64,
+    pub rng: &'a mut StdRng,
+}
+
+pub type BuildSpawn = Rc<dyn Fn(&mut Spawn) -> Option<DynObstacleBehaviour>>;
+#[derive(Clone)]
+pub enum SpawnTemplate {
+    /// Builds every spawn in code, skipping the ones it returns `None` for.
+    Build(BuildSpawn),
+    /// A level file obstacle, with its per-spawn values filled in for every spawn.
+    Definition(BehaviourDefinition),
+}
+impl SpawnTemplate {
+    fn spawn(&self, spawn: &mut Spawn) -> Option<Obstacle> {
+        match self {
+            SpawnTemplate::Build(build) => {
+                build(spawn).map(|behaviour| Obstacle::new(0.0, behaviour))
+            }
+            // the first spawn gets checked while loading, so this only fails on
+            // values that are wrong for some spawns, like a random non-whole count
+            SpawnTemplate::Definition(definition) => {
+                definition.resolve(spawn).ok()?.build(0.0).ok()
+            }
+        }
+    }
+}
+
+/// Spawns `count` obstacles from a template, one every `interval` beats,
+/// `lead_time` beats before each of them starts so they can warn.
+#[derive(Clone)]
+pub struct Spawner {
+    pub template: SpawnTemplate,
+    pub interval: f64,
+    pub count: usize,
+    pub lead_time: f64,
+    pub spawned: usize,
+    pub rng: StdRng,
+}
+impl Spawner {
+    /// The rng starts out unseeded, and gets seeded when it's added to a level.
+    pub fn new(template: SpawnTemplate, interval: f64, count: usize) -> Self {
+        Self {
+            template,
+            interval,
+            count,
+            lead_time: 4.0,
+            spawned: 0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+    builder!(lead_time: f64);
+}
+impl ObstacleBehaviour for Spawner {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    fn should_enable(&self, beat: f64) -> bool {
+        beat > -self.lead_time
+    }
+    fn should_kill(&self, _beat: f64) -> bool {
+        self.spawned >= self.count
+    }
+    fn seed(&mut self, rng: &mut StdRng) {
+        self.rng = StdRng::seed_from_u64(rng.gen());
+    }
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        while self.spawned < self.count
+            && beat + self.lead_time > self.spawned as f64 * self.interval
+        {
+            let start = self.spawned as f64 * self.interval;
+            let mut spawn = Spawn {
+                index: self.spawned,
+                beat: start,
+                rng: &mut self.rng,
+            };
+            if let Some(mut obstacle) = self.template.spawn(&mut spawn) {
+                obstacle.behaviour.seed(&mut self.rng);
+                // the level adds the current beat to this
+                obstacle.offset = start - beat;
+                shared.add_obstacle(obstacle);
+            }
+            self.spawned += 1;
+        }
+    }
+    fn draw(&self, _main_color: Color, _beat: f64) {}
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        let SpawnTemplate::Definition(template) = &self.template else {
+            return None;
+        };
+        Some(
+            BehaviourDefinition::new("Spawner")
+                .field("template", Value::Obstacle(template.clone()))
+                .field("interval", self.interval)
+                .field("count", self.count)
+                .field("lead_time", self.lead_time),
+        )
+    }
+}
+
+/// A formation of obstacles that gets moved, turned and scaled as a whole.
+/// Children are laid out around (0, 0), and their offsets are relative to the group's.
+/// Anything checking the arena's bounds, like pellets dying offscreen, does so in the children's space.
+pub struct Group {
+    pub children: Vec<Obstacle>,
+    pub position: Box<dyn Provider<DVec2>>,
+    /// In radians, clockwise.
+    pub rotation: Box<dyn Provider<f64>>,
+    pub scale: Box<dyn Provider<f64>>,
+}
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            children: vec![],
+            position: Constant(DVec2::ZERO).boxed(),
+            rotation: Constant(0.0).boxed(),
+            scale: Constant(1.0).boxed(),
+        }
+    }
+}
+impl Clone for Group {
+    fn clone(&self) -> Self {
+        Self {
+            children: self.children.clone(),
+            position: self.position.box_clone(),
+            rotation: self.rotation.box_clone(),
+            scale: self.scale.box_clone(),
+        }
+    }
+}
+impl Group {
+    builder!(children: Vec<Obstacle>);
+    builder!(position: Box<dyn Provider<DVec2>>);
+    builder!(rotation: Box<dyn Provider<f64>>);
+    builder!(scale: Box<dyn Provider<f64>>);
+
+    /// Turns a point in the arena into one in the children's space.
+    pub fn to_local(&self, beat: f64, point: DVec2) -> DVec2 {
+        DVec2::from_angle(-self.rotation.get(beat)).rotate(point - self.position.get(beat))
+            / self.scale.get(beat)
+    }
+}
+impl ObstacleBehaviour for Group {
+    fn box_clone(&self) -> DynObstacleBehaviour {
+        self.clone().boxed()
+    }
+    /// Children check this themselves, and still have to be killed once they're disabled.
+    fn should_enable(&self, _beat: f64) -> bool {
+        true
+    }
+    fn should_kill(&self, _beat: f64) -> bool {
+        self.children.is_empty()
+    }
+    fn seed(&mut self, rng: &mut StdRng) {
+        for child in &mut self.children {
+            child.behaviour.seed(rng);
+        }
+    }
+    /// Runs the children like a tiny level, passing their effects on in arena space.
+    fn update(&mut self, shared: &mut Shared, beat: f64) {
+        let target = shared.target();
+        self.position.update(target, beat);
+        self.rotation.update(target, beat);
+        self.scale.update(target, beat);
+        let rotation = self.rotation.get(beat);
+        let transform = DAffine2::from_scale_angle_translation(
+            DVec2::splat(self.scale.get(beat)),
+            rotation,
+            self.position.get(beat),
+        );
+        let mut local = shared.nested(
+            Target {
+                position: self.to_local(beat, target.position),
+                velocity: DVec2::from_angle(-rotation).rotate(target.velocity)
+                    / self.scale.get(beat),
+            },
+            transform,
+        );
+        for child in &mut self.children {
+            child.update(&mut local, beat);
+        }
+        let mut i = 0;
+        while i < self.children.len() {
+            if self.children[i].should_kill(beat) {
+                self.children[i].kill(&mut local, beat);
+                self.children.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        shared.add_shake(local.shake());
+        shared.add_jerk(
+            Vec2::from_angle(rotation as f32).rotate(local.jerk()) * self.scale.get(beat) as f32,
+        );
+        // cameras already pan to arena points
+        shared.add_view(local.view());
+        shared.swaps.append(&mut local.swaps);
+        // backdrops cover the whole arena, so they aren't moved with the group
+        if let Some(backdrop) = local.backdrop.take() {
+            shared.set_backdrop(backdrop);
+        }
+        for mut child in local.consume_for_obstacles() {
+            child.offset += beat;
+            self.children.push(child);
+        }
+    }
+    /// Without a swatch, every child is drawn in the group's color.
+    fn draw(&self, main_color: Color, beat: f64) {
+        self.draw_with_swatch(&Swatch::uniform(main_color), main_color, beat);
+    }
+    fn draw_with_swatch(&self, swatch: &Swatch, _main_color: Color, beat: f64) {
+        let transform = Mat4::from_scale_rotation_translation(
+            vec3(
+                self.scale.get(beat) as f32,
+                self.scale.get(beat) as f32,
+                1.0,
+            ),
+            Quat::from_rotation_z(self.rotation.get(beat) as f32),
+            self.position.get(beat).as_vec2().extend(0.0),
+        );
+        draw::with_transform(transform, || {
+            for child in &self.children {
+                child.draw(swatch, beat);
+            }
+        });
+    }
+    fn collides(&self, beat: f64, circle_pos: DVec2, circle_radius: f64) -> bool {
+        let (position, radius) = (
+            self.to_local(beat, circle_pos),
+            circle_radius / self.scale.get(beat),
+        );
+        self.children
+            .iter()
+            .any(|child| child.collides(beat, position, radius))
+    }
+    fn serialize(&self) -> Option<BehaviourDefinition> {
+        let children = self
+            .children
+            .iter()
+            .map(|child| {
+                let definition = Value::Obstacle(child.serialize()?);
+                Some(if child.offset == 0.0 {
+                    definition
+                } else {
+                    Value::call("at", [child.offset.into(), definition])
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            BehaviourDefinition::new("Group")
+                .field("children", Value::List(children))
+                .field("position", self.position.serialize()?)
+                .field("rotation", self.rotation.serialize()?)
+                .field("scale", self.scale.serialize()?),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::{
+        lasers::{RotatingLaser, SlamLaser},
+        *,
+    };
+    use crate::{backdrop::Stripes, provider::Linear, shared::Target};
+
+    fn laser() -> Obstacle {
+        Obstacle::new(
+            10.0,
+            SlamLaser::default()
+                .start_pos(dvec2(0.0, 300.0))
+                .end_pos(dvec2(800.0, 300.0))
+                .boxed(),
+        )
+    }
+
+    #[test]
+    fn text_only_hurts_where_the_letters_are() {
+        // an `o` 100 tall, whose hole is 23 wide and 30 tall and centered on the text
+        let text = Obstacle::new(
+            2.0,
+            Text::default()
+                .text("o")
+                .position(Constant(dvec2(400.0, 300.0)).boxed())
+                .scale(Constant(dvec2(100.0, 100.0)).boxed())
+                .boxed(),
+        );
+        let stroke = dvec2(385.0, 300.0);
+        assert!(!text.collides(1.0, stroke, 1.0), "while warning");
+        assert!(text.collides(3.0, stroke, 1.0), "on the letter");
+        assert!(!text.collides(3.0, dvec2(400.0, 300.0), 5.0), "in the hole");
+        assert!(
+            !text.collides(3.0, dvec2(425.0, 300.0), 1.0),
+            "past the letter"
+        );
+    }
+
+    #[test]
+    fn slam_laser_only_hits_after_slamming() {
+        let laser = laser();
+        let middle = dvec2(400.0, 300.0);
+        assert!(!laser.collides(7.0, middle, 10.0), "before warning");
+        assert!(!laser.collides(9.0, middle, 10.0), "while warning");
+        assert!(laser.collides(10.5, middle, 10.0), "after slamming");
+        assert!(!laser.collides(10.5, dvec2(400.0, 100.0), 10.0), "far away");
+        assert!(!laser.collides(13.5, middle, 10.0), "after leaving");
+    }
+
+    #[test]
+    fn slam_laser_shakes_once() {
+        let mut laser = Obstacle::new(10.0, SlamLaser::default().shake(5.0).boxed());
+        let mut shared = Shared::new(Target::default());
+        laser.update(&mut shared, 9.0);
+        assert_eq!(shared.shake(), 0.0);
+        laser.update(&mut shared, 10.5);
+        laser.update(&mut shared, 11.0);
+        assert_eq!(shared.shake(), 5.0);
+    }
+
+    #[test]
+    fn slam_laser_is_killed_after_leaving() {
+        let laser = laser();
+        assert!(!laser.should_kill(12.5));
+        assert!(laser.should_kill(13.5));
+    }
+
+    #[test]
+    fn rotating_laser_hits_where_it_points() {
+        let laser = Obstacle::new(
+            0.0,
+            RotatingLaser::default()
+                .pivot(Constant(dvec2(400.0, 300.0)).boxed())
+                .angle(Linear::new(0.0, FRAC_PI_2).boxed())
+                .boxed(),
+        );
+        let right = dvec2(600.0, 300.0);
+        let down = dvec2(400.0, 500.0);
+        assert!(laser.collides(0.1, right, 10.0));
+        assert!(!laser.collides(0.1, down, 10.0));
+        assert!(!laser.collides(1.0, right, 10.0));
+        assert!(laser.collides(1.0, down, 10.0));
+        assert!(!laser.collides(3.5, down, 10.0));
+    }
+
+    #[test]
+    fn ring_grows_with_gaps() {
+        let mut ring = Obstacle::new(
+            0.0,
+            Ring::default()
+                .center(Constant(dvec2(400.0, 300.0)).boxed())
+                .inner_radius(Linear::new(-20.0, 100.0).boxed())
+                .outer_radius(Linear::new(0.0, 100.0).boxed())
+                .gap_count(2)
+                .gap_width(1.0)
+                .shake(10.0)
+                .boxed(),
+        );
+        let gap = dvec2(490.0, 300.0);
+        let solid = dvec2(400.0, 390.0);
+        assert!(!ring.collides(-1.0, solid, 5.0));
+        assert!(!ring.collides(1.0, gap, 5.0));
+        assert!(ring.collides(1.0, solid, 5.0));
+        assert!(!ring.collides(1.0, dvec2(400.0, 300.0), 5.0));
+        assert!(!ring.collides(2.0, solid, 5.0));
+
+        let mut shared = Shared::new(Target::default());
+        ring.update(&mut shared, 0.5);
+        ring.update(&mut shared, 1.0);
+        assert_eq!(shared.shake(), 10.0);
+    }
+
+    #[test]
+    fn wall_scrolls_in_with_gaps() {
+        let mut wall = Obstacle::new(
+            0.0,
+            Wall::default()
+                .from(Edge::Left)
+                .speed(Constant(100.0).boxed())
+                .gaps(vec![dvec2(300.0, 100.0)])
+                .boxed(),
+        );
+        let mut shared = Shared::new(Target::default());
+        wall.update(&mut shared, 2.0);
+        // the wall is 40 thick, and its front is 200 in
+        assert!(wall.collides(2.0, dvec2(180.0, 100.0), 5.0));
+        assert!(!wall.collides(2.0, dvec2(180.0, 300.0), 5.0));
+        assert!(!wall.collides(2.0, dvec2(300.0, 100.0), 5.0));
+        assert!(!wall.should_kill(8.0));
+        assert!(wall.should_kill(8.5));
+    }
+
+    #[test]
+    fn wall_gap_patterns_repeat() {
+        let wall = Wall::default()
+            .gap_spacing(200.0)
+            .gap_width(50.0)
+            .gap_offset(100.0);
+        assert_eq!(
+            wall.segments(),
+            [(0.0, 75.0), (125.0, 275.0), (325.0, 475.0), (525.0, 600.0)]
+        );
+    }
+
+    #[test]
+    fn pellets_are_killed_outside_the_arena() {
+        let pellet = Obstacle::new(
+            0.0,
+            Circle::pellet(5.0, dvec2(780.0, 300.0), dvec2(10.0, 0.0)).boxed(),
+        );
+        assert!(pellet.collides(1.0, dvec2(790.0, 300.0), 1.0));
+        assert!(!pellet.should_kill(2.0));
+        assert!(pellet.should_kill(3.0));
+    }
+
+    #[test]
+    fn bomb_bursts_into_projectiles() {
+        let mut bomb = Obstacle::new(
+            0.0,
+            Bomb {
+                start_position: dvec2(800.0, 300.0),
+                end_position: dvec2(400.0, 300.0),
+                lifetime: 4.0,
+                radius_per_beat: 10.0,
+                projectile_count: 8,
+                projectile_radius: 5.0,
+                projectile_speed: 100.0,
+                aimed: false,
+            }
+            .boxed(),
+        );
+        assert!(!bomb.should_kill(3.0));
+        assert!(bomb.should_kill(4.0));
+        let mut shared = Shared::new(Target::default());
+        bomb.kill(&mut shared, 4.0);
+        assert_eq!(shared.consume_for_obstacles().len(), 8);
+    }
+
+    #[test]
+    fn spawner_spawns_every_interval() {
+        let template = SpawnTemplate::Build(Rc::new(|spawn| {
+            (spawn.index != 1).then(|| {
+                SlamLaser::default()
+                    .start_pos(dvec2(spawn.index as f64, 0.0))
+                    .boxed() as DynObstacleBehaviour
+            })
+        }));
+        let mut spawner = Obstacle::new(8.0, Spawner::new(template, 2.0, 3).lead_time(1.0).boxed());
+        let spawned = |spawner: &mut Obstacle, beat: f64| {
+            let mut shared = Shared::new(Target::default());
+            spawner.update(&mut shared, beat);
+            shared
+                .consume_for_obstacles()
+                .into_iter()
+                .map(|obstacle| {
+                    let start = obstacle.behaviour.serialize().unwrap().fields[0].1.clone();
+                    (obstacle.offset + beat, start)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(
+            spawned(&mut spawner, 6.5).is_empty(),
+            "before the lead time"
+        );
+        assert_eq!(spawned(&mut spawner, 7.5), [(8.0, dvec2(0.0, 0.0).into())]);
+        assert!(spawned(&mut spawner, 8.0).is_empty());
+        assert!(!spawner.should_kill(8.0));
+        // the second spawn is skipped
+        assert_eq!(
+            spawned(&mut spawner, 12.0),
+            [(12.0, dvec2(2.0, 0.0).into())]
+        );
+        assert!(spawner.should_kill(12.0));
+    }
+
+    #[test]
+    fn spawner_templates_vary_per_spawn() {
+        let template = BehaviourDefinition::new("SlamLaser")
+            .field(
+                "start_pos",
+                Value::call(
+                    "by_index",
+                    [Value::call(
+                        "velocity",
+                        [dvec2(0.0, 0.0).into(), dvec2(10.0, 0.0).into()],
+                    )],
+                ),
+            )
+            .field(
+                "thickness",
+                Value::call("random", [20.0.into(), 60.0.into()]),
+            );
+        let spawn_all = |seed: u64| {
+            let mut spawner = Spawner::new(SpawnTemplate::Definition(template.clone()), 1.0, 4);
+            spawner.seed(&mut StdRng::seed_from_u64(seed));
+            let mut shared = Shared::new(Target::default());
+            spawner.update(&mut shared, 0.0);
+            shared
+                .consume_for_obstacles()
+                .into_iter()
+                .map(|obstacle| obstacle.behaviour.serialize().unwrap().fields)
+                .collect::<Vec<_>>()
+        };
+        let spawned = spawn_all(1);
+        assert_eq!(spawned.len(), 4);
+        for (index, fields) in spawned.iter().enumerate() {
+            let field = |name: &str| &fields.iter().find(|(field, _)| field == name).unwrap().1;
+            assert_eq!(field("start_pos"), &dvec2(index as f64 * 10.0, 0.0).into());
+            let Value::Number(thickness) = field("thickness") else {
+                panic!("thickness isn't a number");
+            };
+            assert!((20.0..=60.0).contains(thickness));
+        }
+        assert_ne!(spawned[0], spawned[1], "thickness is random per spawn");
+        assert_eq!(
+            spawned,
+            spawn_all(1),
+            "the same seed spawns the same obstacles"
+        );
+        assert_ne!(spawned, spawn_all(2));
+    }
+
+    #[test]
+    fn group_moves_turns_and_scales_its_children() {
+        let child = Circle::new(Constant(dvec2(100.0, 0.0)), 10.0, Some(4.0));
+        let group = Obstacle::new(
+            2.0,
+            Group::default()
+                .children(vec![Obstacle::new(1.0, child.boxed())])
+                .position(Constant(dvec2(400.0, 300.0)).boxed())
+                .rotation(Constant(FRAC_PI_2).boxed())
+                .scale(Constant(2.0).boxed())
+                .boxed(),
+        );
+        assert!(group.collides(3.5, dvec2(400.0, 500.0), 1.0));
+        assert!(
+            group.collides(3.5, dvec2(400.0, 519.0), 1.0),
+            "the radius gets scaled too"
+        );
+        assert!(
+            !group.collides(3.5, dvec2(600.0, 300.0), 1.0),
+            "where it'd be unturned"
+        );
+    }
+
+    #[test]
+    fn group_keeps_what_its_children_spawn() {
+        let bomb = Bomb {
+            start_position: dvec2(0.0, 0.0),
+            end_position: dvec2(0.0, 0.0),
+            lifetime: 1.0,
+            radius_per_beat: 10.0,
+            projectile_count: 4,
+            projectile_radius: 5.0,
+            projectile_speed: 100.0,
+            aimed: false,
+        };
+        let mut group = Obstacle::new(
+            0.0,
+            Group::default()
+                .children(vec![Obstacle::new(0.0, bomb.boxed())])
+                .position(Constant(dvec2(400.0, 300.0)).boxed())
+                .boxed(),
+        );
+        let mut shared = Shared::new(Target::default());
+        group.update(&mut shared, 0.5);
+        group.update(&mut shared, 1.5);
+        assert!(shared.consume_for_obstacles().is_empty());
+        assert!(!group.should_kill(1.5));
+        // the projectiles fly out from the group's position
+        assert!(group.collides(2.5, dvec2(500.0, 300.0), 1.0));
+    }
+
+    #[test]
+    fn camera_blends_in_and_out() {
+        let mut camera = Obstacle::new(
+            10.0,
+            Camera::default()
+                .zoom(Constant(2.0).boxed())
+                .pan(Constant(arena::CENTER + dvec2(100.0, 0.0)).boxed())
+                .easing(Easing::Linear)
+                .boxed(),
+        );
+        let view = |camera: &mut Obstacle, beat| {
+            let mut shared = Shared::new(Target::default());
+            camera.update(&mut shared, beat);
+            shared.view()
+        };
+        assert_eq!(view(&mut camera, 9.0), View::NEUTRAL);
+        assert_eq!(view(&mut camera, 10.5).zoom, 1.5);
+        let held = view(&mut camera, 12.0);
+        assert_eq!((held.zoom, held.pan), (2.0, dvec2(100.0, 0.0)));
+        assert_eq!(view(&mut camera, 13.5).zoom, 1.5);
+        assert!(camera.should_kill(14.5));
+    }
+
+    #[test]
+    fn jerks_turn_and_scale_with_the_group() {
+        let slam = SlamLaser::default().jerk(Vec2::new(5.0, 0.0));
+        let mut group = Obstacle::new(
+            0.0,
+            Group::default()
+                .children(vec![Obstacle::new(0.0, slam.boxed())])
+                .rotation(Constant(FRAC_PI_2).boxed())
+                .scale(Constant(2.0).boxed())
+                .boxed(),
+        );
+        let mut shared = Shared::new(Target::default());
+        group.update(&mut shared, 0.5);
+        assert!(shared.jerk().abs_diff_eq(Vec2::new(0.0, 10.0), 1e-5));
+    }
+
+    #[test]
+    fn cameras_in_groups_pan_to_arena_points() {
+        let camera = Camera::default()
+            .pan(Constant(dvec2(10.0, 0.0)).boxed())
+            .duration(4.0)
+            .blend_time(2.0)
+            .easing(Easing::Linear);
+        let mut group = Obstacle::new(
+            0.0,
+            Group::default()
+                .children(vec![Obstacle::new(0.0, camera.boxed())])
+                .position(Constant(dvec2(400.0, 300.0)).boxed())
+                .rotation(Constant(FRAC_PI_2).boxed())
+                .scale(Constant(2.0).boxed())
+                .boxed(),
+        );
+        let pan = |group: &mut Obstacle, beat| {
+            let mut shared = Shared::new(Target::default());
+            group.update(&mut shared, beat);
+            shared.view().pan
+        };
+        // (10, 0) turns to (0, 10), then doubles and moves with the group
+        let looked_at = dvec2(400.0, 320.0) - arena::CENTER;
+        assert!(pan(&mut group, 2.0).abs_diff_eq(looked_at, 1e-9));
+        assert!(pan(&mut group, 1.0).abs_diff_eq(looked_at * 0.5, 1e-9));
+        assert_eq!(
+            pan(&mut group, 4.5),
+            DVec2::ZERO,
+            "without a camera, nothing pans"
+        );
+    }
+
+    #[test]
+    fn backdrops_switch_through_groups() {
+        let set = SetBackdrop(Stripes::default().boxed());
+        let mut group = Obstacle::new(
+            0.0,
+            Group::default()
+                .children(vec![Obstacle::new(4.0, set.boxed())])
+                .boxed(),
+        );
+        let backdrop = |group: &mut Obstacle, beat| {
+            let mut shared = Shared::new(Target::default());
+            group.update(&mut shared, beat);
+            shared.backdrop.and_then(|backdrop| backdrop.serialize())
+        };
+        assert_eq!(backdrop(&mut group, 3.0), None);
+        assert_eq!(
+            backdrop(&mut group, 4.5).map(|definition| definition.kind),
+            Some("Stripes".to_owned())
+        );
+    }
 }
@@ -0,0 +1,374 @@
+//! Suggests obstacle timings from a level's song, so charting can start from a draft instead of a
+//! blank file. Runs without opening a window, like the checker.
+//!
+//! The song gets decoded by SoLoud the same way it is when playing, mixed down to mono, and split
+//! into low, mid and high bands. An onset is where a band suddenly gets louder than it has been
+//! lately, and percussive onsets are the ones where most bands jump at once, like drums do. Every
+//! onset is snapped to the nearest quarter of a beat with the level's tempo map.
+
+use std::{
+    error::Error,
+    f64::consts::{SQRT_2, TAU},
+    ffi::OsString,
+    io,
+    path::Path,
+};
+
+use jut::extensions::Boxed;
+use macroquad::math::dvec2;
+use soloud::{Backend, LoadExt, Soloud, SoloudFlag, Wav};
+
+use crate::{
+    arena,
+    level::{Level, LevelBuilder, Song},
+    levelfile,
+    levels::{self, FileLevel, LevelDefinition},
+    obstacle::{
+        lasers::{SlamLaser, WidenLaser},
+        Circle, Obstacle, Shake,
+    },
+    tempo::TempoMap,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+/// How many samples get mixed at a time while decoding.
+const BUFFER_SIZE: usize = 1024;
+/// How many samples each loudness measurement covers, about 12 milliseconds.
+const HOP: usize = 512;
+/// Where the low band ends and the high band starts, in hertz.
+const LOW_CUTOFF: f64 = 200.0;
+const HIGH_CUTOFF: f64 = 2500.0;
+/// How many decibels a band has to jump by, on top of how much it's been jumping nearby.
+const ONSET_DECIBELS: f64 = 6.0;
+/// How many hops either side count as nearby.
+const NEARBY_HOPS: usize = 16;
+/// Hops this far below the loudest part of the song are too quiet to count, so a band that's
+/// barely there doesn't get onsets from the others leaking into it.
+const QUIET_DECIBELS: f64 = 50.0;
+/// How close together onsets in the same band can be, in seconds.
+const MIN_GAP: f64 = 0.06;
+/// Onsets get snapped to this fraction of a beat.
+const SNAP: f64 = 0.25;
+
+/// The times of every onset found in a song, in seconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Onsets {
+    pub low: Vec<f64>,
+    pub mid: Vec<f64>,
+    pub high: Vec<f64>,
+    pub percussive: Vec<f64>,
+}
+impl Onsets {
+    /// Each kind of onset along with its name.
+    fn named(&self) -> [(&'static str, &[f64]); 4] {
+        [
+            ("low", &self.low),
+            ("mid", &self.mid),
+            ("high", &self.high),
+            ("percussive", &self.percussive),
+        ]
+    }
+}
+
+/// A second order filter, using the formulas from the Audio EQ Cookbook.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    inputs: [f64; 2],
+    outputs: [f64; 2],
+}
+impl Biquad {
+    fn new(cutoff: f64, high_pass: bool) -> Self {
+        let omega = TAU * cutoff / SAMPLE_RATE as f64;
+        let alpha = omega.sin() / 2.0 * SQRT_2;
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+        let b = if high_pass {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        } else {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        };
+        Self {
+            b: b.map(|b| b / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            inputs: [0.0; 2],
+            outputs: [0.0; 2],
+        }
+    }
+    fn low_pass(cutoff: f64) -> Self {
+        Self::new(cutoff, false)
+    }
+    fn high_pass(cutoff: f64) -> Self {
+        Self::new(cutoff, true)
+    }
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.inputs[0] + self.b[2] * self.inputs[1]
+            - self.a[0] * self.outputs[0]
+            - self.a[1] * self.outputs[1];
+        self.inputs = [input, self.inputs[0]];
+        self.outputs = [output, self.outputs[0]];
+        output
+    }
+}
+
+/// Decodes a song into mono samples at [`SAMPLE_RATE`].
+pub fn decode(song: &Song) -> Result<Vec<f32>, Box<dyn Error>> {
+    let mut wav = Wav::default();
+    wav.load_mem(&song.data)?;
+    // the null driver doesn't play anything, it only mixes when asked to
+    let mut soloud = Soloud::new(
+        SoloudFlag::empty(),
+        Backend::Null,
+        SAMPLE_RATE,
+        BUFFER_SIZE as u32,
+        1,
+    )?;
+    soloud.play(&wav);
+    let mut samples = vec![0.0; (wav.length() * SAMPLE_RATE as f64) as usize];
+    for buffer in samples.chunks_mut(BUFFER_SIZE) {
+        soloud.mix(buffer);
+    }
+    Ok(samples)
+}
+
+/// How loud each hop of a band is, in decibels.
+fn loudness(samples: &[f32], mut filters: Vec<Biquad>) -> Vec<f64> {
+    samples
+        .chunks(HOP)
+        .map(|hop| {
+            let power = hop
+                .iter()
+                .map(|&sample| {
+                    let filtered = filters
+                        .iter_mut()
+                        .fold(sample as f64, |sample, filter| filter.process(sample));
+                    filtered * filtered
+                })
+                .sum::<f64>()
+                / hop.len() as f64;
+            10.0 * (power + 1e-12).log10()
+        })
+        .collect()
+}
+
+/// How much louder every hop is than two hops before, so onsets split between two hops still
+/// show up in one. Hops too far below `loudest` don't count.
+fn flux(loudness: &[f64], loudest: f64) -> Vec<f64> {
+    (0..loudness.len())
+        .map(|hop| {
+            if hop < 2 || loudness[hop] < loudest - QUIET_DECIBELS {
+                0.0
+            } else {
+                (loudness[hop] - loudness[hop - 2]).max(0.0)
+            }
+        })
+        .collect()
+}
+
+/// Finds the hops where `flux` peaks above what's around it, in seconds.
+fn peaks(flux: &[f64], threshold: f64, keep: impl Fn(usize) -> bool) -> Vec<f64> {
+    let mut onsets: Vec<f64> = vec![];
+    for hop in 0..flux.len() {
+        let nearby =
+            &flux[hop.saturating_sub(NEARBY_HOPS)..(hop + NEARBY_HOPS + 1).min(flux.len())];
+        let mean = nearby.iter().sum::<f64>() / nearby.len() as f64;
+        let closest = &flux[hop.saturating_sub(3)..(hop + 4).min(flux.len())];
+        let is_peak = closest.iter().all(|&other| other <= flux[hop]);
+        if !is_peak || flux[hop] < mean + threshold || !keep(hop) {
+            continue;
+        }
+        // the jump started the hop before
+        let time = (hop - 1) as f64 * HOP as f64 / SAMPLE_RATE as f64;
+        if onsets.last().is_some_and(|&last| time - last < MIN_GAP) {
+            continue;
+        }
+        onsets.push(time);
+    }
+    onsets
+}
+
+/// Finds the onsets in mono samples at [`SAMPLE_RATE`].
+pub fn detect(samples: &[f32]) -> Onsets {
+    let bands = [
+        vec![Biquad::low_pass(LOW_CUTOFF), Biquad::low_pass(LOW_CUTOFF)],
+        vec![Biquad::high_pass(LOW_CUTOFF), Biquad::low_pass(HIGH_CUTOFF)],
+        vec![
+            Biquad::high_pass(HIGH_CUTOFF),
+            Biquad::high_pass(HIGH_CUTOFF),
+        ],
+    ]
+    .map(|filters| loudness(samples, filters));
+    let loudest = bands
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let bands = bands.map(|band| flux(&band, loudest));
+    let [low, mid, high] = &bands;
+    let total: Vec<f64> = (0..low.len())
+        .map(|hop| low[hop] + mid[hop] + high[hop])
+        .collect();
+    Onsets {
+        low: peaks(low, ONSET_DECIBELS, |_| true),
+        mid: peaks(mid, ONSET_DECIBELS, |_| true),
+        high: peaks(high, ONSET_DECIBELS, |_| true),
+        percussive: peaks(&total, ONSET_DECIBELS * 2.0, |hop| {
+            bands
+                .iter()
+                .filter(|band| band[hop] >= ONSET_DECIBELS)
+                .count()
+                >= 2
+        }),
+    }
+}
+
+/// Turns onset times into beats, snapped to [`SNAP`]. Onsets before beat 0 are left out, and
+/// onsets snapping to the same beat only count once.
+pub fn quantize(times: &[f64], tempo: &TempoMap) -> Vec<f64> {
+    let mut beats: Vec<f64> = times
+        .iter()
+        .map(|&time| (tempo.seconds_to_beats(time) / SNAP).round() * SNAP)
+        .filter(|&beat| beat >= 0.0)
+        .collect();
+    beats.dedup();
+    beats
+}
+
+/// Prints beats like the timing arrays in the built-in level, so they can be pasted in.
+fn print_array(name: &str, beats: &[f64]) {
+    println!("let {name}_timings = [");
+    for row in beats.chunks(8) {
+        let row: Vec<String> = row.iter().map(|beat| format!("{beat:?}")).collect();
+        println!("    {},", row.join(", "));
+    }
+    println!("];");
+}
+
+/// A level with an obstacle on every onset, to be edited into something playable.
+/// Low onsets get horizontal slams, mid ones vertical lasers, high ones pellets, and percussive
+/// ones shake the screen.
+fn draft(definition: &dyn LevelDefinition, song: Song, onsets: &Onsets) -> Level {
+    let tempo = definition.tempo();
+    let mut obstacles = vec![];
+    // going between a few spots, so neighbouring obstacles don't pile up
+    let spot = |index: usize, size: f64| size * (index % 3 + 1) as f64 / 4.0;
+    for (index, &beat) in quantize(&onsets.low, tempo).iter().enumerate() {
+        let y = spot(index, arena::HEIGHT);
+        let laser = SlamLaser::default()
+            .start_pos(dvec2(-50.0, y))
+            .end_pos(dvec2(arena::WIDTH + 50.0, y));
+        obstacles.push(Obstacle::new(beat, laser.boxed()));
+    }
+    for (index, &beat) in quantize(&onsets.mid, tempo).iter().enumerate() {
+        let x = spot(index, arena::WIDTH);
+        let laser = WidenLaser::default()
+            .start_pos(dvec2(x, -50.0))
+            .end_pos(dvec2(x, arena::HEIGHT + 50.0));
+        obstacles.push(Obstacle::new(beat, laser.boxed()));
+    }
+    for (index, &beat) in quantize(&onsets.high, tempo).iter().enumerate() {
+        let start = dvec2(arena::WIDTH + 20.0, spot(index, arena::HEIGHT));
+        let pellet = Circle::pellet(20.0, start, dvec2(-200.0, 0.0));
+        obstacles.push(Obstacle::new(beat, pellet.boxed()));
+    }
+    for &beat in &quantize(&onsets.percussive, tempo) {
+        obstacles.push(Obstacle::new(beat, Shake(10.0).boxed()));
+    }
+    // in order, so the level file reads from start to end
+    obstacles.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    let mut builder = LevelBuilder::new(0);
+    builder.info(definition.info().clone());
+    for obstacle in obstacles {
+        builder.obstacle(obstacle);
+    }
+    builder.build(song, tempo.clone(), vec![])
+}
+
+/// Finds the onsets of a level's song, or of every level's if there isn't one, and prints them as
+/// timing arrays. With `--draft`, prints a level file with an obstacle on every onset instead.
+pub fn suggest_timings(args: impl Iterator<Item = OsString>) -> Result<(), Box<dyn Error>> {
+    let (flags, paths): (Vec<OsString>, Vec<OsString>) =
+        args.partition(|arg| arg.to_string_lossy().starts_with("--"));
+    let draft_level = match flags.first() {
+        None => false,
+        Some(flag) if flag == "--draft" && flags.len() == 1 => true,
+        Some(flag) => return Err(format!("unknown option `{}`", flag.to_string_lossy()).into()),
+    };
+    let definitions: Vec<Box<dyn LevelDefinition>> = match paths.first() {
+        Some(path) => vec![Box::new(FileLevel::new(path)?)],
+        None => levels::registry(levels::LEVEL_DIRECTORY),
+    };
+    // song paths get written relative to where the level file is
+    let base_directory = match paths.first() {
+        Some(path) => Path::new(path).parent().unwrap_or(Path::new("")),
+        None => Path::new(levels::LEVEL_DIRECTORY),
+    };
+    for definition in definitions {
+        let song = definition.song()?;
+        let onsets = detect(&decode(&song)?);
+        if draft_level {
+            let level = draft(definition.as_ref(), song, &onsets);
+            levelfile::write_level(&level, &mut io::stdout().lock(), base_directory)?;
+            continue;
+        }
+        println!("// {}", definition.info().name);
+        for (name, times) in onsets.named() {
+            print_array(name, &quantize(times, definition.tempo()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Silence with a burst of sound at every one of `times`, made by `sample` from the time
+    /// since the burst started.
+    fn bursts(times: &[f64], mut sample: impl FnMut(f64) -> f64) -> Vec<f32> {
+        let mut samples = vec![0.0; SAMPLE_RATE as usize * 4];
+        for &time in times {
+            let start = (time * SAMPLE_RATE as f64) as usize;
+            for (i, out) in samples[start..start + 4410].iter_mut().enumerate() {
+                let since = i as f64 / SAMPLE_RATE as f64;
+                *out += (sample(since) * (-since * 30.0).exp()) as f32;
+            }
+        }
+        samples
+    }
+
+    fn close(found: &[f64], expected: &[f64]) -> bool {
+        found.len() == expected.len()
+            && found
+                .iter()
+                .zip(expected)
+                .all(|(found, expected)| (found - expected).abs() < 0.03)
+    }
+
+    #[test]
+    fn kicks_are_low_onsets() {
+        let times = [0.5, 1.0, 1.5, 2.25];
+        let onsets = detect(&bursts(&times, |since| (since * 60.0 * TAU).sin()));
+        assert!(close(&onsets.low, &times), "{onsets:?}");
+        assert!(onsets.high.is_empty(), "{onsets:?}");
+    }
+
+    #[test]
+    fn noise_bursts_are_percussive() {
+        let times = [0.5, 1.25, 2.0];
+        let mut rng = StdRng::seed_from_u64(0);
+        let onsets = detect(&bursts(&times, |_| rng.gen_range(-1.0..1.0)));
+        for band in [&onsets.low, &onsets.mid, &onsets.high, &onsets.percussive] {
+            assert!(close(band, &times), "{onsets:?}");
+        }
+    }
+
+    #[test]
+    fn onsets_snap_to_the_tempo_map() {
+        let tempo = TempoMap::new(120.0, 1.0).change(4.0, 60.0);
+        let times = [0.5, 1.0, 1.26, 1.3, 3.0, 4.1];
+        assert_eq!(quantize(&times, &tempo), [0.0, 0.5, 4.0, 5.0]);
+    }
+}
@@ -0,0 +1,300 @@
+//! The colors a level is drawn in, split into slots that every obstacle picks one of.
+
+use std::{f32::consts::TAU, mem, ops::Index};
+
+use jut::extensions::Boxed;
+use macroquad::color::{Color, BLACK, WHITE};
+
+use crate::{
+    easing::Easing,
+    ext::ColorExt,
+    levelfile::Value,
+    provider::{Constant, Keyframes, Provider, ProviderExt},
+};
+
+/// A named color in the palette. Obstacles are drawn in the primary color unless they pick
+/// another slot, and the background fills the arena behind them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Slot {
+    #[default]
+    Primary,
+    Accent,
+    Bullet,
+    Background,
+}
+impl Slot {
+    pub const ALL: [Slot; 4] = [Slot::Primary, Slot::Accent, Slot::Bullet, Slot::Background];
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::Primary => "primary",
+            Slot::Accent => "accent",
+            Slot::Bullet => "bullet",
+            Slot::Background => "background",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|slot| slot.name() == name)
+    }
+}
+
+/// Every slot's color at one beat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swatch([Color; 4]);
+impl Swatch {
+    /// Every slot in the same color.
+    pub fn uniform(color: Color) -> Self {
+        Self([color; 4])
+    }
+}
+impl Index<Slot> for Swatch {
+    type Output = Color;
+    fn index(&self, slot: Slot) -> &Color {
+        &self.0[slot as usize]
+    }
+}
+
+/// A color provider for every slot. Providers get the level's beat.
+#[derive(Clone)]
+pub struct Palette {
+    colors: [Box<dyn Provider<Color>>; 4],
+    /// Swaps that are still blending, for each slot.
+    blending: [Option<Blending>; 4],
+}
+#[derive(Clone)]
+struct Blending {
+    end: f64,
+    /// What the slot ends up as once it's done.
+    color: Box<dyn Provider<Color>>,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        let pink = Color::new(1.0, 0.0, 0.5, 1.0);
+        Self {
+            colors: [
+                Constant(pink).boxed(),
+                Constant(WHITE).boxed(),
+                Constant(pink).boxed(),
+                Constant(BLACK).boxed(),
+            ],
+            blending: Default::default(),
+        }
+    }
+}
+impl Palette {
+    pub fn color(&self, slot: Slot) -> &dyn Provider<Color> {
+        &*self.colors[slot as usize]
+    }
+    pub fn set(&mut self, slot: Slot, color: Box<dyn Provider<Color>>) -> &mut Self {
+        self.colors[slot as usize] = color;
+        self.blending[slot as usize] = None;
+        self
+    }
+    /// Swaps a slot's color at `beat`, blending from the old one over the swap's duration.
+    pub fn swap(&mut self, swap: Swap, beat: f64) -> &mut Self {
+        if swap.duration <= 0.0 {
+            return self.set(swap.slot, swap.color);
+        }
+        let factor =
+            Keyframes::new(beat, 0.0, swap.easing).key(beat + swap.duration, 1.0, Easing::Linear);
+        let old = mem::replace(
+            &mut self.colors[swap.slot as usize],
+            Constant(BLACK).boxed(),
+        );
+        self.set(swap.slot, old.blend(swap.color.clone(), factor).boxed());
+        self.blending[swap.slot as usize] = Some(Blending {
+            end: beat + swap.duration,
+            color: swap.color,
+        });
+        self
+    }
+    /// Drops the old colors of swaps that are done blending by `beat`, so swapping over and over
+    /// doesn't keep nesting blends.
+    pub fn settle(&mut self, beat: f64) -> &mut Self {
+        for slot in Slot::ALL {
+            let blending = &mut self.blending[slot as usize];
+            if let Some(done) = blending.take_if(|blending| beat >= blending.end) {
+                self.colors[slot as usize] = done.color;
+            }
+        }
+        self
+    }
+    pub fn swatch(&self, beat: f64) -> Swatch {
+        Swatch(Slot::ALL.map(|slot| self.color(slot).get(beat)))
+    }
+}
+
+/// A change to one slot of the palette, blending in over `duration` beats.
+#[derive(Clone)]
+pub struct Swap {
+    pub slot: Slot,
+    pub color: Box<dyn Provider<Color>>,
+    pub duration: f64,
+    pub easing: Easing,
+}
+
+/// The colors of the pride flags, cycled through in "...during pride month?".
+#[rustfmt::skip]
+pub const PRIDE: [Color; 10] = [
+    Color::new(1.0, 0.0, 0.0, 1.0),
+    Color::new(1.0, 0.5, 0.0, 1.0),
+    Color::new(1.0, 1.0, 0.0, 1.0),
+    Color::new(0.0, 1.0, 0.0, 1.0),
+    Color::new(0.0, 0.0, 1.0, 1.0),
+    Color::new(0.5, 0.0, 1.0, 1.0),
+    //
+    Color::new(1.0, 0.5, 0.8, 1.0),
+    Color::new(0.5, 0.8, 1.0, 1.0),
+    Color::new(0.8, 0.8, 0.8, 1.0),
+    //
+    Color::new(0.5, 0.25, 0.0, 1.0),
+];
+
+/// A fully saturated rainbow, going all the way around once every `TAU` of `phase`.
+pub fn sinebow(phase: f32) -> Color {
+    Color {
+        r: (phase + TAU / 3.0 * 3.0).sin() / 2.0 + 0.5,
+        g: (phase + TAU / 3.0 * 2.0).sin() / 2.0 + 0.5,
+        b: (phase + TAU / 3.0 * 1.0).sin() / 2.0 + 0.5,
+        a: 1.0,
+    }
+}
+
+/// Goes around the [`sinebow`] once every `period` beats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sinebow {
+    pub period: f64,
+}
+impl Provider<Color> for Sinebow {
+    fn box_clone(&self) -> Box<dyn Provider<Color>> {
+        (*self).boxed()
+    }
+    fn get(&self, beat: f64) -> Color {
+        sinebow((beat / self.period) as f32 * TAU)
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call("sinebow", [self.period.into()]))
+    }
+}
+
+/// Steps to the next color at each of `timings`, flashing in from `flash` over `flash_time` beats
+/// every time. Starts on the first color at beat 0, and wraps around after the last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    pub colors: Vec<Color>,
+    pub timings: Vec<f64>,
+    pub flash: Color,
+    pub flash_time: f64,
+}
+impl Cycle {
+    pub fn new(colors: Vec<Color>, timings: Vec<f64>) -> Self {
+        Self {
+            colors,
+            timings,
+            flash: WHITE,
+            flash_time: 0.5,
+        }
+    }
+    pub fn flash(mut self, flash: Color, flash_time: f64) -> Self {
+        self.flash = flash;
+        self.flash_time = flash_time;
+        self
+    }
+}
+impl Provider<Color> for Cycle {
+    fn box_clone(&self) -> Box<dyn Provider<Color>> {
+        self.clone().boxed()
+    }
+    fn get(&self, beat: f64) -> Color {
+        let steps = self.timings.partition_point(|&timing| timing < beat);
+        let start = steps
+            .checked_sub(1)
+            .map_or(0.0, |index| self.timings[index]);
+        let color = self.colors[steps % self.colors.len()];
+        self.flash
+            .mix(color, ((beat - start) / self.flash_time).clamp(0.0, 1.0))
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "cycle",
+            [
+                self.colors.clone().into(),
+                self.timings.clone().into(),
+                self.flash.into(),
+                self.flash_time.into(),
+            ],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_blend_from_the_old_color() {
+        let mut palette = Palette::default();
+        palette.swap(
+            Swap {
+                slot: Slot::Background,
+                color: Constant(WHITE).boxed(),
+                duration: 2.0,
+                easing: Easing::Linear,
+            },
+            10.0,
+        );
+        assert_eq!(palette.swatch(9.0)[Slot::Background], BLACK);
+        assert_eq!(
+            palette.swatch(11.0)[Slot::Background],
+            Color::new(0.5, 0.5, 0.5, 1.0)
+        );
+        assert_eq!(palette.swatch(12.0)[Slot::Background], WHITE);
+        assert_eq!(
+            palette.swatch(11.0)[Slot::Primary],
+            Palette::default().swatch(11.0)[Slot::Primary],
+            "other slots are left alone"
+        );
+        palette.swap(
+            Swap {
+                slot: Slot::Background,
+                color: Constant(BLACK).boxed(),
+                duration: 0.0,
+                easing: Easing::Linear,
+            },
+            12.0,
+        );
+        assert_eq!(palette.swatch(12.0)[Slot::Background], BLACK);
+    }
+
+    #[test]
+    fn finished_swaps_settle() {
+        let mut palette = Palette::default();
+        for i in 0..100 {
+            let beat = i as f64;
+            palette.settle(beat).swap(
+                Swap {
+                    slot: Slot::Primary,
+                    color: Constant(Color::new(0.0, 0.0, i as f32 / 100.0, 1.0)).boxed(),
+                    duration: 0.5,
+                    easing: Easing::Linear,
+                },
+                beat,
+            );
+        }
+        palette.settle(99.5);
+        assert_eq!(
+            palette.color(Slot::Primary).serialize(),
+            Constant(Color::new(0.0, 0.0, 0.99, 1.0)).serialize(),
+            "only the newest color is left"
+        );
+    }
+
+    #[test]
+    fn cycles_step_and_flash() {
+        let cycle = Cycle::new(vec![BLACK, WHITE], vec![1.0, 2.0]).flash(WHITE, 0.5);
+        assert_eq!(cycle.get(0.5), BLACK, "the first color starts at beat 0");
+        assert_eq!(cycle.get(1.0), BLACK, "steps just after each timing");
+        assert_eq!(cycle.get(1.25), WHITE);
+        assert_eq!(cycle.get(2.25), Color::new(0.5, 0.5, 0.5, 1.0));
+        assert_eq!(cycle.get(3.0), BLACK, "wraps around");
+    }
+}
@@ -2,18 +2,28 @@ use std::f64::NEG_INFINITY;
 
 use macroquad::{
     color::{RED, SKYBLUE, WHITE},
-    input::{is_key_down, is_key_pressed, KeyCode},
-    math::{dvec2, DVec2, Vec2},
-    miniquad::window::screen_size,
+    math::{dvec2, DVec2},
     shapes::draw_circle,
-    window::{screen_height, screen_width},
 };
 
-use crate::{draw::draw_centered_text, level::Level, transform};
+use crate::{arena, draw::draw_centered_text, level::Level, shared::Target, transform};
+
+/// What the player is trying to do this frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    /// Only true on the frame the dash button was pressed.
+    pub dash: bool,
+}
 
 #[derive(Clone, Copy)]
 pub struct Player {
     pub position: DVec2,
+    /// How far the player moved in the last second, going by the last frame.
+    pub velocity: DVec2,
     pub radius: f64,
     pub last_time: f64,
     pub regular_speed: f64,
@@ -28,13 +38,18 @@ pub struct Player {
     pub stun_velocity: DVec2,
     pub hit_cooldown: f64,
     pub hp: usize,
+    /// Every hit taken, even ones that didn't do damage.
+    pub hits: usize,
+    /// Counts hits without losing any hp, for practicing.
+    pub no_damage: bool,
 
     single_frame_freeze: bool,
 }
 impl Player {
     pub fn new() -> Self {
         Self {
-            position: Vec2::from(screen_size()).as_dvec2() * dvec2(0.25, 0.5),
+            position: arena::SIZE * dvec2(0.25, 0.5),
+            velocity: DVec2::ZERO,
             radius: 5.0,
             last_time: 0.0,
             regular_speed: 200.0,
@@ -49,6 +64,8 @@ impl Player {
             stun_velocity: -DVec2::X * 500.0,
             hit_cooldown: 2.0,
             hp: 3,
+            hits: 0,
+            no_damage: false,
 
             single_frame_freeze: true,
         }
@@ -64,35 +81,41 @@ impl Player {
             self.regular_speed
         }
     }
-    pub fn update(&mut self, time: f64, beat: f64, level: &Level) -> bool {
+    pub fn update(&mut self, time: f64, beat: f64, level: &Level, input: Input) -> bool {
         let dt = time - self.last_time;
         if self.single_frame_freeze {
             self.last_time = time;
             self.single_frame_freeze = false;
             return false;
         }
+        let start = self.position;
 
         if self.last_hit_time + self.stun_lifetime > time {
             self.position += self.stun_velocity * dt;
         } else {
             let before = self.position;
-            if is_key_down(KeyCode::A) {
-                self.position.x -= dt * self.speed(time);
+            let mut direction = DVec2::ZERO;
+            if input.left {
+                direction.x -= 1.0;
             }
-            if is_key_down(KeyCode::D) {
-                self.position.x += dt * self.speed(time);
+            if input.right {
+                direction.x += 1.0;
             }
-            if is_key_down(KeyCode::W) {
-                self.position.y -= dt * self.speed(time);
+            if input.up {
+                direction.y -= 1.0;
             }
-            if is_key_down(KeyCode::S) {
-                self.position.y += dt * self.speed(time);
+            if input.down {
+                direction.y += 1.0;
             }
+            // controls stay the same way around on screen when the camera turns
+            let direction = DVec2::from_angle(-level.view.rotation).rotate(direction);
+            let distance = dt * self.speed(time);
+            self.position += direction * distance;
             let after = self.position;
             if after != before {
                 self.stun_velocity = (before - after).normalize() * self.dash_speed * 0.5;
             }
-            if is_key_pressed(KeyCode::Space) && self.last_dash_time + self.dash_lifetime < time {
+            if input.dash && self.last_dash_time + self.dash_lifetime < time {
                 self.last_dash_time = time;
             }
         }
@@ -101,7 +124,10 @@ impl Player {
 
         if self.last_hit_time + self.hit_cooldown < time {
             if !is_invincible && level.collide(&self, beat) {
-                self.hp = self.hp.saturating_sub(1);
+                self.hits += 1;
+                if !self.no_damage {
+                    self.hp = self.hp.saturating_sub(1);
+                }
                 println!("womp womp {}", self.hp);
                 self.last_hit_time = time;
             }
@@ -109,15 +135,22 @@ impl Player {
 
         self.position = self.position.clamp(
             DVec2::new(self.radius + 10.0, self.radius + 10.0),
-            DVec2::new(
-                screen_width() as f64 - (self.radius + 10.0),
-                screen_height() as f64 - (self.radius + 10.0),
-            ),
+            arena::SIZE - (self.radius + 10.0),
         );
 
+        if dt > 0.0 {
+            self.velocity = (self.position - start) / dt;
+        }
         self.last_time = time;
         self.hp == 0
     }
+    /// What obstacles aim at, for a song at `bpm`.
+    pub fn target(&self, bpm: f64) -> Target {
+        Target {
+            position: self.position,
+            velocity: self.velocity * 60.0 / bpm,
+        }
+    }
     pub fn draw(&self, time: f64) {
         let mut color = SKYBLUE;
         if self.last_hit_time + self.hit_cooldown > time && time.rem_euclid(0.1) > 0.05 {
@@ -138,3 +171,121 @@ impl Player {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use jut::extensions::Boxed;
+
+    use crate::{
+        level::{LevelBuilder, Song},
+        obstacle::{Circle, Obstacle},
+        provider::Constant,
+        tempo::TempoMap,
+    };
+
+    use super::*;
+
+    fn level(obstacles: impl IntoIterator<Item = Obstacle>) -> Level {
+        let mut builder = LevelBuilder::new(0);
+        for obstacle in obstacles {
+            builder.obstacle(obstacle);
+        }
+        builder.build(
+            Song::builtin("during_pride_month").unwrap(),
+            TempoMap::new(120.0, 0.0),
+            vec![],
+        )
+    }
+
+    /// A player past its first frame, which never moves.
+    fn player(level: &Level) -> Player {
+        let mut player = Player::new();
+        player.update(10.0, 0.0, level, Input::default());
+        player
+    }
+
+    #[test]
+    fn moves_with_input() {
+        let level = level([]);
+        let mut player = player(&level);
+        let start = player.position;
+        let right = Input {
+            right: true,
+            ..Default::default()
+        };
+        player.update(10.5, 1.0, &level, right);
+        assert_eq!(player.position, start + dvec2(100.0, 0.0));
+        let up_left = Input {
+            up: true,
+            left: true,
+            ..Default::default()
+        };
+        player.update(11.0, 2.0, &level, up_left);
+        assert_eq!(player.position, start + dvec2(0.0, -100.0));
+    }
+
+    #[test]
+    fn controls_turn_with_the_camera() {
+        let mut level = level([]);
+        level.view.rotation = FRAC_PI_2;
+        let mut player = player(&level);
+        let start = player.position;
+        let right = Input {
+            right: true,
+            ..Default::default()
+        };
+        player.update(10.5, 1.0, &level, right);
+        // the arena's up is on the right of the screen
+        assert!(player.position.distance(start + dvec2(0.0, -100.0)) < 1e-9);
+    }
+
+    #[test]
+    fn stays_inside_the_arena() {
+        let level = level([]);
+        let mut player = player(&level);
+        let left = Input {
+            left: true,
+            ..Default::default()
+        };
+        player.update(20.0, 1.0, &level, left);
+        assert_eq!(player.position.x, player.radius + 10.0);
+    }
+
+    #[test]
+    fn dashing_is_faster() {
+        let level = level([]);
+        let mut player = player(&level);
+        let start = player.position;
+        let dash = Input {
+            dash: true,
+            ..Default::default()
+        };
+        player.update(10.0, 0.0, &level, dash);
+        let right = Input {
+            right: true,
+            ..Default::default()
+        };
+        player.update(10.1, 0.2, &level, right);
+        assert!(player.position.x - start.x > player.regular_speed * 0.1);
+    }
+
+    #[test]
+    fn hits_take_hp_unless_practicing() {
+        let spawn = Player::new().position;
+        let level = level([Obstacle::new(
+            0.0,
+            Circle::new(Constant(spawn), 20.0, Some(100.0)).boxed(),
+        )]);
+        let mut player = player(&level);
+        assert!(!player.update(10.1, 1.0, &level, Input::default()));
+        assert_eq!((player.hp, player.hits), (2, 1));
+
+        let mut practicing = Player::new();
+        practicing.no_damage = true;
+        practicing.update(10.0, 0.0, &level, Input::default());
+        practicing.update(10.1, 1.0, &level, Input::default());
+        assert_eq!((practicing.hp, practicing.hits), (3, 1));
+    }
+}
@@ -1,21 +1,64 @@
-use std::{array::TryFromSliceError, io};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
 
 use macroquad::{
     color::Color,
-    math::{Mat2, Vec2},
+    math::{DVec2, Mat2, Vec2},
     shapes::draw_triangle,
 };
 
 use thiserror::Error;
 
+use crate::{arrayreader::ArrayReader, collide};
+
+pub mod text;
+
 #[derive(Debug, Error)]
 pub enum PolygonDecodeError {
-    /*#[error("too many points while decoding polygon")]
-    TooManyPoints,*/
+    #[error("not a polygon file")]
+    BadMagic,
+    #[error("unsupported polygon version {0}")]
+    UnsupportedVersion(u16),
+    #[error("too many points while decoding polygon ({0})")]
+    TooManyPoints(u32),
+    #[error("too many indices while decoding polygon ({0})")]
+    TooManyIndices(u32),
+    #[error("index count {0} isn't a multiple of 3")]
+    IncompleteTriangle(u32),
+    #[error("index {index} is out of bounds for {vertex_count} points")]
+    IndexOutOfBounds { index: u32, vertex_count: u32 },
     #[error("io error while decoding polygon")]
     IoError(#[from] io::Error),
-    #[error("unreachable logic while decoding polygon")]
-    SliceError(#[from] TryFromSliceError),
+}
+#[derive(Debug, Error)]
+pub enum PolygonEncodeError {
+    #[error("too many points to encode polygon ({0})")]
+    TooManyPoints(usize),
+    #[error("too many indices to encode polygon ({0})")]
+    TooManyIndices(usize),
+    #[error("index {index} is out of bounds for {vertex_count} points")]
+    IndexOutOfBounds { index: usize, vertex_count: usize },
+    #[error("io error while encoding polygon")]
+    IoError(#[from] io::Error),
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
 }
 
 #[derive(Clone)]
@@ -24,62 +67,148 @@ pub struct Polygon {
     pub indices: Vec<usize>,
 }
 impl Polygon {
-    /*pub const MAX_VERTICES: usize = 32768;
+    /// Polygon files start with these bytes, followed by a little endian u16 version.
+    ///
+    /// Version 1 is laid out as (all little endian):
+    /// - u32 vertex count, then that many pairs of f32 x and y
+    /// - u32 index count, then that many u32 indices, 3 per triangle
+    pub const MAGIC: [u8; 4] = *b"EPLY";
+    pub const VERSION: u16 = 1;
+    pub const MAX_VERTICES: usize = 32768;
     pub const MAX_INDEX_TRIPLETS: usize = 32768;
     pub fn from_stream(reader: &mut impl Read) -> Result<Self, PolygonDecodeError> {
-        let mut buf = [0u8; 8];
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(PolygonDecodeError::BadMagic);
+        }
+        let version = read_u16(reader)?;
+        if version != Self::VERSION {
+            return Err(PolygonDecodeError::UnsupportedVersion(version));
+        }
 
-        reader.read(&mut buf)?;
-        let vertex_count = usize::from_le_bytes(buf);
-        if vertex_count > Self::MAX_VERTICES {
-            return Err(PolygonDecodeError::TooManyPoints);
+        let vertex_count = read_u32(reader)?;
+        if vertex_count as usize > Self::MAX_VERTICES {
+            return Err(PolygonDecodeError::TooManyPoints(vertex_count));
         }
-        let mut vertices = Vec::with_capacity(vertex_count);
+        let mut vertices = Vec::with_capacity(vertex_count as usize);
         for _ in 0..vertex_count {
-            reader.read(&mut buf)?;
-            let xbuf: [u8; 4] = (&buf[..4]).try_into()?;
-            let ybuf: [u8; 4] = (&buf[4..]).try_into()?;
             vertices.push(Vec2 {
-                x: f32::from_le_bytes(xbuf),
-                y: f32::from_le_bytes(ybuf),
+                x: read_f32(reader)?,
+                y: read_f32(reader)?,
             });
         }
 
-        reader.read(&mut buf)?;
-        let index_count = usize::from_le_bytes(buf);
-        if index_count > Self::MAX_VERTICES {
-            return Err(PolygonDecodeError::TooManyPoints);
+        let index_count = read_u32(reader)?;
+        if index_count as usize > Self::MAX_INDEX_TRIPLETS * 3 {
+            return Err(PolygonDecodeError::TooManyIndices(index_count));
+        }
+        if index_count % 3 != 0 {
+            return Err(PolygonDecodeError::IncompleteTriangle(index_count));
         }
-        let mut indices = Vec::with_capacity(index_count);
+        let mut indices = Vec::with_capacity(index_count as usize);
         for _ in 0..index_count {
-            reader.read(&mut buf)?;
-            indices.push(usize::from_le_bytes(buf));
+            let index = read_u32(reader)?;
+            if index >= vertex_count {
+                return Err(PolygonDecodeError::IndexOutOfBounds {
+                    index,
+                    vertex_count,
+                });
+            }
+            indices.push(index as usize);
         }
         Ok(Polygon { vertices, indices })
-    }*/
+    }
+    /// Decodes a polygon from memory, like one embedded with `include_bytes!`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PolygonDecodeError> {
+        Self::from_stream(&mut ArrayReader::new(bytes))
+    }
+    #[allow(dead_code)]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PolygonDecodeError> {
+        Self::from_stream(&mut BufReader::new(File::open(path)?))
+    }
+    pub fn to_stream(&self, writer: &mut impl Write) -> Result<(), PolygonEncodeError> {
+        if self.vertices.len() > Self::MAX_VERTICES {
+            return Err(PolygonEncodeError::TooManyPoints(self.vertices.len()));
+        }
+        if self.indices.len() > Self::MAX_INDEX_TRIPLETS * 3 {
+            return Err(PolygonEncodeError::TooManyIndices(self.indices.len()));
+        }
+        if let Some(&index) = self.indices.iter().find(|&&i| i >= self.vertices.len()) {
+            return Err(PolygonEncodeError::IndexOutOfBounds {
+                index,
+                vertex_count: self.vertices.len(),
+            });
+        }
+        writer.write_all(&Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
+
+        writer.write_all(&(self.vertices.len() as u32).to_le_bytes())?;
+        for vertex in &self.vertices {
+            writer.write_all(&vertex.x.to_le_bytes())?;
+            writer.write_all(&vertex.y.to_le_bytes())?;
+        }
+
+        // incomplete triangles are never drawn, so they're left out
+        let index_count = self.indices.len() - self.indices.len() % 3;
+        writer.write_all(&(index_count as u32).to_le_bytes())?;
+        for &index in &self.indices[..index_count] {
+            writer.write_all(&(index as u32).to_le_bytes())?;
+        }
+        Ok(())
+    }
+    #[allow(dead_code)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PolygonEncodeError> {
+        let mut out = vec![];
+        self.to_stream(&mut out)?;
+        Ok(out)
+    }
+    #[allow(dead_code)]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PolygonEncodeError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.to_stream(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+    /// Every complete triangle, with indices that are out of bounds skipped.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec2; 3]> + '_ {
+        self.indices.chunks_exact(3).filter_map(|chunk| {
+            Some([
+                *self.vertices.get(chunk[0])?,
+                *self.vertices.get(chunk[1])?,
+                *self.vertices.get(chunk[2])?,
+            ])
+        })
+    }
     pub fn draw_mat(&self, color: Color, offset: Vec2, transformation: Mat2) {
-        for [i1, i2, i3] in self
-            .indices
-            .chunks(3)
-            .flat_map(|chunk| -> Option<[usize; 3]> { chunk.try_into().ok() })
-        {
-            if let (Some(v1), Some(v2), Some(v3)) = (
-                self.vertices.get(i1),
-                self.vertices.get(i2),
-                self.vertices.get(i3),
-            ) {
-                draw_triangle(
-                    transformation * *v1 + offset,
-                    transformation * *v2 + offset,
-                    transformation * *v3 + offset,
-                    color,
-                );
-            }
+        for [v1, v2, v3] in self.triangles() {
+            draw_triangle(
+                transformation * v1 + offset,
+                transformation * v2 + offset,
+                transformation * v3 + offset,
+                color,
+            );
         }
     }
     pub fn draw(&self, position: Vec2, rotation: f32, scale: Vec2, color: Color) {
         self.draw_mat(color, position, Mat2::from_scale_angle(scale, rotation));
     }
+    /// Whether a circle touches this polygon, placed the same way as [`Polygon::draw`].
+    pub fn collides(
+        &self,
+        circle_pos: DVec2,
+        circle_radius: f64,
+        position: Vec2,
+        rotation: f32,
+        scale: Vec2,
+    ) -> bool {
+        collide::circle_polygon(
+            circle_pos,
+            circle_radius,
+            self,
+            position,
+            Mat2::from_scale_angle(scale, rotation),
+        )
+    }
     /*pub fn from_slices_checked((vertices, indices): (&[Vec2], &[usize])) -> Option<Self> {
         if vertices.len() % 3 != 0 || indices.iter().any(|&i| i >= vertices.len()) {
             return None;
@@ -107,6 +236,64 @@ pub mod presets {
 
     use crate::transform;
 
+    use super::Polygon;
+
+    /// A polygon that can be named in a level file.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Preset {
+        Regular {
+            sides: usize,
+        },
+        /// Points stick out to 1, with the corners between them at `inner_radius`.
+        Star {
+            points: usize,
+            inner_radius: f64,
+        },
+        Heart {
+            points: usize,
+        },
+    }
+    impl Preset {
+        pub fn polygon(self) -> Polygon {
+            let outline: Vec<Vec2> = match self {
+                Preset::Regular { sides } => (0..sides)
+                    .map(|i| Vec2::from_angle((i as f64 / sides as f64 * TAU64) as f32))
+                    .collect(),
+                Preset::Star {
+                    points,
+                    inner_radius,
+                } => (0..points * 2)
+                    .map(|i| {
+                        let radius = if i % 2 == 0 { 1.0 } else { inner_radius };
+                        // starts pointing up
+                        let period = i as f64 / (points * 2) as f64 * TAU64 - TAU64 / 4.0;
+                        Vec2::from_angle(period as f32) * radius as f32
+                    })
+                    .collect(),
+                Preset::Heart { points } => (0..points)
+                    .map(|i| {
+                        let period = i as f64 / points as f64 * TAU64;
+                        Vec2::new(
+                            period.sin() as f32,
+                            -(period.cos() + period.sin().abs() - 0.25) as f32 * 0.75,
+                        )
+                    })
+                    .collect(),
+            };
+            fan(&outline)
+        }
+    }
+
+    /// Fills in an outline with triangles from the middle, which works for anything star shaped.
+    fn fan(outline: &[Vec2]) -> Polygon {
+        let mut vertices = vec![Vec2::ZERO];
+        vertices.extend_from_slice(outline);
+        let indices = (0..outline.len())
+            .flat_map(|i| [0, i + 1, (i + 1) % outline.len() + 1])
+            .collect();
+        Polygon { vertices, indices }
+    }
+
     pub fn generate_polygon<const N: usize>(rotation: f64) -> ([Vec2; N + 1], [usize; N * 3]) {
         let mut vertices = [Vec2::ZERO; N + 1];
         let mut indices = [0; N * 3];
@@ -126,6 +313,8 @@ pub mod presets {
         return (vertices, indices);
     }
 
+    /// Made `shapes/heart.eply` with 50 points. The level loads that file now, this just checks it.
+    #[cfg(test)]
     pub fn generate_heart<const N: usize>() -> ([Vec2; N + 1], [usize; N * 3]) {
         let mut vertices = [Vec2::ZERO; N + 1];
         let mut indices = [0; N * 3];
@@ -188,3 +377,81 @@ pub mod presets {
         ([], [])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::vec2;
+
+    use crate::res::shapes;
+
+    use super::*;
+
+    fn triangle() -> Polygon {
+        Polygon::from_slices((
+            &[vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)],
+            &[0, 1, 2],
+        ))
+    }
+
+    /// A polygon file header followed by `rest`.
+    fn file(version: u16, rest: &[u32]) -> Vec<u8> {
+        let mut bytes = Polygon::MAGIC.to_vec();
+        bytes.extend(version.to_le_bytes());
+        bytes.extend(rest.iter().flat_map(|word| word.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn polygons_round_trip_through_bytes() {
+        let bytes = triangle().to_bytes().unwrap();
+        let decoded = Polygon::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.vertices, triangle().vertices);
+        assert_eq!(decoded.indices, triangle().indices);
+    }
+
+    #[test]
+    fn embedded_heart_matches_its_preset() {
+        let heart = Polygon::from_bytes(shapes::HEART).unwrap();
+        let preset = Polygon::from_arrays(presets::generate_heart::<50>());
+        assert_eq!(heart.vertices, preset.vertices);
+        assert_eq!(heart.indices, preset.indices);
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let bytes = triangle().to_bytes().unwrap();
+        assert!(matches!(
+            Polygon::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(PolygonDecodeError::IoError(_))
+        ));
+        assert!(matches!(
+            Polygon::from_bytes(b"PLY!\x01\x00"),
+            Err(PolygonDecodeError::BadMagic)
+        ));
+        assert!(matches!(
+            Polygon::from_bytes(&file(2, &[0, 0])),
+            Err(PolygonDecodeError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Polygon::from_bytes(&file(1, &[Polygon::MAX_VERTICES as u32 + 1])),
+            Err(PolygonDecodeError::TooManyPoints(_))
+        ));
+        let too_many_indices = Polygon::MAX_INDEX_TRIPLETS as u32 * 3 + 3;
+        assert!(matches!(
+            Polygon::from_bytes(&file(1, &[0, too_many_indices])),
+            Err(PolygonDecodeError::TooManyIndices(_))
+        ));
+        assert!(matches!(
+            Polygon::from_bytes(&file(1, &[0, 2])),
+            Err(PolygonDecodeError::IncompleteTriangle(2))
+        ));
+        // one point at (0, 0), then a triangle pointing past it
+        assert!(matches!(
+            Polygon::from_bytes(&file(1, &[1, 0, 0, 3, 0, 0, 1])),
+            Err(PolygonDecodeError::IndexOutOfBounds {
+                index: 1,
+                vertex_count: 1
+            })
+        ));
+    }
+}
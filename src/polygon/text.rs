@@ -0,0 +1,198 @@
+//! Text turned into polygons with the built-in font, so words can be obstacles.
+//!
+//! Every contour of every glyph gets cut into triangles on its own. That's exact for the built-in
+//! font, where glyphs are made of pixel squares, but would fill in the holes of fonts that cut
+//! them out with separate contours.
+
+use macroquad::math::Vec2;
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::res::fonts;
+
+use super::Polygon;
+
+/// How many lines curves get split into. The built-in font doesn't have any.
+const CURVE_STEPS: usize = 4;
+
+/// Collects a glyph's contours, placed at `pen` and scaled so a line of text is 1 tall.
+struct Outlines {
+    contours: Vec<Vec<Vec2>>,
+    pen: Vec2,
+    line_height: f32,
+    last: Vec2,
+}
+impl Outlines {
+    fn push(&mut self, x: f32, y: f32) {
+        self.last = Vec2::new(x, y);
+        // fonts go up from the baseline, and the arena goes down
+        let point = (self.pen + Vec2::new(x, -y)) / self.line_height;
+        if let Some(contour) = self.contours.last_mut() {
+            if contour.last() != Some(&point) {
+                contour.push(point);
+            }
+        }
+    }
+}
+impl OutlineBuilder for Outlines {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(vec![]);
+        self.push(x, y);
+    }
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y);
+    }
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let [start, control, end] = [self.last, Vec2::new(x1, y1), Vec2::new(x, y)];
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let point = start.lerp(control, t).lerp(control.lerp(end, t), t);
+            self.push(point.x, point.y);
+        }
+    }
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let start = self.last;
+        let [control1, control2, end] = [Vec2::new(x1, y1), Vec2::new(x2, y2), Vec2::new(x, y)];
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let [a, b, c] = [
+                start.lerp(control1, t),
+                control1.lerp(control2, t),
+                control2.lerp(end, t),
+            ];
+            let point = a.lerp(b, t).lerp(b.lerp(c, t), t);
+            self.push(point.x, point.y);
+        }
+    }
+    fn close(&mut self) {
+        // contours end back where they started, which doesn't need to be a separate point
+        if let Some(contour) = self.contours.last_mut() {
+            if contour.len() > 1 && contour.first() == contour.last() {
+                contour.pop();
+            }
+        }
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Cuts a simple outline into triangles by clipping off its corners one at a time.
+/// Returns indices into `outline`, 3 per triangle.
+fn ear_clip(outline: &[Vec2]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..outline.len()).collect();
+    let area: f32 = (0..outline.len())
+        .map(|i| cross(outline[i], outline[(i + 1) % outline.len()]))
+        .sum();
+    // going the same way every time, so convex corners always turn the same way
+    if area < 0.0 {
+        remaining.reverse();
+    }
+    let mut indices = vec![];
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let corner =
+            |i: usize| [count - 1, 0, 1].map(|offset| outline[remaining[(i + offset) % count]]);
+        // points in a straight line don't make a corner, so they can go without a triangle
+        if let Some(i) = (0..count).find(|&i| {
+            let [a, b, c] = corner(i);
+            cross(b - a, c - b) == 0.0
+        }) {
+            remaining.remove(i);
+            continue;
+        }
+        let ear = (0..count).find(|&i| {
+            let [a, b, c] = corner(i);
+            cross(b - a, c - b) > 0.0
+                && !remaining.iter().any(|&other| {
+                    let point = outline[other];
+                    ![a, b, c].contains(&point)
+                        && cross(b - a, point - a) >= 0.0
+                        && cross(c - b, point - b) >= 0.0
+                        && cross(a - c, point - c) >= 0.0
+                })
+        });
+        // only a self intersecting outline can run out of ears
+        let Some(i) = ear else { break };
+        indices.extend([count - 1, 0, 1].map(|offset| remaining[(i + offset) % count]));
+        remaining.remove(i);
+    }
+    if remaining.len() == 3 {
+        indices.extend_from_slice(&remaining);
+    }
+    indices
+}
+
+/// Lays out `text` in the built-in font, centered on (0, 0) with every line 1 tall.
+/// Lines are split by `\n` and centered on their own. Characters the font doesn't have are drawn
+/// as its placeholder box.
+pub fn polygon(text: &str) -> Polygon {
+    let face = Face::parse(fonts::PROGGY_CLEAN, 0).expect("the built-in font is valid");
+    let line_height = (face.ascender() - face.descender() + face.line_gap()) as f32;
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut polygon = Polygon {
+        vertices: vec![],
+        indices: vec![],
+    };
+    for (row, line) in lines.iter().enumerate() {
+        let glyphs: Vec<GlyphId> = line
+            .chars()
+            .map(|char| face.glyph_index(char).unwrap_or(GlyphId(0)))
+            .collect();
+        let advance = |glyph| face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+        let width: f32 = glyphs.iter().map(|&glyph| advance(glyph)).sum();
+        let top = (row as f32 - lines.len() as f32 * 0.5) * line_height;
+        let mut outlines = Outlines {
+            contours: vec![],
+            pen: Vec2::new(-width * 0.5, top + face.ascender() as f32),
+            line_height,
+            last: Vec2::ZERO,
+        };
+        for glyph in glyphs {
+            face.outline_glyph(glyph, &mut outlines);
+            outlines.pen.x += advance(glyph);
+        }
+        for contour in outlines.contours {
+            let start = polygon.vertices.len();
+            polygon
+                .indices
+                .extend(ear_clip(&contour).into_iter().map(|index| start + index));
+            polygon.vertices.extend(contour);
+        }
+    }
+    polygon
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::vec2;
+
+    use super::*;
+
+    fn area(outline: &[Vec2], indices: &[usize]) -> f32 {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| outline[triangle[i]]);
+                cross(b - a, c - a).abs() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn ear_clipping_covers_concave_outlines() {
+        // an L, going both ways around, with a point partway along an edge
+        let mut outline = vec![
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(2.0, 0.0),
+            vec2(2.0, 1.0),
+            vec2(1.0, 1.0),
+            vec2(1.0, 3.0),
+            vec2(0.0, 3.0),
+        ];
+        assert_eq!(area(&outline, &ear_clip(&outline)), 4.0);
+        outline.reverse();
+        assert_eq!(area(&outline, &ear_clip(&outline)), 4.0);
+    }
+}
@@ -0,0 +1,147 @@
+//! Practice mode, for learning one part of a level without playing through everything before it.
+
+use std::error::Error;
+
+use macroquad::{
+    color::{BLACK, GRAY, WHITE, YELLOW},
+    input::{is_key_down, is_key_pressed, KeyCode},
+    window::{clear_background, next_frame},
+};
+
+use crate::{arena, draw::draw_screen_centered_text, levels::LevelDefinition};
+
+const MIN_SPEED: f64 = 0.5;
+const SPEED_STEP: f64 = 0.1;
+/// How long a loop is when it gets turned on.
+const DEFAULT_LOOP_LENGTH: f64 = 16.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Practice {
+    /// The beat every attempt starts from.
+    pub start: f64,
+    /// Once this beat is reached, the level starts over from `start`.
+    pub end: Option<f64>,
+    /// How fast the song plays, from [`MIN_SPEED`] to 1.
+    pub speed: f64,
+    /// Hits get counted instead of hurting the player.
+    pub no_damage: bool,
+}
+impl Default for Practice {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: None,
+            speed: 1.0,
+            no_damage: false,
+        }
+    }
+}
+impl Practice {
+    /// Keeps the loop after the start and the speed in range.
+    fn fix(&mut self) {
+        self.start = self.start.max(0.0);
+        if let Some(end) = self.end {
+            self.end = (end > self.start).then_some(end);
+        }
+        self.speed = (self.speed.clamp(MIN_SPEED, 1.0) / SPEED_STEP).round() * SPEED_STEP;
+    }
+}
+
+/// Lets the practice settings for a level be changed before playing it.
+/// Returns false if escape was pressed instead.
+pub async fn practice_setup(
+    definition: &dyn LevelDefinition,
+    practice: &mut Practice,
+) -> Result<bool, Box<dyn Error>> {
+    let checkpoints = definition.build(0)?.checkpoints;
+    let tempo = definition.tempo();
+    let mut row = 0;
+
+    loop {
+        arena::update_fullscreen();
+        // waits out the frame before leaving, so the next screen doesn't see the same key press
+        if is_key_pressed(KeyCode::Escape) {
+            next_frame().await;
+            return Ok(false);
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            next_frame().await;
+            return Ok(true);
+        }
+        if is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::W) {
+            row = (row + 3) % 4;
+        }
+        if is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::S) {
+            row = (row + 1) % 4;
+        }
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let beats = if shift { 16.0 } else { 1.0 };
+        let direction = if is_key_pressed(KeyCode::Right) || is_key_pressed(KeyCode::D) {
+            1.0
+        } else if is_key_pressed(KeyCode::Left) || is_key_pressed(KeyCode::A) {
+            -1.0
+        } else {
+            0.0
+        };
+        if direction != 0.0 {
+            match row {
+                0 => practice.start += direction * beats,
+                1 => {
+                    practice.end = match practice.end {
+                        Some(end) => Some(end + direction * beats),
+                        None if direction > 0.0 => Some(practice.start + DEFAULT_LOOP_LENGTH),
+                        None => None,
+                    }
+                }
+                2 => practice.speed += direction * SPEED_STEP,
+                _ => practice.no_damage = !practice.no_damage,
+            }
+        }
+        if row == 0 && is_key_pressed(KeyCode::Tab) {
+            // jumps between checkpoints, since they're usually where sections start
+            practice.start = checkpoints
+                .iter()
+                .copied()
+                .find(|&checkpoint| checkpoint > practice.start)
+                .unwrap_or(0.0);
+        }
+        practice.fix();
+
+        clear_background(BLACK);
+        draw_screen_centered_text(
+            &format!("Practice - {}", definition.info().name),
+            0.0,
+            -150.0,
+            40,
+            WHITE,
+        );
+        let rows = [
+            format!(
+                "Start at beat {} ({})",
+                practice.start,
+                tempo.position(practice.start)
+            ),
+            match practice.end {
+                Some(end) => format!("Loop at beat {end} ({})", tempo.position(end)),
+                None => "Loop off".to_owned(),
+            },
+            format!("Speed {:.0}%", practice.speed * 100.0),
+            format!(
+                "No damage {}",
+                if practice.no_damage { "on" } else { "off" }
+            ),
+        ];
+        for (i, text) in rows.iter().enumerate() {
+            let color = if i == row { YELLOW } else { WHITE };
+            draw_screen_centered_text(text, 0.0, -50.0 + i as f32 * 40.0, 32, color);
+        }
+        draw_screen_centered_text(
+            "A/D to change (Shift for 16 beats), Tab for the next checkpoint, Enter to play",
+            0.0,
+            150.0,
+            20,
+            GRAY,
+        );
+        next_frame().await;
+    }
+}
@@ -1,9 +1,42 @@
 use jut::extensions::Boxed;
 use macroquad::math::DVec2;
 
+use crate::{easing::Easing, levelfile::Value, shared::Target, transform::Lerp};
+
+pub mod combinators;
+
+pub use combinators::ProviderExt;
+
 pub trait Provider<T> {
     fn box_clone(&self) -> Box<dyn Provider<T>>;
     fn get(&self, beat: f64) -> T;
+    /// Called by obstacles that support it every update, before `get`.
+    /// Lets providers keep track of the player.
+    #[allow(unused_variables)]
+    fn update(&mut self, target: Target, beat: f64) {}
+    /// Converts this provider into its level file representation, if it has one.
+    fn serialize(&self) -> Option<Value> {
+        None
+    }
+}
+impl<T: 'static> Clone for Box<dyn Provider<T>> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+impl<T: 'static> Provider<T> for Box<dyn Provider<T>> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        (**self).box_clone()
+    }
+    fn get(&self, beat: f64) -> T {
+        (**self).get(beat)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        (**self).update(target, beat)
+    }
+    fn serialize(&self) -> Option<Value> {
+        (**self).serialize()
+    }
 }
 pub struct ProviderOffset<T>(pub Box<dyn Provider<T>>, pub f64);
 impl<T: 'static> Provider<T> for ProviderOffset<T> {
@@ -13,6 +46,12 @@ impl<T: 'static> Provider<T> for ProviderOffset<T> {
     fn get(&self, beat: f64) -> T {
         self.0.get(beat - self.1)
     }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat - self.1)
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call("offset", [self.0.serialize()?, self.1.into()]))
+    }
 }
 #[derive(Clone)]
 pub struct Constant<T: Clone>(pub T);
@@ -23,6 +62,9 @@ impl<T: Clone + 'static> Provider<T> for Constant<T> {
     fn get(&self, _beat: f64) -> T {
         self.0.clone()
     }
+    fn serialize(&self) -> Option<Value> {
+        Value::from_any(&self.0)
+    }
 }
 
 #[derive(Clone)]
@@ -52,4 +94,331 @@ impl Provider<DVec2> for Velocity {
     fn get(&self, beat: f64) -> DVec2 {
         self.start + self.velocity * beat
     }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "velocity",
+            [self.start.into(), self.velocity.into()],
+        ))
+    }
+}
+#[derive(Clone, Copy)]
+pub struct Linear {
+    pub start: f64,
+    /// How much it changes every beat.
+    pub rate: f64,
+}
+impl Linear {
+    pub fn new(start: f64, rate: f64) -> Self {
+        Self { start, rate }
+    }
+}
+impl Provider<f64> for Linear {
+    fn box_clone(&self) -> Box<dyn Provider<f64>> {
+        (*self).boxed()
+    }
+    fn get(&self, beat: f64) -> f64 {
+        self.start + self.rate * beat
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call("linear", [self.start.into(), self.rate.into()]))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub beat: f64,
+    pub value: T,
+    /// How it eases from this keyframe into the next one.
+    pub easing: Easing,
+}
+/// A value animated between keyframes, holding still before the first one and after the last one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframes<T> {
+    keys: Vec<Keyframe<T>>,
+}
+impl<T> Keyframes<T> {
+    pub fn new(beat: f64, value: T, easing: Easing) -> Self {
+        Self {
+            keys: vec![Keyframe {
+                beat,
+                value,
+                easing,
+            }],
+        }
+    }
+    /// Adds a keyframe, keeping them in order. Ones on the same beat stay in the order they're added.
+    pub fn key(mut self, beat: f64, value: T, easing: Easing) -> Self {
+        let index = self.keys.partition_point(|key| key.beat <= beat);
+        self.keys.insert(
+            index,
+            Keyframe {
+                beat,
+                value,
+                easing,
+            },
+        );
+        self
+    }
+}
+impl<T: Lerp + Clone + 'static> Provider<T> for Keyframes<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        self.clone().boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        let next = self.keys.partition_point(|key| key.beat <= beat);
+        let Some(from) = next.checked_sub(1).map(|index| &self.keys[index]) else {
+            return self.keys[0].value.clone();
+        };
+        let Some(to) = self.keys.get(next) else {
+            return from.value.clone();
+        };
+        let factor = (beat - from.beat) / (to.beat - from.beat);
+        from.value.lerp_to(&to.value, from.easing.apply(factor))
+    }
+    fn serialize(&self) -> Option<Value> {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| {
+                Some(Value::List(vec![
+                    key.beat.into(),
+                    Value::from_any(&key.value)?,
+                    key.easing.into(),
+                ]))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Value::call("keyframes", keys))
+    }
+}
+
+/// The direction to fire something from `from` at `speed` to hit `target`,
+/// if it keeps moving the same way. Falls back to aiming straight at it if that's impossible.
+fn intercept(from: DVec2, speed: f64, target: Target) -> DVec2 {
+    let offset = target.position - from;
+    // solves |offset + velocity * time| = speed * time for the earliest time
+    let a = target.velocity.length_squared() - speed * speed;
+    let b = 2.0 * offset.dot(target.velocity);
+    let c = offset.length_squared();
+    let time = if a.abs() < 1e-9 {
+        (b < 0.0).then(|| -c / b)
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        (discriminant >= 0.0)
+            .then(|| {
+                let root = discriminant.sqrt();
+                let (first, second) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+                [first.min(second), first.max(second)]
+                    .into_iter()
+                    .find(|&time| time > 0.0)
+            })
+            .flatten()
+    };
+    match time {
+        Some(time) => (offset + target.velocity * time).normalize_or_zero(),
+        None => offset.normalize_or_zero(),
+    }
+}
+
+/// Flies in a straight line from `start`, at whoever it's aimed at when it's first updated.
+#[derive(Clone, Copy)]
+pub struct Aimed {
+    pub start: DVec2,
+    pub speed: f64,
+    /// Aims at where the player will be instead of where they are, if they keep moving.
+    pub lead: bool,
+    direction: Option<DVec2>,
+}
+impl Aimed {
+    pub fn new(start: DVec2, speed: f64) -> Self {
+        Self {
+            start,
+            speed,
+            lead: false,
+            direction: None,
+        }
+    }
+    pub fn lead(start: DVec2, speed: f64) -> Self {
+        Self {
+            lead: true,
+            ..Self::new(start, speed)
+        }
+    }
+}
+impl Provider<DVec2> for Aimed {
+    fn box_clone(&self) -> Box<dyn Provider<DVec2>> {
+        (*self).boxed()
+    }
+    fn get(&self, beat: f64) -> DVec2 {
+        self.start + self.direction.unwrap_or(DVec2::ZERO) * self.speed * beat.max(0.0)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        if self.direction.is_none() && beat >= 0.0 {
+            self.direction = Some(if self.lead {
+                intercept(self.start, self.speed, target)
+            } else {
+                (target.position - self.start).normalize_or_zero()
+            });
+        }
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            if self.lead { "lead_aimed" } else { "aimed" },
+            [self.start.into(), self.speed.into()],
+        ))
+    }
+}
+
+/// Chases the player, turning towards them by at most `turn_rate` radians a beat.
+#[derive(Clone, Copy)]
+pub struct Homing {
+    pub start: DVec2,
+    /// Which way it's going at first. Zero starts it off straight at the player.
+    pub direction: DVec2,
+    pub speed: f64,
+    pub turn_rate: f64,
+    position: DVec2,
+    heading: DVec2,
+    last_beat: f64,
+}
+impl Homing {
+    /// How far apart turns are, so big jumps in time still make smooth curves.
+    const STEP: f64 = 1.0 / 16.0;
+
+    pub fn new(start: DVec2, direction: DVec2, speed: f64, turn_rate: f64) -> Self {
+        Self {
+            start,
+            direction,
+            speed,
+            turn_rate,
+            position: start,
+            heading: direction.normalize_or_zero(),
+            last_beat: 0.0,
+        }
+    }
+}
+impl Provider<DVec2> for Homing {
+    fn box_clone(&self) -> Box<dyn Provider<DVec2>> {
+        (*self).boxed()
+    }
+    fn get(&self, beat: f64) -> DVec2 {
+        self.position + self.heading * self.speed * (beat - self.last_beat)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        while self.last_beat < beat {
+            let dt = (beat - self.last_beat).min(Self::STEP);
+            let wanted = target.position - self.position;
+            if self.heading == DVec2::ZERO {
+                self.heading = wanted.normalize_or_zero();
+            } else if wanted != DVec2::ZERO {
+                let angle = self
+                    .heading
+                    .perp_dot(wanted)
+                    .atan2(self.heading.dot(wanted));
+                let turn = angle.clamp(-self.turn_rate * dt, self.turn_rate * dt);
+                self.heading = DVec2::from_angle(turn).rotate(self.heading);
+            }
+            self.position += self.heading * self.speed * dt;
+            self.last_beat += dt;
+        }
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "homing",
+            [
+                self.start.into(),
+                self.direction.into(),
+                self.speed.into(),
+                self.turn_rate.into(),
+            ],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::{color::Color, math::dvec2};
+
+    use super::*;
+    use crate::easing::Curve;
+
+    fn target(position: DVec2, velocity: DVec2) -> Target {
+        Target { position, velocity }
+    }
+
+    #[test]
+    fn aimed_flies_at_where_the_player_was() {
+        let mut aimed = Aimed::new(DVec2::ZERO, 10.0);
+        aimed.update(target(dvec2(100.0, 0.0), DVec2::ZERO), 0.0);
+        aimed.update(target(dvec2(0.0, 100.0), DVec2::ZERO), 1.0);
+        assert_eq!(aimed.get(2.0), dvec2(20.0, 0.0));
+    }
+
+    #[test]
+    fn lead_aimed_meets_a_moving_player() {
+        let player = target(dvec2(100.0, 0.0), dvec2(0.0, 10.0));
+        let mut aimed = Aimed::lead(DVec2::ZERO, 20.0);
+        aimed.update(player, 0.0);
+        // |(100, 10t)| = 20t
+        let time = (10000.0f64 / 300.0).sqrt();
+        let hit = player.position + player.velocity * time;
+        assert!(aimed.get(time).distance(hit) < 1e-6);
+    }
+
+    #[test]
+    fn homing_turns_slowly_but_catches_up() {
+        let player = target(dvec2(0.0, 100.0), DVec2::ZERO);
+        let mut homing = Homing::new(DVec2::ZERO, dvec2(1.0, 0.0), 10.0, 0.5);
+        homing.update(player, 1.0);
+        let heading = homing.get(2.0) - homing.get(1.0);
+        assert!((heading.y.atan2(heading.x) - 0.5).abs() < 1e-6);
+
+        let mut closest = f64::INFINITY;
+        for beat in 2..60 {
+            homing.update(player, beat as f64);
+            closest = closest.min(homing.get(beat as f64).distance(player.position));
+        }
+        assert!(closest < 10.0);
+    }
+
+    #[test]
+    fn keyframes_ease_between_keys() {
+        let keyframes = Keyframes::new(2.0, 10.0, Easing::Linear)
+            .key(6.0, 30.0, Easing::In(Curve::Quad))
+            .key(4.0, 20.0, Easing::Step);
+        assert_eq!(keyframes.get(0.0), 10.0, "holds before the first key");
+        assert_eq!(keyframes.get(3.0), 15.0);
+        assert_eq!(keyframes.get(5.5), 20.0, "steps hold until the next key");
+        assert_eq!(keyframes.get(6.0), 30.0);
+        assert_eq!(keyframes.get(100.0), 30.0, "holds after the last key");
+        let colors = Keyframes::new(0.0, Color::new(0.0, 0.0, 0.0, 1.0), Easing::Linear).key(
+            2.0,
+            Color::new(1.0, 0.5, 0.0, 1.0),
+            Easing::Linear,
+        );
+        assert_eq!(colors.get(1.0), Color::new(0.5, 0.25, 0.0, 1.0));
+    }
+
+    #[test]
+    fn every_easing_starts_and_ends_in_place() {
+        let curves = Curve::ALL.into_iter();
+        let easings = [Easing::Linear]
+            .into_iter()
+            .chain(curves.clone().map(Easing::In))
+            .chain(curves.clone().map(Easing::Out))
+            .chain(curves.map(Easing::InOut));
+        for easing in easings {
+            assert!(easing.apply(0.0).abs() < 1e-9, "{easing:?} starts at 0");
+            assert!(
+                (easing.apply(1.0) - 1.0).abs() < 1e-9,
+                "{easing:?} ends at 1"
+            );
+            assert_eq!(Easing::from_name(&easing.name()), Some(easing));
+        }
+        assert_eq!(Easing::In(Curve::Quad).apply(0.5), 0.25);
+        assert_eq!(Easing::Out(Curve::Quad).apply(0.5), 0.75);
+        assert!(
+            Easing::In(Curve::Back).apply(0.2) < 0.0,
+            "back pulls back first"
+        );
+    }
 }
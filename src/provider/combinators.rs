@@ -0,0 +1,421 @@
+//! Providers built out of other providers, see [`ProviderExt`].
+
+use std::{
+    ops::{Add, Mul},
+    rc::Rc,
+};
+
+use jut::extensions::Boxed;
+use macroquad::math::{dvec2, DVec2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{levelfile::Value, shared::Target, transform::Lerp};
+
+use super::Provider;
+
+/// Combinators for any provider. They all take beats the same way the provider they wrap does.
+pub trait ProviderExt<T: 'static>: Provider<T> + Sized + 'static {
+    /// Boxes this as any provider of `T`, like obstacle fields take.
+    fn dyn_boxed(self) -> Box<dyn Provider<T>> {
+        Box::new(self)
+    }
+    /// Changes every value with `f`. Can't be written to a level file.
+    fn map<U: 'static>(self, f: impl Fn(T) -> U + 'static) -> Map<T, U> {
+        Map(self.dyn_boxed(), Rc::new(f))
+    }
+    fn plus(self, other: impl Provider<T> + 'static) -> Sum<T>
+    where
+        T: Add<Output = T>,
+    {
+        Sum(self.dyn_boxed(), other.dyn_boxed())
+    }
+    /// Multiplies by another provider, like scaling vectors by a number.
+    fn times<S: 'static>(self, other: impl Provider<S> + 'static) -> Product<T, S>
+    where
+        T: Mul<S, Output = T>,
+    {
+        Product(self.dyn_boxed(), Box::new(other))
+    }
+    /// Blends into `other`, going from all of this at a factor of 0 to all of `other` at 1.
+    fn blend(
+        self,
+        other: impl Provider<T> + 'static,
+        factor: impl Provider<f64> + 'static,
+    ) -> Blend<T>
+    where
+        T: Lerp,
+    {
+        Blend {
+            from: self.dyn_boxed(),
+            to: other.dyn_boxed(),
+            factor: Box::new(factor),
+        }
+    }
+    /// Switches to `other` from `beat` on, with both seeing the same beats.
+    fn switch_at(self, beat: f64, other: impl Provider<T> + 'static) -> Switch<T> {
+        Switch {
+            before: self.dyn_boxed(),
+            after: other.dyn_boxed(),
+            at: beat,
+            restart: false,
+        }
+    }
+    /// Switches to `other` from `beat` on, starting it from its beat 0.
+    /// Chaining these plays providers one after another.
+    fn then(self, beat: f64, other: impl Provider<T> + 'static) -> Switch<T> {
+        Switch {
+            restart: true,
+            ..self.switch_at(beat, other)
+        }
+    }
+    /// Repeats the first `period` beats forever, both ways.
+    fn looped(self, period: f64) -> Loop<T> {
+        debug_assert!(period > 0.0, "loops need a positive period");
+        Loop(self.dyn_boxed(), period)
+    }
+    /// Plays `scale` times as fast.
+    fn time_scale(self, scale: f64) -> TimeScale<T> {
+        TimeScale(self.dyn_boxed(), scale)
+    }
+    fn clamp(self, min: T, max: T) -> Clamp<T>
+    where
+        Clamp<T>: Provider<T>,
+    {
+        Clamp(self.dyn_boxed(), min, max)
+    }
+    /// Shakes by up to `amount` in any direction, jumping somewhere new `rate` times a beat.
+    /// The same seed always shakes the same way.
+    fn jitter(self, amount: f64, rate: f64, seed: u32) -> Jitter<T>
+    where
+        Jitter<T>: Provider<T>,
+    {
+        Jitter {
+            inner: self.dyn_boxed(),
+            amount,
+            rate,
+            seed,
+        }
+    }
+}
+impl<T: 'static, P: Provider<T> + 'static> ProviderExt<T> for P {}
+
+pub struct Map<T, U>(pub Box<dyn Provider<T>>, pub Rc<dyn Fn(T) -> U>);
+impl<T: 'static, U: 'static> Provider<U> for Map<T, U> {
+    fn box_clone(&self) -> Box<dyn Provider<U>> {
+        Self(self.0.box_clone(), self.1.clone()).boxed()
+    }
+    fn get(&self, beat: f64) -> U {
+        self.1(self.0.get(beat))
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat)
+    }
+}
+
+pub struct Sum<T>(pub Box<dyn Provider<T>>, pub Box<dyn Provider<T>>);
+impl<T: Add<Output = T> + 'static> Provider<T> for Sum<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self(self.0.box_clone(), self.1.box_clone()).boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        self.0.get(beat) + self.1.get(beat)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat);
+        self.1.update(target, beat);
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "add",
+            [self.0.serialize()?, self.1.serialize()?],
+        ))
+    }
+}
+
+pub struct Product<T, S>(pub Box<dyn Provider<T>>, pub Box<dyn Provider<S>>);
+impl<T: Mul<S, Output = T> + 'static, S: 'static> Provider<T> for Product<T, S> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self(self.0.box_clone(), self.1.box_clone()).boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        self.0.get(beat) * self.1.get(beat)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat);
+        self.1.update(target, beat);
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "multiply",
+            [self.0.serialize()?, self.1.serialize()?],
+        ))
+    }
+}
+
+pub struct Blend<T> {
+    pub from: Box<dyn Provider<T>>,
+    pub to: Box<dyn Provider<T>>,
+    pub factor: Box<dyn Provider<f64>>,
+}
+impl<T: Lerp + 'static> Provider<T> for Blend<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self {
+            from: self.from.box_clone(),
+            to: self.to.box_clone(),
+            factor: self.factor.box_clone(),
+        }
+        .boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        self.from
+            .get(beat)
+            .lerp_to(&self.to.get(beat), self.factor.get(beat))
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.from.update(target, beat);
+        self.to.update(target, beat);
+        self.factor.update(target, beat);
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "blend",
+            [
+                self.from.serialize()?,
+                self.to.serialize()?,
+                self.factor.serialize()?,
+            ],
+        ))
+    }
+}
+
+pub struct Switch<T> {
+    pub before: Box<dyn Provider<T>>,
+    pub after: Box<dyn Provider<T>>,
+    pub at: f64,
+    /// Whether `after` starts from its beat 0 when switching.
+    pub restart: bool,
+}
+impl<T: 'static> Provider<T> for Switch<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self {
+            before: self.before.box_clone(),
+            after: self.after.box_clone(),
+            ..*self
+        }
+        .boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        match (beat < self.at, self.restart) {
+            (true, _) => self.before.get(beat),
+            (false, true) => self.after.get(beat - self.at),
+            (false, false) => self.after.get(beat),
+        }
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        match (beat < self.at, self.restart) {
+            (true, _) => self.before.update(target, beat),
+            (false, true) => self.after.update(target, beat - self.at),
+            (false, false) => self.after.update(target, beat),
+        }
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            if self.restart { "then" } else { "switch" },
+            [
+                self.before.serialize()?,
+                self.at.into(),
+                self.after.serialize()?,
+            ],
+        ))
+    }
+}
+
+pub struct Loop<T>(pub Box<dyn Provider<T>>, pub f64);
+impl<T: 'static> Provider<T> for Loop<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self(self.0.box_clone(), self.1).boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        self.0.get(beat.rem_euclid(self.1))
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat.rem_euclid(self.1))
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call("loop", [self.0.serialize()?, self.1.into()]))
+    }
+}
+
+pub struct TimeScale<T>(pub Box<dyn Provider<T>>, pub f64);
+impl<T: 'static> Provider<T> for TimeScale<T> {
+    fn box_clone(&self) -> Box<dyn Provider<T>> {
+        Self(self.0.box_clone(), self.1).boxed()
+    }
+    fn get(&self, beat: f64) -> T {
+        self.0.get(beat * self.1)
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.0.update(target, beat * self.1)
+    }
+    fn serialize(&self) -> Option<Value> {
+        Some(Value::call(
+            "time_scale",
+            [self.0.serialize()?, self.1.into()],
+        ))
+    }
+}
+
+/// Keeps values between a minimum and a maximum, per component for vectors.
+pub struct Clamp<T>(pub Box<dyn Provider<T>>, pub T, pub T);
+macro_rules! clamp_provider {
+    ($type:ty) => {
+        impl Provider<$type> for Clamp<$type> {
+            fn box_clone(&self) -> Box<dyn Provider<$type>> {
+                Self(self.0.box_clone(), self.1, self.2).boxed()
+            }
+            fn get(&self, beat: f64) -> $type {
+                self.0.get(beat).clamp(self.1, self.2)
+            }
+            fn update(&mut self, target: Target, beat: f64) {
+                self.0.update(target, beat)
+            }
+            fn serialize(&self) -> Option<Value> {
+                Some(Value::call(
+                    "clamp",
+                    [self.0.serialize()?, self.1.into(), self.2.into()],
+                ))
+            }
+        }
+    };
+}
+clamp_provider!(f64);
+clamp_provider!(DVec2);
+
+pub struct Jitter<T> {
+    pub inner: Box<dyn Provider<T>>,
+    pub amount: f64,
+    /// How many times a beat it jumps somewhere new.
+    pub rate: f64,
+    /// Only 32 bits, like fixed level seeds, so it fits in a level file.
+    pub seed: u32,
+}
+impl<T> Jitter<T> {
+    /// A random generator for the current jump, so the same beat always shakes the same way.
+    fn rng(&self, beat: f64) -> StdRng {
+        let step = (beat * self.rate).floor() as i64 as u64;
+        StdRng::seed_from_u64(u64::from(self.seed) ^ step.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+    fn serialize_with(&self, inner: Option<Value>) -> Option<Value> {
+        Some(Value::call(
+            "jitter",
+            [
+                inner?,
+                self.amount.into(),
+                self.rate.into(),
+                f64::from(self.seed).into(),
+            ],
+        ))
+    }
+}
+impl Provider<f64> for Jitter<f64> {
+    fn box_clone(&self) -> Box<dyn Provider<f64>> {
+        Self {
+            inner: self.inner.box_clone(),
+            ..*self
+        }
+        .boxed()
+    }
+    fn get(&self, beat: f64) -> f64 {
+        self.inner.get(beat) + self.rng(beat).gen_range(-1.0..=1.0) * self.amount
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.inner.update(target, beat)
+    }
+    fn serialize(&self) -> Option<Value> {
+        self.serialize_with(self.inner.serialize())
+    }
+}
+impl Provider<DVec2> for Jitter<DVec2> {
+    fn box_clone(&self) -> Box<dyn Provider<DVec2>> {
+        Self {
+            inner: self.inner.box_clone(),
+            ..*self
+        }
+        .boxed()
+    }
+    fn get(&self, beat: f64) -> DVec2 {
+        let mut rng = self.rng(beat);
+        // evenly spread over a circle, instead of a square
+        let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+        let distance = rng.gen::<f64>().sqrt() * self.amount;
+        self.inner.get(beat) + dvec2(angle.cos(), angle.sin()) * distance
+    }
+    fn update(&mut self, target: Target, beat: f64) {
+        self.inner.update(target, beat)
+    }
+    fn serialize(&self) -> Option<Value> {
+        self.serialize_with(self.inner.serialize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{Constant, Linear};
+
+    #[test]
+    fn then_plays_one_after_another() {
+        let provider = Linear::new(0.0, 1.0)
+            .then(2.0, Constant(10.0))
+            .then(3.0, Linear::new(0.0, -1.0));
+        assert_eq!(provider.get(1.0), 1.0);
+        assert_eq!(provider.get(2.5), 10.0);
+        assert_eq!(provider.get(4.0), -1.0);
+        let switched = Linear::new(0.0, 1.0).switch_at(2.0, Linear::new(0.0, -1.0));
+        assert_eq!(switched.get(4.0), -4.0, "switching keeps the beat");
+    }
+
+    #[test]
+    fn loops_and_time_scales() {
+        let looped = Linear::new(0.0, 1.0).looped(2.0);
+        assert_eq!(looped.get(3.5), 1.5);
+        assert_eq!(looped.get(-0.5), 1.5);
+        assert_eq!(Linear::new(0.0, 1.0).time_scale(2.0).get(1.5), 3.0);
+    }
+
+    #[test]
+    fn math_on_providers() {
+        let scaled = Constant(dvec2(1.0, 2.0)).times(Linear::new(0.0, 1.0));
+        assert_eq!(scaled.get(3.0), dvec2(3.0, 6.0));
+        let sum = scaled.plus(Constant(dvec2(1.0, 1.0)));
+        assert_eq!(sum.box_clone().get(1.0), dvec2(2.0, 3.0));
+        let blended = Constant(0.0).blend(Constant(10.0), Linear::new(0.0, 0.25));
+        assert_eq!(blended.get(2.0), 5.0);
+        let clamped = Linear::new(0.0, 1.0).clamp(-1.0, 1.0);
+        assert_eq!(clamped.get(-3.0), -1.0);
+        assert_eq!(clamped.get(3.0), 1.0);
+        let mapped = Linear::new(0.0, 1.0).map(|beat| beat as i32);
+        assert_eq!(mapped.get(2.5), 2);
+    }
+
+    #[test]
+    fn jitter_is_seeded() {
+        let jitter = Constant(dvec2(100.0, 100.0)).jitter(5.0, 4.0, 1);
+        let samples = |provider: &dyn Provider<DVec2>| {
+            (0..16)
+                .map(|i| provider.get(i as f64 * 0.25))
+                .collect::<Vec<_>>()
+        };
+        let shaken = samples(&jitter);
+        assert!(shaken
+            .iter()
+            .all(|position| position.distance(dvec2(100.0, 100.0)) <= 5.0));
+        assert_ne!(shaken[0], shaken[1], "jumps every quarter beat");
+        assert_eq!(jitter.get(0.1), shaken[0], "holds between jumps");
+        assert_eq!(samples(&*jitter.box_clone()), shaken);
+        assert_ne!(
+            samples(&Constant(dvec2(100.0, 100.0)).jitter(5.0, 4.0, 2)),
+            shaken
+        );
+    }
+}
@@ -1,7 +1,25 @@
 pub mod songs {
     pub const DURING_PRIDE_MONTH: &'static [u8] = include_bytes!("songs/[193] dpm.ogg");
+
+    /// Embedded songs, by the name level files refer to them with.
+    pub const BUILTIN: &'static [(&'static str, &'static [u8])] =
+        &[("during_pride_month", DURING_PRIDE_MONTH)];
+    pub fn builtin(name: &str) -> Option<&'static [u8]> {
+        BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, data)| *data)
+    }
 }
 pub mod sfx {
     pub const CHECKPOINT: &'static [u8] = include_bytes!("sfx/checkpoint.wav");
     pub const DIE: &'static [u8] = include_bytes!("sfx/die.wav");
 }
+pub mod shapes {
+    /// The heart from the big drop of "...during pride month?", a polygon file.
+    pub const HEART: &[u8] = include_bytes!("shapes/heart.eply");
+}
+pub mod fonts {
+    /// The font macroquad draws text with by default.
+    pub const PROGGY_CLEAN: &[u8] = include_bytes!("fonts/ProggyClean.ttf");
+}
@@ -0,0 +1,114 @@
+use std::error::Error;
+
+use macroquad::{
+    color::{BLACK, GRAY, WHITE},
+    input::{is_key_pressed, KeyCode},
+    time::get_time,
+    window::{clear_background, next_frame},
+};
+use soloud::{LoadExt, Wav};
+
+use crate::{arena, draw::draw_screen_centered_text, levels::LevelDefinition, music::Music};
+
+/// How many seconds of a song are previewed before it starts over.
+pub const PREVIEW_LENGTH: f64 = 8.0;
+const ROW_HEIGHT: f32 = 50.0;
+
+/// What to do with the level picked in the level select.
+pub enum Choice {
+    Play,
+    Practice,
+}
+
+/// Shows every level and previews the selected one's song.
+/// Returns once a level has been picked, leaving its index in `selected`.
+pub async fn level_select(
+    levels: &[Box<dyn LevelDefinition>],
+    music: &mut Music,
+    selected: &mut usize,
+) -> Result<Choice, Box<dyn Error>> {
+    let mut preview = Wav::default();
+    let mut loaded_preview = None;
+    let mut preview_started = f64::NEG_INFINITY;
+
+    loop {
+        arena::update_fullscreen();
+        clear_background(BLACK);
+        if levels.is_empty() {
+            draw_screen_centered_text("No levels found!", 0.0, 0.0, 32, WHITE);
+            next_frame().await;
+            continue;
+        }
+
+        if is_key_pressed(KeyCode::W) || is_key_pressed(KeyCode::Up) {
+            *selected = (*selected + levels.len() - 1) % levels.len();
+        }
+        if is_key_pressed(KeyCode::S) || is_key_pressed(KeyCode::Down) {
+            *selected = (*selected + 1) % levels.len();
+        }
+        *selected = (*selected).min(levels.len() - 1);
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Space) {
+            return Ok(Choice::Play);
+        }
+        if is_key_pressed(KeyCode::P) {
+            return Ok(Choice::Practice);
+        }
+
+        let level = &levels[*selected];
+        let time = get_time();
+        if loaded_preview != Some(*selected) {
+            loaded_preview = Some(*selected);
+            preview_started = f64::NEG_INFINITY;
+            match level.song() {
+                Ok(song) => preview.load_mem(&song.data)?,
+                Err(error) => {
+                    eprintln!("couldn't preview {}: {error}", level.info().name);
+                    preview = Wav::default();
+                }
+            }
+        }
+        if time - preview_started > PREVIEW_LENGTH {
+            preview_started = time;
+            music.play(&preview, level.tempo());
+            music.seek(level.info().preview_beat)?;
+        }
+
+        draw_screen_centered_text(
+            "Exclusively Polygons Alongside Rhythms",
+            0.0,
+            -250.0,
+            40,
+            WHITE,
+        );
+        draw_screen_centered_text(
+            "W/S to choose, Enter to play, P to practice",
+            0.0,
+            260.0,
+            20,
+            GRAY,
+        );
+        for (i, level) in levels.iter().enumerate() {
+            // keeps the selected level in the middle of the screen
+            let y = (i as f32 - *selected as f32) * ROW_HEIGHT;
+            let info = level.info();
+            let color = if i == *selected { WHITE } else { GRAY };
+            draw_screen_centered_text(&info.name, 0.0, y, 32, color);
+            let bpm = match level.tempo().bpm_range() {
+                (min, max) if min == max => format!("{min}"),
+                (min, max) => format!("{min}-{max}"),
+            };
+            draw_screen_centered_text(
+                &format!("{} - {bpm} BPM - {}", info.artist, info.difficulty.name()),
+                0.0,
+                y + 18.0,
+                16,
+                if i == *selected {
+                    info.difficulty.color()
+                } else {
+                    GRAY
+                },
+            );
+        }
+        next_frame().await;
+    }
+}
@@ -1,27 +1,89 @@
-use macroquad::{
-    color::Color,
-    math::{vec2, Vec2},
-};
+use macroquad::math::{vec2, DAffine2, DVec2, Vec2};
 
-use crate::{obstacle::Obstacle, provider::Provider};
+use crate::{backdrop::DynBackdrop, obstacle::Obstacle, palette::Swap};
+
+/// Where the player is, for obstacles that aim at them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Target {
+    pub position: DVec2,
+    /// In arena units per beat, like obstacle speeds.
+    pub velocity: DVec2,
+}
+
+/// How obstacles move the camera, on top of shaking and jerking it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    /// How much closer the camera is, 2 being twice as close.
+    pub zoom: f64,
+    /// In radians, clockwise. The arena turns this way on screen.
+    pub rotation: f64,
+    /// How far the camera is moved from the center of the arena.
+    pub pan: DVec2,
+}
+impl View {
+    pub const NEUTRAL: Self = Self {
+        zoom: 1.0,
+        rotation: 0.0,
+        pan: DVec2::ZERO,
+    };
+    /// This view, `amount` of the way from neutral.
+    pub fn blend(self, amount: f64) -> Self {
+        Self {
+            zoom: 1.0 + (self.zoom - 1.0) * amount,
+            rotation: self.rotation * amount,
+            pan: self.pan * amount,
+        }
+    }
+    /// Both views at once. Zooms multiply, while rotations and pans add up.
+    pub fn combine(self, other: Self) -> Self {
+        Self {
+            zoom: self.zoom * other.zoom,
+            rotation: self.rotation + other.rotation,
+            pan: self.pan + other.pan,
+        }
+    }
+}
+impl Default for View {
+    fn default() -> Self {
+        Self::NEUTRAL
+    }
+}
 
 pub struct Shared {
+    target: Target,
     shake: f64,
     jerk: Vec2,
+    view: View,
     new_obstacles: Vec<Obstacle>,
-    pub new_background: Option<Box<dyn Provider<Color>>>,
-    pub new_foreground: Option<Box<dyn Provider<Color>>>,
+    pub swaps: Vec<Swap>,
+    pub backdrop: Option<DynBackdrop>,
+    /// Moves points from the obstacles' space into the arena's, for obstacles inside groups.
+    to_arena: DAffine2,
 }
 impl Shared {
-    pub fn new() -> Shared {
+    pub fn new(target: Target) -> Shared {
         Self {
+            target,
             shake: 0.0,
             jerk: vec2(0.0, 0.0),
+            view: View::NEUTRAL,
             new_obstacles: vec![],
-            new_background: None,
-            new_foreground: None,
+            swaps: vec![],
+            backdrop: None,
+            to_arena: DAffine2::IDENTITY,
+        }
+    }
+    /// A fresh one for obstacles inside a group, with `transform` moving their points into ours.
+    pub fn nested(&self, target: Target, transform: DAffine2) -> Shared {
+        Self {
+            to_arena: self.to_arena * transform,
+            ..Self::new(target)
         }
     }
+    /// Where a point in the obstacles' space is in the arena.
+    pub fn to_arena(&self, point: DVec2) -> DVec2 {
+        self.to_arena.transform_point2(point)
+    }
     pub fn add_jerk(&mut self, add: Vec2) -> &mut Self {
         self.jerk += add;
         self
@@ -30,21 +92,33 @@ impl Shared {
         self.shake += add;
         self
     }
+    pub fn add_view(&mut self, add: View) -> &mut Self {
+        self.view = self.view.combine(add);
+        self
+    }
     pub fn add_obstacle(&mut self, add: Obstacle) -> &mut Self {
         self.new_obstacles.push(add);
         self
     }
-    pub fn set_foreground(&mut self, to: Box<dyn Provider<Color>>) -> &mut Self {
-        self.new_foreground = Some(to);
+    /// Swaps a slot of the level's palette, starting at the current beat.
+    pub fn swap_color(&mut self, swap: Swap) -> &mut Self {
+        self.swaps.push(swap);
         self
     }
-    pub fn set_background(&mut self, to: Box<dyn Provider<Color>>) -> &mut Self {
-        self.new_background = Some(to);
+    /// Switches the level's backdrop, replacing any other switch this update.
+    pub fn set_backdrop(&mut self, backdrop: DynBackdrop) -> &mut Self {
+        self.backdrop = Some(backdrop);
         self
     }
+    pub fn target(&self) -> Target {
+        self.target
+    }
     pub fn jerk(&self) -> Vec2 {
         self.jerk
     }
+    pub fn view(&self) -> View {
+        self.view
+    }
     pub fn shake(&self) -> f64 {
         self.shake
     }
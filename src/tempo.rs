@@ -0,0 +1,253 @@
+//! Tempo maps, for songs that change speed or time signature partway through.
+//!
+//! Beats are quarter notes, so a bar of 7/8 is 3.5 beats long.
+
+use std::fmt;
+
+/// How finely bar:beat:tick positions split up a beat.
+pub const TICKS_PER_BEAT: i64 = 480;
+
+/// The song playing at `bpm` from `beat` on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoChange {
+    pub beat: f64,
+    pub bpm: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub beats_per_bar: u32,
+    /// Which note counts as a beat, like the 8 in 7/8.
+    pub note_value: u32,
+}
+impl TimeSignature {
+    pub const COMMON: Self = Self {
+        beats_per_bar: 4,
+        note_value: 4,
+    };
+    /// How many quarter note beats one of this signature's beats lasts.
+    fn beat_length(self) -> f64 {
+        4.0 / self.note_value as f64
+    }
+    fn ticks_per_bar(self) -> i64 {
+        self.beats_per_bar as i64 * TICKS_PER_BEAT
+    }
+    /// How many ticks of this signature's beats `beats` quarter notes are.
+    fn ticks(self, beats: f64) -> i64 {
+        (beats / self.beat_length() * TICKS_PER_BEAT as f64).round() as i64
+    }
+}
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.beats_per_bar, self.note_value)
+    }
+}
+
+/// A new time signature starting at `beat`. Bars start over from there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignatureChange {
+    pub beat: f64,
+    pub signature: TimeSignature,
+}
+
+/// Where a beat is in the song's bars, counting from 1 like music software does.
+/// Beat 0 of the level is 1:1:000.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarPosition {
+    pub bar: i64,
+    pub beat: i64,
+    pub tick: i64,
+}
+impl fmt::Display for BarPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{:03}", self.bar, self.beat, self.tick)
+    }
+}
+
+/// Converts between seconds into a song's audio and beats of its level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+    /// Seconds of audio before beat 0.
+    start_time: f64,
+    /// Sorted by beat, starting at beat 0. The first tempo also goes back before beat 0.
+    tempos: Vec<TempoChange>,
+    /// Sorted by beat, starting at beat 0.
+    signatures: Vec<SignatureChange>,
+}
+impl TempoMap {
+    /// A song at one tempo in 4/4, with beat 0 being `start_time` seconds into the audio.
+    pub fn new(bpm: f64, start_time: f64) -> Self {
+        Self {
+            start_time,
+            tempos: vec![TempoChange { beat: 0.0, bpm }],
+            signatures: vec![SignatureChange {
+                beat: 0.0,
+                signature: TimeSignature::COMMON,
+            }],
+        }
+    }
+    /// Changes the tempo from `beat` on. Changes at or before beat 0 replace the starting tempo.
+    pub fn change(mut self, beat: f64, bpm: f64) -> Self {
+        insert(
+            &mut self.tempos,
+            TempoChange {
+                beat: beat.max(0.0),
+                bpm,
+            },
+            |change| change.beat,
+        );
+        self
+    }
+    /// Changes the time signature from `beat` on.
+    pub fn signature(mut self, beat: f64, signature: TimeSignature) -> Self {
+        let change = SignatureChange {
+            beat: beat.max(0.0),
+            signature,
+        };
+        insert(&mut self.signatures, change, |change| change.beat);
+        self
+    }
+    pub fn start_time(&self) -> f64 {
+        self.start_time
+    }
+    pub fn tempos(&self) -> &[TempoChange] {
+        &self.tempos
+    }
+    pub fn signatures(&self) -> &[SignatureChange] {
+        &self.signatures
+    }
+    /// The slowest and fastest tempos in the song.
+    pub fn bpm_range(&self) -> (f64, f64) {
+        self.tempos
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), change| {
+                (min.min(change.bpm), max.max(change.bpm))
+            })
+    }
+    pub fn bpm_at(&self, beat: f64) -> f64 {
+        let index = self.tempos.partition_point(|change| change.beat <= beat);
+        self.tempos[index.saturating_sub(1)].bpm
+    }
+    /// How many seconds into the audio `beat` is.
+    pub fn beats_to_seconds(&self, beat: f64) -> f64 {
+        let mut seconds = self.start_time;
+        let mut current = self.tempos[0];
+        for &next in &self.tempos[1..] {
+            if next.beat > beat {
+                break;
+            }
+            seconds += (next.beat - current.beat) * 60.0 / current.bpm;
+            current = next;
+        }
+        seconds + (beat - current.beat) * 60.0 / current.bpm
+    }
+    /// Which beat is playing `seconds` into the audio.
+    pub fn seconds_to_beats(&self, seconds: f64) -> f64 {
+        let mut start = self.start_time;
+        let mut current = self.tempos[0];
+        for &next in &self.tempos[1..] {
+            let next_start = start + (next.beat - current.beat) * 60.0 / current.bpm;
+            if next_start > seconds {
+                break;
+            }
+            start = next_start;
+            current = next;
+        }
+        current.beat + (seconds - start) * current.bpm / 60.0
+    }
+    /// Where `beat` is in the song's bars. A time signature changing partway through a bar cuts
+    /// that bar short.
+    pub fn position(&self, beat: f64) -> BarPosition {
+        let mut bars = 0;
+        let mut current = self.signatures[0];
+        for &next in &self.signatures[1..] {
+            if next.beat > beat {
+                break;
+            }
+            let ticks = current.signature.ticks(next.beat - current.beat);
+            let ticks_per_bar = current.signature.ticks_per_bar();
+            bars += (ticks + ticks_per_bar - 1).div_euclid(ticks_per_bar);
+            current = next;
+        }
+        let ticks = current.signature.ticks(beat - current.beat);
+        let ticks_per_bar = current.signature.ticks_per_bar();
+        let within = ticks.rem_euclid(ticks_per_bar);
+        BarPosition {
+            bar: bars + ticks.div_euclid(ticks_per_bar) + 1,
+            beat: within / TICKS_PER_BEAT + 1,
+            tick: within % TICKS_PER_BEAT,
+        }
+    }
+}
+
+/// Adds a change in order, replacing any other change at the same beat.
+fn insert<T>(changes: &mut Vec<T>, change: T, beat: impl Fn(&T) -> f64) {
+    let index = changes.partition_point(|other| beat(other) < beat(&change));
+    if changes
+        .get(index)
+        .is_some_and(|other| beat(other) == beat(&change))
+    {
+        changes[index] = change;
+    } else {
+        changes.insert(index, change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn half_time() -> TempoMap {
+        // 2 seconds of silence, 8 beats at 120, 8 at 60, then back to 120
+        TempoMap::new(120.0, 2.0)
+            .change(8.0, 60.0)
+            .change(16.0, 120.0)
+    }
+
+    #[test]
+    fn tempo_changes_convert_both_ways() {
+        let tempo = half_time();
+        let expected = [
+            (-4.0, 0.0),
+            (0.0, 2.0),
+            (8.0, 6.0),
+            (12.0, 10.0),
+            (16.0, 14.0),
+            (20.0, 16.0),
+        ];
+        for (beat, seconds) in expected {
+            assert_eq!(tempo.beats_to_seconds(beat), seconds);
+            assert_eq!(tempo.seconds_to_beats(seconds), beat);
+        }
+        assert_eq!(tempo.bpm_at(-1.0), 120.0);
+        assert_eq!(tempo.bpm_at(8.0), 60.0);
+        assert_eq!(tempo.bpm_range(), (60.0, 120.0));
+    }
+
+    #[test]
+    fn changes_at_the_same_beat_replace_each_other() {
+        let tempo = TempoMap::new(100.0, 0.0)
+            .change(-4.0, 120.0)
+            .change(8.0, 90.0)
+            .change(8.0, 60.0);
+        assert_eq!(tempo, TempoMap::new(120.0, 0.0).change(8.0, 60.0));
+    }
+
+    #[test]
+    fn positions_follow_time_signatures() {
+        let seven_eight = TimeSignature {
+            beats_per_bar: 7,
+            note_value: 8,
+        };
+        // two bars of 4/4, a bar cut short at beat 10, then 7/8
+        let tempo = TempoMap::new(120.0, 0.0).signature(10.0, seven_eight);
+        let position = |bar, beat, tick| BarPosition { bar, beat, tick };
+        assert_eq!(tempo.position(0.0), position(1, 1, 0));
+        assert_eq!(tempo.position(5.5), position(2, 2, 240));
+        assert_eq!(tempo.position(-1.0), position(0, 4, 0));
+        assert_eq!(tempo.position(10.0), position(4, 1, 0));
+        assert_eq!(tempo.position(13.0), position(4, 7, 0));
+        assert_eq!(tempo.position(13.5), position(5, 1, 0));
+        assert_eq!(tempo.position(5.5).to_string(), "2:2:240");
+    }
+}
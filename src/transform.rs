@@ -1,4 +1,9 @@
-use macroquad::math::{dvec2, DVec2, Vec2};
+use macroquad::{
+    color::Color,
+    math::{dvec2, DVec2, Vec2},
+};
+
+use crate::ext::ColorExt;
 
 /// Rotates a vector around (0, 0) using radians.
 pub fn rotate_d(vec: DVec2, rot: f64) -> DVec2 {
@@ -43,3 +48,23 @@ pub fn time_independent_vec2_lerp(
     let factor = factor_per_second.powf(delta_time);
     start.lerp(end, factor as f32)
 }
+
+/// Values that can be blended between, for animating them.
+pub trait Lerp {
+    fn lerp_to(&self, other: &Self, factor: f64) -> Self;
+}
+impl Lerp for f64 {
+    fn lerp_to(&self, other: &Self, factor: f64) -> Self {
+        lerp(*self, *other, factor)
+    }
+}
+impl Lerp for DVec2 {
+    fn lerp_to(&self, other: &Self, factor: f64) -> Self {
+        self.lerp(*other, factor)
+    }
+}
+impl Lerp for Color {
+    fn lerp_to(&self, other: &Self, factor: f64) -> Self {
+        self.mix(*other, factor)
+    }
+}